@@ -0,0 +1,86 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use ferrous_chess::{Board, Colour, PieceType};
+use std::hint::black_box;
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const ROOK_ENDGAME: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const MIDDLEGAME: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+const SPARSE: &str = "8/8/8/4k3/8/8/8/R3K3 w Q - 0 1";
+
+const POSITIONS: [(&str, &str); 4] = [
+    ("start", START),
+    ("kiwipete", KIWIPETE),
+    ("rook_endgame", ROOK_ENDGAME),
+    ("middlegame", MIDDLEGAME),
+];
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+fn pseudo_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pseudo_moves");
+    let board = Board::from_fen(KIWIPETE).unwrap();
+    for piece_type in PIECE_TYPES {
+        let squares = board
+            .into_iter()
+            .flatten()
+            .filter(|(_, piece)| piece.piece_type() == piece_type)
+            .map(|(coord, _)| coord)
+            .collect::<Vec<_>>();
+        group.bench_function(BenchmarkId::from_parameter(piece_type), |b| {
+            b.iter(|| {
+                for square in &squares {
+                    black_box(board.pseudo_moves(black_box(square)).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn legal_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("legal_moves");
+    for (name, fen) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        group.bench_function(name, |b| b.iter(|| black_box(&board).get_legal_moves()));
+    }
+    group.finish();
+}
+
+fn perft(c: &mut Criterion) {
+    let mut group = c.benchmark_group("perft");
+    group.sample_size(10);
+    for (name, fen, depth) in [
+        ("start", START, 3),
+        ("kiwipete", KIWIPETE, 2),
+        ("rook_endgame", ROOK_ENDGAME, 3),
+        ("middlegame", MIDDLEGAME, 2),
+    ] {
+        let mut board = Board::from_fen(fen).unwrap();
+        group.bench_with_input(BenchmarkId::new(name, depth), &depth, |b, &depth| {
+            b.iter(|| board.perft(depth))
+        });
+    }
+    group.finish();
+}
+
+fn is_in_check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("is_in_check");
+    for (name, fen) in [("crowded", MIDDLEGAME), ("sparse", SPARSE)] {
+        let board = Board::from_fen(fen).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(&board).is_in_check(black_box(Colour::Black)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pseudo_moves, legal_moves, perft, is_in_check);
+criterion_main!(benches);
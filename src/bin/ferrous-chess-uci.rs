@@ -0,0 +1,16 @@
+use ferrous_chess::{uci, xboard};
+use std::io::{self, BufRead, Read};
+
+fn main() {
+    let mut input = io::stdin().lock();
+    // GUIs speaking the older CECP protocol announce themselves with `xboard` first.
+    let mut first_line = String::new();
+    if input.read_line(&mut first_line).is_err() {
+        return;
+    }
+    if first_line.trim() == "xboard" {
+        xboard::run(input, io::stdout());
+    } else {
+        uci::run(first_line.as_bytes().chain(input), io::stdout());
+    }
+}
@@ -1,12 +1,46 @@
-use crate::piece::Piece;
+use crate::eval;
+use crate::game::{Outcome, Termination};
+use crate::piece::{Piece, PieceType};
+use crate::variant::Variant;
+use crate::zobrist;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub(crate) struct Board {
+pub struct Board {
+    variant: Variant,
     squares: [[Option<Piece>; 8]; 8],
+    /// Captured pieces waiting to be dropped, indexed by colour, in crazyhouse.
+    pockets: [Pocket; 2],
+    /// The pieces that were pawns before promoting, which are pocketed as pawns when captured.
+    promoted: SquareMask,
+    /// The checks each side has given, indexed by colour, in three-check.
+    checks: [u8; 2],
     turn: Colour,
     white_castling: CastlingRights,
     black_castling: CastlingRights,
+    en_passant: Option<Coordinate>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
     move_list: Vec<Move>,
+    // Irreversible state saved by `make_move`, popped in lockstep with `move_list`.
+    undo_list: Vec<UndoState>,
+    // Zobrist hash of the position, kept up to date by `set_square` and `make_move`.
+    hash: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct UndoState {
+    pockets: [Pocket; 2],
+    promoted: SquareMask,
+    checks: [u8; 2],
+    /// The pieces blown up around the destination of an atomic capture, by `KING_DIRS`.
+    pub(crate) exploded: [Option<Piece>; 8],
+    white_castling: CastlingRights,
+    black_castling: CastlingRights,
+    en_passant: Option<Coordinate>,
+    halfmove_clock: u32,
+    hash: u64,
 }
 
 impl Default for Board {
@@ -28,7 +62,7 @@ impl<'a> IntoIterator for &'a Board {
     }
 }
 
-pub(crate) struct BoardIterator<'a> {
+pub struct BoardIterator<'a> {
     board: &'a Board,
     row: usize,
     col: usize,
@@ -92,7 +126,7 @@ const DEFAULT_BOARD: [[Option<Piece>; 8]; 8] = [
 // Direction constants for move generation
 const ORTHOGONAL_DIRS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
 const DIAGONAL_DIRS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
-const KING_DIRS: [(i8, i8); 8] = [
+pub(crate) const KING_DIRS: [(i8, i8); 8] = [
     (1, 0),
     (-1, 0),
     (0, 1),
@@ -114,56 +148,947 @@ const KNIGHT_DELTAS: [(i8, i8); 8] = [
 ];
 
 impl Board {
-    const fn new() -> Self {
-        Self {
+    pub const fn new() -> Self {
+        let mut board = Self {
+            variant: Variant::Standard,
             squares: DEFAULT_BOARD,
+            pockets: [Pocket::EMPTY; 2],
+            promoted: SquareMask::EMPTY,
+            checks: [0; 2],
             turn: Colour::White,
-            white_castling: CastlingRights {
-                kingside: true,
-                queenside: true,
-            },
-            black_castling: CastlingRights {
-                kingside: true,
-                queenside: true,
-            },
+            white_castling: CastlingRights::CLASSICAL,
+            black_castling: CastlingRights::CLASSICAL,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
             move_list: Vec::new(),
-        }
+            undo_list: Vec::new(),
+            hash: 0,
+        };
+        board.hash = board.compute_hash();
+        board
     }
 
-    #[cfg(test)]
     pub(crate) const fn empty() -> Self {
         Self {
+            variant: Variant::Standard,
             squares: [[None; 8]; 8],
+            pockets: [Pocket::EMPTY; 2],
+            promoted: SquareMask::EMPTY,
+            checks: [0; 2],
             turn: Colour::White,
-            white_castling: CastlingRights {
-                kingside: false,
-                queenside: false,
-            },
-            black_castling: CastlingRights {
-                kingside: false,
-                queenside: false,
-            },
+            white_castling: CastlingRights::NONE,
+            black_castling: CastlingRights::NONE,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
             move_list: Vec::new(),
+            undo_list: Vec::new(),
+            // An empty board with White to move and no rights hashes to zero.
+            hash: 0,
+        }
+    }
+
+    /// The Chess960 start position with Scharnagl number `index`, below 960. Number 518 is the
+    /// classical setup.
+    ///
+    /// The light-squared bishop is placed first, then the dark-squared bishop, the queen on one
+    /// of the six squares left and the knights on two of the five after that. The rooks and the
+    /// king fill the last three squares, the king in the middle.
+    pub fn chess960(index: u16) -> Result<Self, &'static str> {
+        // The ten ways of placing the knights on five empty squares.
+        const KNIGHTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+        if index >= 960 {
+            return Err("Chess960 positions are numbered from 0 to 959");
         }
+        let mut index = index as usize;
+        let mut rank = [None; 8];
+        rank[index % 4 * 2 + 1] = Some(PieceType::Bishop);
+        index /= 4;
+        rank[index % 4 * 2] = Some(PieceType::Bishop);
+        index /= 4;
+        let empty_files = |rank: &[Option<PieceType>; 8]| {
+            (0..8).filter(|&x| rank[x].is_none()).collect::<Vec<_>>()
+        };
+        rank[empty_files(&rank)[index % 6]] = Some(PieceType::Queen);
+        index /= 6;
+        let (first, second) = KNIGHTS[index];
+        let files = empty_files(&rank);
+        rank[files[first]] = Some(PieceType::Knight);
+        rank[files[second]] = Some(PieceType::Knight);
+        let files = empty_files(&rank);
+        for (x, piece_type) in files
+            .iter()
+            .zip([PieceType::Rook, PieceType::King, PieceType::Rook])
+        {
+            rank[*x] = Some(piece_type);
+        }
+
+        let mut board = Self::empty();
+        for colour in [Colour::White, Colour::Black] {
+            let home_rank = colour.home_rank();
+            let pawn_rank = match colour {
+                Colour::White => 1,
+                Colour::Black => 6,
+            };
+            for (x, piece_type) in rank.iter().enumerate() {
+                let piece = piece_type.map(|piece_type| Piece { piece_type, colour });
+                board.set_square(Coordinate::new_unchecked(x as u8, home_rank), piece);
+                board.set_square(
+                    Coordinate::new_unchecked(x as u8, pawn_rank),
+                    Some(Piece::pawn(colour)),
+                );
+            }
+            *board.castling_rights_mut(colour) = CastlingRights {
+                kingside: Some(files[2] as u8),
+                queenside: Some(files[0] as u8),
+            };
+        }
+        board.hash = board.compute_hash();
+        Ok(board)
+    }
+
+    /// The start position of `variant`.
+    pub fn start(variant: Variant) -> Self {
+        Self::from_variant_fen(variant, variant.start_fen())
+            .expect("every variant's start position is valid")
     }
 
-    pub(crate) fn get_square(&self, coord: &Coordinate) -> Option<Piece> {
+    pub fn from_fen(fen: &str) -> Result<Self, &'static str> {
+        Self::from_variant_fen(Variant::Standard, fen)
+    }
+
+    /// Reads a position of `variant` from FEN.
+    pub fn from_variant_fen(variant: Variant, fen: &str) -> Result<Self, &'static str> {
+        let mut board = Self {
+            variant,
+            ..Self::empty()
+        };
+        board.import_from_fen(fen)?;
+        Ok(board)
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn get_square(&self, coord: &Coordinate) -> Option<Piece> {
         self.squares[coord.y as usize][coord.x as usize]
     }
 
-    #[cfg(test)]
     pub(crate) fn set_square(&mut self, coord: Coordinate, piece: Option<Piece>) {
-        self.squares[coord.y as usize][coord.x as usize] = piece;
+        let square = &mut self.squares[coord.y as usize][coord.x as usize];
+        for piece in [*square, piece].into_iter().flatten() {
+            self.hash ^= zobrist::piece(piece, &coord);
+        }
+        *square = piece;
+    }
+
+    /// The Zobrist hash of the position, identical for positions with the same pieces, side to
+    /// move, castling rights and en passant square.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    const fn compute_hash(&self) -> u64 {
+        let mut hash = self.state_hash();
+        let mut y = 0;
+        while y < 8 {
+            let mut x = 0;
+            while x < 8 {
+                if let Some(piece) = self.squares[y as usize][x as usize] {
+                    hash ^= zobrist::piece(piece, &Coordinate::new_unchecked(x, y));
+                }
+                x += 1;
+            }
+            y += 1;
+        }
+        hash
+    }
+
+    /// The part of the hash covering everything except piece placement.
+    const fn state_hash(&self) -> u64 {
+        let mut hash = 0;
+        if matches!(self.turn, Colour::Black) {
+            hash ^= zobrist::black_to_move();
+        }
+        let rights = [
+            (Colour::White, self.white_castling),
+            (Colour::Black, self.black_castling),
+        ];
+        let mut i = 0;
+        while i < rights.len() {
+            let (colour, rights) = rights[i];
+            if rights.kingside.is_some() {
+                hash ^= zobrist::castling(colour, true);
+            }
+            if rights.queenside.is_some() {
+                hash ^= zobrist::castling(colour, false);
+            }
+            i += 1;
+        }
+        if let Some(coord) = &self.en_passant {
+            hash ^= zobrist::en_passant(coord);
+        }
+        let mut colour = 0;
+        while colour < 2 {
+            let mut piece_type = 0;
+            while piece_type < DROP_TYPES.len() {
+                hash ^= zobrist::pocket(
+                    Colour::ALL[colour],
+                    DROP_TYPES[piece_type],
+                    self.pockets[colour].counts[piece_type],
+                );
+                piece_type += 1;
+            }
+            hash ^= zobrist::checks(Colour::ALL[colour], self.checks[colour]);
+            colour += 1;
+        }
+        hash
+    }
+
+    pub fn turn(&self) -> Colour {
+        self.turn
+    }
+
+    pub fn en_passant(&self) -> Option<Coordinate> {
+        self.en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn move_list(&self) -> &[Move] {
+        &self.move_list
+    }
+
+    /// The pieces `colour` may drop, always empty outside crazyhouse.
+    pub fn pocket(&self, colour: Colour) -> Pocket {
+        self.pockets[colour as usize]
+    }
+
+    pub(crate) fn pocket_mut(&mut self, colour: Colour) -> &mut Pocket {
+        &mut self.pockets[colour as usize]
+    }
+
+    /// Whether the piece on `coord` was promoted from a pawn, tracked in crazyhouse only.
+    pub fn is_promoted(&self, coord: &Coordinate) -> bool {
+        self.promoted.contains(coord)
+    }
+
+    pub(crate) fn promoted_mut(&mut self) -> &mut SquareMask {
+        &mut self.promoted
+    }
+
+    /// The checks `colour` has given, always zero outside three-check.
+    pub fn checks_given(&self, colour: Colour) -> u8 {
+        self.checks[colour as usize]
+    }
+
+    /// The outcome if the game is over: by a rule of the variant, because the side to move has
+    /// no legal move, or by the fifty move rule. Repetitions take the earlier positions of the
+    /// game, so are left to `Game`.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if let Some(outcome) = self.variant.outcome(self) {
+            Some(outcome)
+        } else if self.get_legal_moves().is_empty() {
+            Some(self.variant.no_moves_outcome(self))
+        } else if self.halfmove_clock >= 100 {
+            Some(Outcome::draw(Termination::FiftyMoveRule))
+        } else {
+            None
+        }
+    }
+
+    /// Whether neither side can ever mate: only kings are left, with at most one knight, or with
+    /// bishops that all stand on squares of one colour.
+    pub(crate) fn has_insufficient_material(&self) -> bool {
+        let mut knights = 0;
+        let mut bishop_square_colours = [false; 2];
+        for (coord, piece) in self.into_iter().flatten() {
+            match piece.piece_type {
+                PieceType::King => {}
+                PieceType::Knight => knights += 1,
+                PieceType::Bishop => {
+                    bishop_square_colours[((coord.x() + coord.y()) % 2) as usize] = true;
+                }
+                PieceType::Pawn | PieceType::Rook | PieceType::Queen => return false,
+            }
+        }
+        match knights {
+            0 => bishop_square_colours != [true, true],
+            1 => bishop_square_colours == [false, false],
+            _ => false,
+        }
     }
 
     fn import_from_fen(&mut self, fen: &str) -> Result<(), &'static str> {
-        todo!()
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or("FEN is missing the piece placement")?;
+        let turn = fields.next().ok_or("FEN is missing the side to move")?;
+        // The remaining fields are optional, so EPD-style positions are accepted too.
+        let castling = fields.next().unwrap_or("-");
+        let en_passant = fields.next().unwrap_or("-");
+        let halfmove_clock = fields.next().unwrap_or("0");
+        let fullmove_number = fields.next().unwrap_or("1");
+        // Three-check appends the checks each side has given, as in `+2+1`.
+        let checks = match fields.next() {
+            Some(_) if !self.variant.counts_checks() => return Err("FEN has too many fields"),
+            Some(checks) => parse_checks(checks)?,
+            None => [0; 2],
+        };
+        if fields.next().is_some() {
+            return Err("FEN has too many fields");
+        }
+
+        // Crazyhouse pockets follow the placement in brackets, as in `...RNBQKBNR[Qn]`.
+        let (placement, pockets) = match placement.split_once('[') {
+            Some(_) if !self.variant.has_pockets() => {
+                return Err("FEN pockets are only used in crazyhouse");
+            }
+            Some((placement, pockets)) => (
+                placement,
+                parse_pockets(
+                    pockets
+                        .strip_suffix(']')
+                        .ok_or("FEN pockets must end with ]")?,
+                )?,
+            ),
+            None => (placement, [Pocket::EMPTY; 2]),
+        };
+        let ranks = placement.split('/').collect::<Vec<_>>();
+        if ranks.len() != 8 {
+            return Err("FEN piece placement must have 8 ranks");
+        }
+        let mut squares = [[None; 8]; 8];
+        let mut promoted = SquareMask::EMPTY;
+        for ((row, rank), y) in squares.iter_mut().rev().zip(ranks).zip((0..8).rev()) {
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    x += skip as usize;
+                } else if c == '~' {
+                    // Marks the piece before it as promoted.
+                    if x == 0 || !self.variant.has_pockets() {
+                        return Err("Invalid promoted piece marker in FEN");
+                    }
+                    promoted.insert(Coordinate::new_unchecked(x as u8 - 1, y));
+                } else {
+                    let square = row.get_mut(x).ok_or("FEN rank has too many squares")?;
+                    *square = Some(Piece::from_fen_char(c).ok_or("Invalid piece in FEN")?);
+                    x += 1;
+                }
+            }
+            if x != 8 {
+                return Err("FEN rank must describe exactly 8 squares");
+            }
+        }
+
+        let turn = match turn {
+            "w" => Colour::White,
+            "b" => Colour::Black,
+            _ => return Err("Invalid side to move in FEN"),
+        };
+
+        let mut white_castling = CastlingRights::NONE;
+        let mut black_castling = CastlingRights::NONE;
+        if castling != "-" {
+            if !self.variant.has_royal_king() {
+                return Err("FEN castling rights need a royal king");
+            }
+            for c in castling.chars() {
+                let (colour, kingside, file) = parse_castling_right(&squares, c)?;
+                let rights = match colour {
+                    Colour::White => &mut white_castling,
+                    Colour::Black => &mut black_castling,
+                };
+                if kingside {
+                    rights.kingside = Some(file);
+                } else {
+                    rights.queenside = Some(file);
+                }
+            }
+        }
+
+        let en_passant = match en_passant {
+            "-" => None,
+            square => Some(
+                square
+                    .parse::<Coordinate>()
+                    .map_err(|_| "Invalid en passant square in FEN")?,
+            ),
+        };
+
+        self.squares = squares;
+        self.pockets = pockets;
+        self.promoted = promoted;
+        self.checks = checks;
+        self.turn = turn;
+        self.white_castling = white_castling;
+        self.black_castling = black_castling;
+        self.en_passant = en_passant;
+        self.halfmove_clock = halfmove_clock
+            .parse()
+            .map_err(|_| "Invalid halfmove clock in FEN")?;
+        self.fullmove_number = fullmove_number
+            .parse()
+            .map_err(|_| "Invalid fullmove number in FEN")?;
+        self.move_list.clear();
+        self.undo_list.clear();
+        self.hash = self.compute_hash();
+
+        if !self.variant.is_legal_position(self) {
+            return Err("FEN describes an illegal position");
+        }
+        Ok(())
+    }
+
+    /// The position in FEN, with the castling field in X-FEN: `KQkq` as usual, but the rook's
+    /// file letter when another rook stands further out on the same side of the king.
+    pub fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    /// The position in Shredder-FEN, whose castling field always names the rooks' files, as in
+    /// `HAha` for the classical start position.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
+        let mut placement = self
+            .squares
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(y, row)| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for (x, square) in row.iter().enumerate() {
+                    match square {
+                        Some(piece) => {
+                            if empty > 0 {
+                                rank.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            rank.push(piece.to_fen_char());
+                            if self.is_promoted(&Coordinate::new_unchecked(x as u8, y as u8)) {
+                                rank.push('~');
+                            }
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        if self.variant.has_pockets() {
+            placement.push('[');
+            for colour in Colour::ALL {
+                for &piece_type in DROP_TYPES.iter().rev() {
+                    let piece = Piece { piece_type, colour };
+                    for _ in 0..self.pocket(colour).count(piece_type) {
+                        placement.push(piece.to_fen_char());
+                    }
+                }
+            }
+            placement.push(']');
+        }
+
+        let turn = match self.turn {
+            Colour::White => "w",
+            Colour::Black => "b",
+        };
+
+        let mut castling = String::new();
+        for colour in [Colour::White, Colour::Black] {
+            let rights = self.castling_rights(colour);
+            let home_rank = colour.home_rank();
+            let sides = [(rights.kingside, 'K', 7), (rights.queenside, 'Q', 0)];
+            for (rook_file, letter, corner) in sides {
+                let Some(rook_file) = rook_file else {
+                    continue;
+                };
+                let outer_files = rook_file.min(corner)..=rook_file.max(corner);
+                let outermost = outer_files.filter(|&x| x != rook_file).all(|x| {
+                    self.get_square(&Coordinate::new_unchecked(x, home_rank))
+                        != Some(Piece::rook(colour))
+                });
+                let c = if outermost && !shredder {
+                    letter
+                } else {
+                    (b'A' + rook_file) as char
+                };
+                castling.push(match colour {
+                    Colour::White => c,
+                    Colour::Black => c.to_ascii_lowercase(),
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = self
+            .en_passant
+            .map_or_else(|| "-".to_string(), |coord| coord.to_string());
+
+        let mut fen = format!(
+            "{placement} {turn} {castling} {en_passant} {} {}",
+            self.halfmove_clock, self.fullmove_number
+        );
+        if self.variant.counts_checks() {
+            fen.push_str(&format!(" +{}+{}", self.checks[0], self.checks[1]));
+        }
+        fen
+    }
+
+    /// Generates every legal move for the side to move.
+    ///
+    /// Rather than making each pseudo-legal move and testing `is_in_check`, the checkers, the
+    /// check-evasion mask and the pinned pieces are computed once up front, and each piece's
+    /// pseudo moves are filtered against them. In double check only king moves are generated.
+    pub fn get_legal_moves(&self) -> Vec<Move> {
+        self.generate_moves(MoveKind::All, None)
     }
 
-    // TODO: This method should validate that moves are for pieces matching self.turn.
-    // Pseudo move methods only validate piece type, not turn order.
-    fn get_legal_moves(&self) -> Vec<Move> {
-        todo!()
+    /// Generates the legal captures and promotions, for a search that wants to look at them
+    /// before paying for the quiet moves.
+    pub(crate) fn tactical_moves(&self) -> Vec<Move> {
+        self.generate_moves(MoveKind::Tactical, None)
+    }
+
+    /// Generates the legal moves that neither capture nor promote.
+    pub(crate) fn quiet_moves(&self) -> Vec<Move> {
+        self.generate_moves(MoveKind::Quiet, None)
+    }
+
+    /// Whether `mv` is legal in the current position, generating only the moving piece's moves.
+    /// Used to vet moves remembered from other positions, such as hash moves and killers.
+    pub(crate) fn is_legal(&self, mv: &Move) -> bool {
+        let kind = if mv.is_tactical() {
+            MoveKind::Tactical
+        } else {
+            MoveKind::Quiet
+        };
+        match mv.from {
+            Some(from) => {
+                self.get_square(&from) == Some(mv.piece)
+                    && self.generate_moves(kind, Some(from)).contains(mv)
+            }
+            None => self.generate_moves(kind, None).contains(mv),
+        }
+    }
+
+    /// Generates the legal moves of `kind` by the variant's rules, only for the piece on
+    /// `only_from` if given.
+    fn generate_moves(&self, kind: MoveKind, only_from: Option<Coordinate>) -> Vec<Move> {
+        self.variant.generate(self, kind, only_from)
+    }
+
+    /// Generates the moves of `kind` that are legal by the classical rules.
+    pub(crate) fn classical_moves(
+        &self,
+        kind: MoveKind,
+        only_from: Option<Coordinate>,
+    ) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let Some(king_pos) = self.find_king(self.turn) else {
+            return moves;
+        };
+        let opponent = self.turn.opposite();
+        let checkers = self.attackers_of(&king_pos, opponent);
+
+        // A king stepping directly away from a sliding checker would still be on its ray, but
+        // the king itself hides that square from `attackers_of`, so rule it out explicitly.
+        let mut xrayed = SquareMask::EMPTY;
+        for checker in &checkers {
+            if self.get_square(checker).is_some_and(|p| p.is_slider())
+                && let Ok(behind) = king_pos.try_apply_delta(checker.direction_to(&king_pos))
+            {
+                xrayed.insert(behind);
+            }
+        }
+        let king = Piece::king(self.turn);
+        let from_allowed = |from: &Coordinate| only_from.is_none_or(|only| only == *from);
+        if from_allowed(&king_pos) {
+            for to in self.pseudo_king_moves(&king_pos).unwrap_or_default() {
+                let mv = Move::new(king, king_pos, to, self.get_square(&to));
+                if kind.includes(&mv)
+                    && !xrayed.contains(&to)
+                    && self.attackers_of(&to, opponent).is_empty()
+                {
+                    moves.push(mv);
+                }
+            }
+        }
+
+        let check_mask = match checkers.as_slice() {
+            [] => SquareMask::FULL,
+            [checker] => {
+                let mut mask = king_pos.squares_between(checker);
+                mask.insert(*checker);
+                mask
+            }
+            // Double check, only the king can move.
+            _ => return moves,
+        };
+        let pins = self.pinned_pieces(&king_pos);
+
+        for (from, piece) in self.into_iter().flatten() {
+            if piece.colour != self.turn
+                || piece.piece_type == PieceType::King
+                || !from_allowed(&from)
+            {
+                continue;
+            }
+            let pin_ray = pins
+                .iter()
+                .find(|(pinned, _)| *pinned == from)
+                .map_or(SquareMask::FULL, |(_, ray)| *ray);
+            for to in self
+                .pseudo_moves_by_type(&from, piece.piece_type)
+                .unwrap_or_default()
+            {
+                if piece.piece_type == PieceType::Pawn && Some(to) == self.en_passant {
+                    if kind != MoveKind::Quiet && self.is_en_passant_legal(from, to) {
+                        moves.push(Move {
+                            is_en_passant: true,
+                            ..Move::new(piece, from, to, Some(Piece::pawn(opponent)))
+                        });
+                    }
+                    continue;
+                }
+                if !check_mask.contains(&to) || !pin_ray.contains(&to) {
+                    continue;
+                }
+                let mv = Move::new(piece, from, to, self.get_square(&to));
+                if piece.piece_type == PieceType::Pawn && (to.y == 0 || to.y == 7) {
+                    if kind == MoveKind::Quiet {
+                        continue;
+                    }
+                    moves.extend(PROMOTION_TYPES.iter().map(|&piece_type| Move {
+                        promotion: Some(Piece {
+                            piece_type,
+                            colour: self.turn,
+                        }),
+                        ..mv
+                    }));
+                } else if kind.includes(&mv) {
+                    moves.push(mv);
+                }
+            }
+        }
+
+        if checkers.is_empty() && kind != MoveKind::Tactical && from_allowed(&king_pos) {
+            self.push_castling_moves(&king_pos, &mut moves);
+        }
+        moves
+    }
+
+    /// Generates every move the pieces of the side to move can make, regardless of check, only
+    /// for the piece on `only_from` if given. Castling is not included.
+    pub(crate) fn pseudo_legal_moves(&self, only_from: Option<Coordinate>) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let promotion_types = self.variant.promotion_types();
+        let opponent = self.turn.opposite();
+        for (from, piece) in self.into_iter().flatten() {
+            if piece.colour != self.turn || only_from.is_some_and(|only| only != from) {
+                continue;
+            }
+            for to in self
+                .pseudo_moves_by_type(&from, piece.piece_type)
+                .unwrap_or_default()
+            {
+                let mv = if piece.piece_type == PieceType::Pawn && Some(to) == self.en_passant {
+                    Move {
+                        is_en_passant: true,
+                        ..Move::new(piece, from, to, Some(Piece::pawn(opponent)))
+                    }
+                } else {
+                    Move::new(piece, from, to, self.get_square(&to))
+                };
+                if piece.piece_type == PieceType::Pawn && (to.y == 0 || to.y == 7) {
+                    moves.extend(promotion_types.iter().map(|&piece_type| Move {
+                        promotion: Some(Piece {
+                            piece_type,
+                            colour: self.turn,
+                        }),
+                        ..mv
+                    }));
+                } else {
+                    moves.push(mv);
+                }
+            }
+        }
+        moves
+    }
+
+    /// Whether `mv` would put the opponent in check.
+    pub(crate) fn gives_check(&self, mv: &Move) -> bool {
+        let mut scratch = self.scratch();
+        scratch.make_move_unchecked(*mv);
+        scratch.in_check()
+    }
+
+    /// A copy of the pieces and the side to move alone, cheap enough to try a move out on.
+    pub(crate) fn scratch(&self) -> Self {
+        Self {
+            variant: self.variant,
+            squares: self.squares,
+            turn: self.turn,
+            ..Self::empty()
+        }
+    }
+
+    pub(crate) fn find_king(&self, colour: Colour) -> Option<Coordinate> {
+        self.into_iter()
+            .flatten()
+            .find(|(_, piece)| *piece == Piece::king(colour))
+            .map(|(coord, _)| coord)
+    }
+
+    /// Returns the squares of every `by` piece attacking `target`.
+    pub(crate) fn attackers_of(&self, target: &Coordinate, by: Colour) -> Vec<Coordinate> {
+        let pawn_direction = match by {
+            Colour::White => -1,
+            Colour::Black => 1,
+        };
+        let steppers = [
+            (PieceType::Knight, &KNIGHT_DELTAS[..]),
+            (PieceType::King, &KING_DIRS[..]),
+            (
+                PieceType::Pawn,
+                &[(1, pawn_direction), (-1, pawn_direction)][..],
+            ),
+        ];
+        let sliders = [
+            (PieceType::Rook, &ORTHOGONAL_DIRS),
+            (PieceType::Bishop, &DIAGONAL_DIRS),
+        ];
+
+        let mut attackers = Vec::new();
+        for (piece_type, deltas) in steppers {
+            attackers.extend(target.apply_deltas(deltas.iter().copied()).filter(|coord| {
+                self.get_square(coord)
+                    == Some(Piece {
+                        piece_type,
+                        colour: by,
+                    })
+            }));
+        }
+        for (piece_type, directions) in sliders {
+            for &direction in directions {
+                // Casting the ray as the defending side makes it stop on the first piece of the
+                // attacking colour, which is the only one that can see `target` along this ray.
+                let ray = RayIterator {
+                    board: self,
+                    current: *target,
+                    direction,
+                    moving_piece_colour: by.opposite(),
+                    stopped: false,
+                };
+                if let Some(coord) = ray.last()
+                    && self.get_square(&coord).is_some_and(|piece| {
+                        piece.piece_type == piece_type || piece.piece_type == PieceType::Queen
+                    })
+                {
+                    attackers.push(coord);
+                }
+            }
+        }
+        attackers
+    }
+
+    /// Whether the side to move's king is attacked.
+    pub fn in_check(&self) -> bool {
+        self.king_in_check(self.turn)
+    }
+
+    pub(crate) fn king_in_check(&self, colour: Colour) -> bool {
+        self.variant.can_be_in_check(self)
+            && self
+                .find_king(colour)
+                .is_some_and(|king_pos| self.is_square_attacked(&king_pos, colour.opposite()))
+    }
+
+    pub(crate) fn is_square_attacked(&self, target: &Coordinate, by: Colour) -> bool {
+        !self.attackers_of(target, by).is_empty()
+    }
+
+    /// Finds the pieces of the king's colour that are pinned to it, along with the ray each is
+    /// confined to. The ray runs from the king up to and including the pinning piece.
+    fn pinned_pieces(&self, king_pos: &Coordinate) -> Vec<(Coordinate, SquareMask)> {
+        let colour = self.turn;
+        let mut pins = Vec::new();
+        for (directions, slider) in [
+            (&ORTHOGONAL_DIRS, PieceType::Rook),
+            (&DIAGONAL_DIRS, PieceType::Bishop),
+        ] {
+            for &direction in directions {
+                let mut ray = SquareMask::EMPTY;
+                let mut pinned = None;
+                let mut current = *king_pos;
+                while let Ok(coord) = current.try_apply_delta(direction) {
+                    current = coord;
+                    ray.insert(coord);
+                    let Some(piece) = self.get_square(&coord) else {
+                        continue;
+                    };
+                    match pinned {
+                        None if piece.colour == colour => pinned = Some(coord),
+                        Some(pinned)
+                            if piece.colour != colour
+                                && (piece.piece_type == slider
+                                    || piece.piece_type == PieceType::Queen) =>
+                        {
+                            pins.push((pinned, ray));
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+        pins
+    }
+
+    /// En passant removes two pawns from the same rank at once, which can expose the king in
+    /// ways the pin and check masks do not capture, so it is verified on a scratch board.
+    fn is_en_passant_legal(&self, from: Coordinate, to: Coordinate) -> bool {
+        let mut scratch = Self {
+            squares: self.squares,
+            ..Self::empty()
+        };
+        scratch.squares[to.y as usize][to.x as usize] =
+            scratch.squares[from.y as usize][from.x as usize].take();
+        scratch.squares[from.y as usize][to.x as usize] = None;
+        scratch
+            .find_king(self.turn)
+            .is_some_and(|king_pos| !scratch.is_square_attacked(&king_pos, self.turn.opposite()))
+    }
+
+    fn castling_rights(&self, colour: Colour) -> CastlingRights {
+        match colour {
+            Colour::White => self.white_castling,
+            Colour::Black => self.black_castling,
+        }
+    }
+
+    /// Whether castling is set up as only Chess960 allows: with a rook off the a- or h-file,
+    /// or a king off the e-file. Chess960 positions with the classical setup cannot be told
+    /// apart from standard chess.
+    pub(crate) fn has_chess960_castling(&self) -> bool {
+        Colour::ALL.into_iter().any(|colour| {
+            let rights = self.castling_rights(colour);
+            let king_off_e_file =
+                self.find_king(colour) != Some(Coordinate::new_unchecked(4, colour.home_rank()));
+            rights != CastlingRights::NONE
+                && (king_off_e_file
+                    || rights.kingside.is_some_and(|file| file != 7)
+                    || rights.queenside.is_some_and(|file| file != 0))
+        })
+    }
+
+    /// Whether `colour` still has the right to castle on that side, whether or not castling is
+    /// possible right now.
+    pub(crate) fn can_castle(&self, colour: Colour, kingside: bool) -> bool {
+        let rights = self.castling_rights(colour);
+        if kingside {
+            rights.kingside.is_some()
+        } else {
+            rights.queenside.is_some()
+        }
+    }
+
+    fn castling_rights_mut(&mut self, colour: Colour) -> &mut CastlingRights {
+        match colour {
+            Colour::White => &mut self.white_castling,
+            Colour::Black => &mut self.black_castling,
+        }
+    }
+
+    /// Castling follows the Chess960 rules, which include the classical ones: the king ends on
+    /// the g- or c-file and the rook beside it on the f- or d-file. Every square the two pieces
+    /// cross, their destinations included, must be empty apart from the king and the rook
+    /// themselves, and none of the squares the king crosses may be attacked.
+    pub(crate) fn push_castling_moves(&self, king_pos: &Coordinate, moves: &mut Vec<Move>) {
+        let rights = self.castling_rights(self.turn);
+        let home_rank = self.turn.home_rank();
+        if king_pos.y != home_rank {
+            return;
+        }
+        let opponent = self.turn.opposite();
+        // (rook file, king destination, rook destination)
+        let sides = [(rights.kingside, 6, 5), (rights.queenside, 2, 3)];
+        for (rook_file, king_to, rook_to) in sides {
+            let Some(rook_file) = rook_file else {
+                continue;
+            };
+            let rook_pos = Coordinate::new_unchecked(rook_file, home_rank);
+            if self.get_square(&rook_pos) != Some(Piece::rook(self.turn))
+                || (rook_file > king_pos.x) != (king_to > rook_to)
+            {
+                continue;
+            }
+            let files = [king_pos.x, king_to, rook_file, rook_to];
+            let (first, last) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+            let blocked = (first..=last).any(|x| {
+                x != king_pos.x
+                    && x != rook_file
+                    && self
+                        .get_square(&Coordinate::new_unchecked(x, home_rank))
+                        .is_some()
+            });
+            if blocked {
+                continue;
+            }
+            // The rook is lifted off first, since in Chess960 it can stand between the king's
+            // destination and an enemy rook or queen along the home rank.
+            let mut scratch = Self {
+                squares: self.squares,
+                ..Self::empty()
+            };
+            scratch.squares[home_rank as usize][rook_file as usize] = None;
+            let mut path = king_pos.x.min(king_to)..=king_pos.x.max(king_to);
+            if path.any(|x| {
+                let coord = Coordinate::new_unchecked(x, home_rank);
+                !self
+                    .variant
+                    .is_safe_for_castling_king(&scratch, &coord, opponent)
+            }) {
+                continue;
+            }
+            moves.push(Move {
+                castling_rook: Some(rook_pos),
+                ..Move::new(
+                    Piece::king(self.turn),
+                    *king_pos,
+                    Coordinate::new_unchecked(king_to, home_rank),
+                    None,
+                )
+            });
+        }
     }
 
     fn validate_piece_type(
@@ -260,6 +1185,11 @@ impl Board {
             Colour::White => position.y == 1,
             Colour::Black => position.y == 6,
         };
+        // The rank an opponent's double push skips over, where an en passant target can be.
+        let en_passant_rank = match piece.colour {
+            Colour::White => 5,
+            Colour::Black => 2,
+        };
 
         let forward_one = position.try_apply_delta((0, direction)).ok();
         let forward_two = position.try_apply_delta((0, 2 * direction)).ok();
@@ -287,8 +1217,12 @@ impl Board {
                         }),
                 )
                 .chain(
-                    // FIXME: En passant captures would go here
-                    std::iter::empty(),
+                    // En passant captures
+                    position
+                        .apply_deltas([(1, direction), (-1, direction)].into_iter())
+                        .filter(move |coord| {
+                            Some(*coord) == self.en_passant && coord.y == en_passant_rank
+                        }),
                 )
                 .collect(),
         )
@@ -309,11 +1243,52 @@ impl Board {
         }
     }
 
-    fn is_board_legal(&self) -> bool {
-        todo!()
+    /// Pseudo-legal destinations for the piece at `position`, ignoring checks and pins.
+    pub fn pseudo_moves(&self, position: &Coordinate) -> Result<Vec<Coordinate>, String> {
+        let piece = self
+            .get_square(position)
+            .ok_or_else(|| "No piece at the given position".to_string())?;
+        self.pseudo_moves_by_type(position, piece.piece_type)
+    }
+
+    /// Whether a pawn of `colour` stands on rank `y`, counted from White's side.
+    pub(crate) fn has_pawn_on_rank(&self, colour: Colour, y: u8) -> bool {
+        self.squares[y as usize].contains(&Some(Piece::pawn(colour)))
+    }
+
+    /// How many kings `colour` has.
+    pub(crate) fn king_count(&self, colour: Colour) -> usize {
+        self.into_iter()
+            .flatten()
+            .filter(|(_, piece)| *piece == Piece::king(colour))
+            .count()
     }
 
-    fn is_in_check(&self, colour: Colour) -> Result<bool, String> {
+    /// Whether the side that just moved has left its king, if it has one, out of check, as it
+    /// always must.
+    pub(crate) fn is_opponent_safe(&self) -> bool {
+        let opponent = self.turn.opposite();
+        self.find_king(opponent).is_none() || self.is_in_check(opponent) == Ok(false)
+    }
+
+    /// Whether the position could come up in a game by the classical rules.
+    pub(crate) fn is_board_legal(&self) -> bool {
+        let mut king_counts = [0; 2];
+        for (coord, piece) in self.into_iter().flatten() {
+            match piece.piece_type {
+                PieceType::King => king_counts[piece.colour as usize] += 1,
+                PieceType::Pawn if coord.y == 0 || coord.y == 7 => return false,
+                _ => {}
+            }
+        }
+        // The side that just moved can never have left its own king in check.
+        king_counts == [1, 1] && self.is_in_check(self.turn.opposite()) == Ok(false)
+    }
+
+    pub fn is_in_check(&self, colour: Colour) -> Result<bool, String> {
+        if !self.variant.can_be_in_check(self) {
+            return Ok(false);
+        }
         let (king_pos, opponent_pieces) = self.into_iter().fold(
             (None, Vec::new()),
             |(king_pos, mut opponent_pieces), square| {
@@ -340,29 +1315,447 @@ impl Board {
         Ok(false)
     }
 
-    // FIXME: Should this be infallible, we should be able to leverage get_legal_moves.
-    fn make_move(&mut self, mv: Move) -> Result<(), &'static str> {
-        todo!()
+    /// Finds the legal move written in long algebraic notation, as produced by `Move`'s
+    /// `Display` implementation.
+    ///
+    /// Castling may also be written as the king capturing its own rook, as in Chess960.
+    pub fn parse_move(&self, s: &str) -> Result<Move, &'static str> {
+        self.get_legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_string() == s || mv.to_uci(true) == s)
+            .ok_or("Illegal move")
     }
-}
 
-struct RayIterator<'a> {
-    board: &'a Board,
-    current: Coordinate,
-    direction: (i8, i8),
-    moving_piece_colour: Colour,
-    stopped: bool,
-}
-
-impl<'a> Iterator for RayIterator<'a> {
-    type Item = Coordinate;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.stopped {
-            return None;
+    /// The move in Standard Algebraic Notation, as used by PGN: `Nbd2`, `exd6`, `O-O`, `e8=Q`,
+    /// or `N@f3` for a drop, followed by `+` for check and `#` for checkmate. `mv` must be legal.
+    pub fn san(&self, mv: &Move) -> String {
+        let mut san = self.san_without_check(mv, &self.get_legal_moves());
+        let mut after = self.clone();
+        after.make_move_unchecked(*mv);
+        if after.in_check() {
+            san.push(if after.get_legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
         }
+        san
+    }
 
-        match self.current.try_apply_delta(self.direction) {
+    /// Finds the legal move written in Standard Algebraic Notation. Check marks, annotations
+    /// such as `!?` and the `=` of a promotion may be left out, and castling may be written with
+    /// zeros as well.
+    pub fn parse_san(&self, s: &str) -> Result<Move, &'static str> {
+        let normalize = |san: &str| {
+            san.trim_end_matches(['+', '#', '!', '?'])
+                .replace('0', "O")
+                .replace('=', "")
+        };
+        let wanted = normalize(s);
+        let moves = self.get_legal_moves();
+        moves
+            .iter()
+            .find(|mv| normalize(&self.san_without_check(mv, &moves)) == wanted)
+            .copied()
+            .ok_or("Illegal move")
+    }
+
+    /// SAN without the check mark. The origin square is only given, by file if that suffices,
+    /// else by rank, else in full, when another piece of the same kind could reach `mv`'s
+    /// destination among `legal_moves`.
+    fn san_without_check(&self, mv: &Move, legal_moves: &[Move]) -> String {
+        let Some(from) = mv.from else {
+            return mv.to_string();
+        };
+        if mv.castling_rook.is_some() {
+            return if mv.to.x == 6 { "O-O" } else { "O-O-O" }.to_string();
+        }
+        let letter = |piece_type| {
+            Piece {
+                piece_type,
+                colour: Colour::White,
+            }
+            .to_fen_char()
+        };
+        let mut san = String::new();
+        if mv.piece.piece_type == PieceType::Pawn {
+            if mv.captured.is_some() {
+                san.push((b'a' + from.x) as char);
+            }
+        } else {
+            san.push(letter(mv.piece.piece_type));
+            let rivals: Vec<_> = legal_moves
+                .iter()
+                .filter(|other| {
+                    other.piece == mv.piece
+                        && other.to == mv.to
+                        && other.from != mv.from
+                        && other.castling_rook.is_none()
+                })
+                .filter_map(|other| other.from)
+                .collect();
+            if rivals.iter().all(|rival| rival.x != from.x) {
+                if !rivals.is_empty() {
+                    san.push((b'a' + from.x) as char);
+                }
+            } else if rivals.iter().all(|rival| rival.y != from.y) {
+                san.push((b'1' + from.y) as char);
+            } else {
+                san.push_str(&from.to_string());
+            }
+        }
+        if mv.captured.is_some() {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(letter(promotion.piece_type));
+        }
+        san
+    }
+
+    /// Plays `mv` if it is one of the legal moves in the current position.
+    pub fn make_move(&mut self, mv: Move) -> Result<(), &'static str> {
+        if !self.get_legal_moves().contains(&mv) {
+            return Err("Illegal move");
+        }
+        self.make_move_unchecked(mv);
+        Ok(())
+    }
+
+    /// Plays `mv` without checking it is legal. `mv` must come from `get_legal_moves` for the
+    /// current position, otherwise the board is left in an inconsistent state.
+    pub(crate) fn make_move_unchecked(&mut self, mv: Move) {
+        let mut undo = UndoState {
+            pockets: self.pockets,
+            promoted: self.promoted,
+            checks: self.checks,
+            exploded: [None; 8],
+            white_castling: self.white_castling,
+            black_castling: self.black_castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        let old_state_hash = self.state_hash();
+
+        let colour = mv.piece.colour;
+        if let Some(from) = mv.from {
+            self.set_square(from, None);
+        }
+        // In Chess960 the king may land where the rook stood, or the rook where the king stood,
+        // so both pieces are lifted before either is put down.
+        let castling_rook = mv.castling_rook.map(|rook_from| {
+            let rook = self.get_square(&rook_from);
+            self.set_square(rook_from, None);
+            rook
+        });
+        self.set_square(mv.to, Some(mv.promotion.unwrap_or(mv.piece)));
+        if mv.is_en_passant {
+            self.set_square(mv.en_passant_capture_square(), None);
+        }
+        if let Some(rook) = castling_rook {
+            self.set_square(castling_rook_destination(&mv.to), rook);
+        }
+
+        if mv.piece.piece_type == PieceType::King {
+            self.remove_castling_rights(colour);
+        }
+        // Moving a rook off, or capturing one on, its starting square loses that side's right.
+        for coord in mv.from.into_iter().chain([mv.to]) {
+            self.remove_castling_right_on(&coord);
+        }
+
+        self.en_passant = mv
+            .from
+            .filter(|from| mv.piece.piece_type == PieceType::Pawn && from.y.abs_diff(mv.to.y) == 2)
+            .map(|from| Coordinate::new_unchecked(from.x, (from.y + mv.to.y) / 2));
+        if mv.piece.piece_type == PieceType::Pawn || mv.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if colour == Colour::Black {
+            self.fullmove_number += 1;
+        }
+        self.turn = colour.opposite();
+        self.variant.after_move(self, &mv, &mut undo);
+        self.hash ^= old_state_hash ^ self.state_hash();
+        self.undo_list.push(undo);
+        self.move_list.push(mv);
+    }
+
+    pub(crate) fn clear_en_passant(&mut self) {
+        self.en_passant = None;
+    }
+
+    pub(crate) fn remove_castling_rights(&mut self, colour: Colour) {
+        *self.castling_rights_mut(colour) = CastlingRights::NONE;
+    }
+
+    /// Takes away the castling right of the rook starting on `coord`, if either side has one.
+    pub(crate) fn remove_castling_right_on(&mut self, coord: &Coordinate) {
+        for colour in Colour::ALL {
+            if coord.y != colour.home_rank() {
+                continue;
+            }
+            let rights = self.castling_rights_mut(colour);
+            if rights.kingside == Some(coord.x) {
+                rights.kingside = None;
+            }
+            if rights.queenside == Some(coord.x) {
+                rights.queenside = None;
+            }
+        }
+    }
+
+    /// Counts a check given by `colour`. Moves played on past the end of the game add no more,
+    /// so the counter stays within the keys `zobrist::checks` has.
+    pub(crate) fn count_check(&mut self, colour: Colour) {
+        let checks = &mut self.checks[colour as usize];
+        if *checks < zobrist::MAX_CHECKS {
+            *checks += 1;
+        }
+    }
+
+    /// Passes the turn to the opponent without moving, for null move pruning. Must not be
+    /// played in check. Not recorded in the move list, the returned state takes it back.
+    /// Whether the position came up before since the last capture or pawn move, looking back
+    /// through the moves played. A position from before a null move never counts, since it had
+    /// the other side to move.
+    pub(crate) fn is_repetition(&self) -> bool {
+        self.undo_list
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|undo| undo.hash == self.hash)
+    }
+
+    pub(crate) fn make_null_move(&mut self) -> UndoState {
+        let undo = UndoState {
+            pockets: self.pockets,
+            promoted: self.promoted,
+            checks: self.checks,
+            exploded: [None; 8],
+            white_castling: self.white_castling,
+            black_castling: self.black_castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        let old_state_hash = self.state_hash();
+        self.en_passant = None;
+        self.halfmove_clock += 1;
+        self.turn = self.turn.opposite();
+        self.hash ^= old_state_hash ^ self.state_hash();
+        undo
+    }
+
+    pub(crate) fn unmake_null_move(&mut self, undo: UndoState) {
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.turn = self.turn.opposite();
+        self.hash = undo.hash;
+    }
+
+    /// Takes back the last move played, returning it.
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let mv = self.move_list.pop()?;
+        let undo = self
+            .undo_list
+            .pop()
+            .expect("undo_list is kept in lockstep with move_list");
+
+        if let Some(rook_from) = mv.castling_rook {
+            let rook_to = castling_rook_destination(&mv.to);
+            let rook = self.get_square(&rook_to);
+            self.set_square(rook_to, None);
+            self.set_square(mv.to, None);
+            self.set_square(rook_from, rook);
+        }
+        if let Some(from) = mv.from {
+            self.set_square(from, Some(mv.piece));
+        }
+        if mv.is_en_passant {
+            self.set_square(mv.to, None);
+            self.set_square(mv.en_passant_capture_square(), mv.captured);
+        } else if mv.castling_rook.is_none() {
+            self.set_square(mv.to, mv.captured);
+        }
+        self.variant.undo(self, &mv, &undo);
+
+        self.pockets = undo.pockets;
+        self.promoted = undo.promoted;
+        self.checks = undo.checks;
+        self.white_castling = undo.white_castling;
+        self.black_castling = undo.black_castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        if mv.piece.colour == Colour::Black {
+            self.fullmove_number -= 1;
+        }
+        self.turn = mv.piece.colour;
+        self.hash = undo.hash;
+        Some(mv)
+    }
+
+    /// Static exchange evaluation: the material `mv`'s side can expect to win on its destination
+    /// square once both sides have made every profitable recapture there, in centipawns.
+    pub fn see(&self, mv: &Move) -> i32 {
+        self.variant.see(self, mv)
+    }
+
+    /// Static exchange evaluation by the classical rules.
+    ///
+    /// Each recapture is made by the least valuable attacker left. Attackers are recomputed from
+    /// the emptied board after every capture, so sliding pieces lined up behind one another
+    /// (x-rays) join in as the pieces in front of them are exchanged. Pins are ignored.
+    pub(crate) fn classical_see(&self, mv: &Move) -> i32 {
+        let mut scratch = Self {
+            squares: self.squares,
+            ..Self::empty()
+        };
+        let target = mv.to;
+        let mover = mv.promotion.unwrap_or(mv.piece);
+        let mut gains = vec![
+            mv.captured
+                .map_or(0, |piece| exchange_value(piece.piece_type))
+                + mv.promotion.map_or(0, |piece| {
+                    exchange_value(piece.piece_type) - exchange_value(PieceType::Pawn)
+                }),
+        ];
+        if let Some(from) = mv.from {
+            scratch.squares[from.y as usize][from.x as usize] = None;
+        }
+        if mv.is_en_passant {
+            let captured_on = mv.en_passant_capture_square();
+            scratch.squares[captured_on.y as usize][captured_on.x as usize] = None;
+        }
+        scratch.squares[target.y as usize][target.x as usize] = Some(mover);
+
+        let mut on_target = exchange_value(mover.piece_type);
+        let mut side = mv.piece.colour.opposite();
+        loop {
+            let attackers = scratch.attackers_of(&target, side);
+            let Some(attacker) = attackers
+                .iter()
+                .min_by_key(|coord| exchange_value(scratch.get_square(coord).unwrap().piece_type))
+            else {
+                break;
+            };
+            let piece = scratch.get_square(attacker).unwrap();
+            // The king may only recapture when nothing can take it back.
+            if piece.piece_type == PieceType::King
+                && scratch.is_square_attacked(&target, side.opposite())
+            {
+                break;
+            }
+            gains.push(on_target - gains[gains.len() - 1]);
+            on_target = exchange_value(piece.piece_type);
+            scratch.squares[attacker.y as usize][attacker.x as usize] = None;
+            scratch.squares[target.y as usize][target.x as usize] = Some(piece);
+            side = side.opposite();
+        }
+
+        // Unwind the exchange, each side stopping as soon as carrying on would lose material.
+        for i in (1..gains.len()).rev() {
+            gains[i - 1] = -(-gains[i - 1]).max(gains[i]);
+        }
+        gains[0]
+    }
+
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.get_legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                self.make_move_unchecked(mv);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move();
+                nodes
+            })
+            .sum()
+    }
+}
+
+/// A piece's worth in static exchange evaluation. The king can take part but never be given up,
+/// so it outweighs everything else.
+pub(crate) fn exchange_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::King => 20_000,
+        piece_type => eval::piece_value(piece_type),
+    }
+}
+
+/// Returns the rook's destination for a castling move whose king lands on `king_to`, beside the
+/// king towards the centre.
+fn castling_rook_destination(king_to: &Coordinate) -> Coordinate {
+    let file = if king_to.x == 6 { 5 } else { 3 };
+    Coordinate::new_unchecked(file, king_to.y)
+}
+
+/// Reads one character of an X-FEN or Shredder-FEN castling field as the colour, the side and
+/// the rook's file. `K` and `Q` stand for the outermost rook on that side of the king, a file
+/// letter for the rook on that file. Lowercase letters are Black's.
+fn parse_castling_right(
+    squares: &[[Option<Piece>; 8]; 8],
+    c: char,
+) -> Result<(Colour, bool, u8), &'static str> {
+    let colour = if c.is_ascii_uppercase() {
+        Colour::White
+    } else {
+        Colour::Black
+    };
+    let home_rank = &squares[colour.home_rank() as usize];
+    let king_file = home_rank
+        .iter()
+        .position(|&piece| piece == Some(Piece::king(colour)));
+    let is_rook = |x: &usize| home_rank[*x] == Some(Piece::rook(colour));
+    match (c.to_ascii_lowercase(), king_file) {
+        ('k', Some(king)) => Ok((
+            colour,
+            true,
+            (king + 1..8).rev().find(is_rook).unwrap_or(7) as u8,
+        )),
+        ('q', Some(king)) => Ok((colour, false, (0..king).find(is_rook).unwrap_or(0) as u8)),
+        // Such a right can never be used, but keeping it lets the FEN round-trip.
+        ('k', None) => Ok((colour, true, 7)),
+        ('q', None) => Ok((colour, false, 0)),
+        (file @ 'a'..='h', Some(king)) if (file as u8 - b'a') as usize != king => {
+            let file = file as u8 - b'a';
+            Ok((colour, file as usize > king, file))
+        }
+        _ => Err("Invalid castling rights in FEN"),
+    }
+}
+
+struct RayIterator<'a> {
+    board: &'a Board,
+    current: Coordinate,
+    direction: (i8, i8),
+    moving_piece_colour: Colour,
+    stopped: bool,
+}
+
+impl<'a> Iterator for RayIterator<'a> {
+    type Item = Coordinate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return None;
+        }
+
+        match self.current.try_apply_delta(self.direction) {
             Ok(coord) => {
                 self.current = coord;
                 match self.board.get_square(&coord) {
@@ -390,39 +1783,242 @@ impl<'a> Iterator for RayIterator<'a> {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) enum Colour {
+pub enum Colour {
     White,
     Black,
 }
 
 impl Colour {
-    pub(crate) fn opposite(&self) -> Self {
+    pub const ALL: [Self; 2] = [Self::White, Self::Black];
+
+    pub fn opposite(&self) -> Self {
         match self {
             Colour::White => Colour::Black,
             Colour::Black => Colour::White,
         }
     }
+
+    /// The rank this colour's pieces start on.
+    pub(crate) const fn home_rank(&self) -> u8 {
+        match self {
+            Colour::White => 0,
+            Colour::Black => 7,
+        }
+    }
 }
 
+/// Which of the legal moves `Board::generate_moves` should produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MoveKind {
+    All,
+    /// Captures and promotions.
+    Tactical,
+    Quiet,
+}
+
+impl MoveKind {
+    pub(crate) fn includes(self, mv: &Move) -> bool {
+        match self {
+            Self::All => true,
+            Self::Tactical => mv.is_tactical(),
+            Self::Quiet => !mv.is_tactical(),
+        }
+    }
+}
+
+/// The pieces that can be held in a crazyhouse pocket, in the order of `Pocket::counts`.
+pub(crate) const DROP_TYPES: [PieceType; 5] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+pub(crate) const PROMOTION_TYPES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) struct Move {
+pub struct Move {
     piece: Piece,
-    from: Coordinate,
+    /// `None` for a piece dropped from a pocket in crazyhouse.
+    from: Option<Coordinate>,
     to: Coordinate,
     captured: Option<Piece>,
     promotion: Option<Piece>,
-    is_castling: bool,
+    /// The square of the rook a castling king takes along.
+    castling_rook: Option<Coordinate>,
     is_en_passant: bool,
 }
 
+impl Move {
+    pub(crate) const fn new(
+        piece: Piece,
+        from: Coordinate,
+        to: Coordinate,
+        captured: Option<Piece>,
+    ) -> Self {
+        Self {
+            piece,
+            from: Some(from),
+            to,
+            captured,
+            promotion: None,
+            castling_rook: None,
+            is_en_passant: false,
+        }
+    }
+
+    /// Dropping `piece` from its side's pocket onto `to`.
+    pub(crate) const fn drop(piece: Piece, to: Coordinate) -> Self {
+        Self {
+            from: None,
+            ..Self::new(piece, to, to, None)
+        }
+    }
+
+    pub fn piece(&self) -> Piece {
+        self.piece
+    }
+
+    /// The square the piece moves from, `None` for a drop.
+    pub fn from(&self) -> Option<Coordinate> {
+        self.from
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.from.is_none()
+    }
+
+    pub fn to(&self) -> Coordinate {
+        self.to
+    }
+
+    pub fn captured(&self) -> Option<Piece> {
+        self.captured
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        self.promotion
+    }
+
+    pub fn is_castling(&self) -> bool {
+        self.castling_rook.is_some()
+    }
+
+    /// For castling, the square the rook starts from.
+    pub fn castling_rook(&self) -> Option<Coordinate> {
+        self.castling_rook
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.is_en_passant
+    }
+
+    /// The square of the pawn an en passant capture takes, beside the capturing pawn.
+    pub(crate) fn en_passant_capture_square(&self) -> Coordinate {
+        let from = self.from.expect("en passant is never a drop");
+        Coordinate::new_unchecked(self.to.x, from.y)
+    }
+
+    /// Whether the move captures or promotes, changing the material on the board.
+    pub(crate) fn is_tactical(&self) -> bool {
+        self.captured.is_some() || self.promotion.is_some()
+    }
+
+    /// Packs the move into the low 26 bits of a `u32`, for the transposition table. Castling
+    /// never captures, so the captured piece's bits hold the castling rook's file instead. A drop
+    /// is packed as coming from its destination, which no other move but castling does.
+    pub(crate) fn to_bits(self) -> u32 {
+        let piece = |piece: Option<Piece>| piece.map_or(0, |piece| piece.index() as u32 + 1);
+        let captured = match self.castling_rook {
+            Some(rook) => rook.x as u32,
+            None => piece(self.captured),
+        };
+        piece(Some(self.piece))
+            | (self.from.unwrap_or(self.to).index() as u32) << 4
+            | (self.to.index() as u32) << 10
+            | captured << 16
+            | piece(self.promotion) << 20
+            | (self.castling_rook.is_some() as u32) << 24
+            | (self.is_en_passant as u32) << 25
+    }
+
+    /// Unpacks a move packed by `to_bits`. The move is not checked against any position.
+    pub(crate) fn from_bits(bits: u32) -> Option<Self> {
+        let piece = |shift: u32| match (bits >> shift) & 0xF {
+            0 => Ok(None),
+            code => Piece::from_index(code as usize - 1).map(Some).ok_or(()),
+        };
+        let from = Coordinate::from_index((bits >> 4) as usize & 0x3F);
+        let to = Coordinate::from_index((bits >> 10) as usize & 0x3F);
+        let is_castling = bits >> 24 & 1 == 1;
+        Some(Self {
+            piece: piece(0).ok()??,
+            from: (from != to || is_castling).then_some(from),
+            to,
+            captured: if is_castling { None } else { piece(16).ok()? },
+            promotion: piece(20).ok()?,
+            castling_rook: is_castling
+                .then(|| Coordinate::new_unchecked((bits >> 16) as u8 & 7, from.y)),
+            is_en_passant: bits >> 25 & 1 == 1,
+        })
+    }
+}
+
+impl Move {
+    /// The move in UCI's long algebraic notation. In Chess960 castling is written as the king
+    /// capturing its own rook, e.g. `e1h1`, since the king may not move at all otherwise.
+    pub fn to_uci(&self, chess960: bool) -> String {
+        match (self.from, self.castling_rook) {
+            (Some(from), Some(rook)) if chess960 => format!("{from}{rook}"),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Formats the move in long algebraic notation as used by UCI, e.g. `e2e4`, `e1g1` or `e7e8q`.
+/// Drops are written with the piece's letter, e.g. `N@f3`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.from {
+            Some(from) => write!(f, "{from}{}", self.to)?,
+            None => write!(
+                f,
+                "{}@{}",
+                self.piece.to_fen_char().to_ascii_uppercase(),
+                self.to
+            )?,
+        }
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.to_fen_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) struct Coordinate {
+pub struct Coordinate {
     x: u8,
     y: u8,
 }
 
 impl Coordinate {
-    pub(crate) const fn new(x: u8, y: u8) -> Result<Self, &'static str> {
+    /// A distinct index in `0..64` for each square, a1 = 0, b1 = 1, ..., h8 = 63.
+    pub(crate) const fn index(&self) -> usize {
+        self.y as usize * 8 + self.x as usize
+    }
+
+    /// The square with the given index, which must be below 64.
+    pub(crate) const fn from_index(index: usize) -> Self {
+        Self::new_unchecked((index % 8) as u8, (index / 8) as u8)
+    }
+
+    pub const fn new(x: u8, y: u8) -> Result<Self, &'static str> {
         if x < 8 && y < 8 {
             Ok(Self::new_unchecked(x, y))
         } else {
@@ -434,6 +2030,50 @@ impl Coordinate {
         Self { x, y }
     }
 
+    /// The file, from 0 (a) to 7 (h).
+    pub const fn x(&self) -> u8 {
+        self.x
+    }
+
+    /// The rank, from 0 (1) to 7 (8).
+    pub const fn y(&self) -> u8 {
+        self.y
+    }
+
+    /// The number of king steps between this coordinate and `other`.
+    pub(crate) fn distance(&self, other: &Coordinate) -> u8 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+
+    /// The unit step from this coordinate towards `other`, assuming they share a rank, file or
+    /// diagonal.
+    pub(crate) fn direction_to(&self, other: &Coordinate) -> (i8, i8) {
+        (
+            (other.x as i8 - self.x as i8).signum(),
+            (other.y as i8 - self.y as i8).signum(),
+        )
+    }
+
+    /// The squares strictly between this coordinate and `other` along a shared rank, file or
+    /// diagonal. Empty when they are adjacent or not aligned.
+    pub(crate) fn squares_between(&self, other: &Coordinate) -> SquareMask {
+        let mut mask = SquareMask::EMPTY;
+        let (dx, dy) = (other.x as i8 - self.x as i8, other.y as i8 - self.y as i8);
+        if dx != 0 && dy != 0 && dx.abs() != dy.abs() {
+            return mask;
+        }
+        let direction = self.direction_to(other);
+        let mut current = *self;
+        while let Ok(coord) = current.try_apply_delta(direction) {
+            if coord == *other {
+                break;
+            }
+            mask.insert(coord);
+            current = coord;
+        }
+        mask
+    }
+
     pub(crate) fn try_apply_delta(&self, (dx, dy): (i8, i8)) -> Result<Coordinate, &'static str> {
         let new_x = self.x as i8 + dx;
         let new_y = self.y as i8 + dy;
@@ -455,18 +2095,135 @@ impl Coordinate {
     }
 }
 
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.x) as char, self.y + 1)
+    }
+}
+
+impl FromStr for Coordinate {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Ok(Self::new_unchecked(file - b'a', rank - b'1'))
+            }
+            _ => Err("Invalid algebraic coordinate"),
+        }
+    }
+}
+
+/// A set of squares, one bit per square.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SquareMask(u64);
+
+impl SquareMask {
+    pub(crate) const EMPTY: Self = Self(0);
+    pub(crate) const FULL: Self = Self(u64::MAX);
+
+    const fn bit(coord: &Coordinate) -> u64 {
+        1 << (coord.y * 8 + coord.x)
+    }
+
+    pub(crate) fn insert(&mut self, coord: Coordinate) {
+        self.0 |= Self::bit(&coord);
+    }
+
+    pub(crate) fn remove(&mut self, coord: &Coordinate) {
+        self.0 &= !Self::bit(coord);
+    }
+
+    pub(crate) const fn contains(&self, coord: &Coordinate) -> bool {
+        self.0 & Self::bit(coord) != 0
+    }
+}
+
+/// The pieces a side has captured in crazyhouse, which it may drop back onto the board as its
+/// own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pocket {
+    // Indexed like `DROP_TYPES`.
+    counts: [u8; 5],
+}
+
+impl Pocket {
+    pub const EMPTY: Self = Self { counts: [0; 5] };
+
+    /// How many pieces of `piece_type` the pocket holds, never any kings.
+    pub fn count(&self, piece_type: PieceType) -> u8 {
+        self.counts.get(piece_type as usize).copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::EMPTY
+    }
+
+    pub(crate) fn add(&mut self, piece_type: PieceType) {
+        self.counts[piece_type as usize] += 1;
+    }
+
+    pub(crate) fn remove(&mut self, piece_type: PieceType) {
+        self.counts[piece_type as usize] -= 1;
+    }
+}
+
+/// Parses the pockets inside a FEN's brackets, one piece letter per piece held, upper case for
+/// White's.
+fn parse_pockets(field: &str) -> Result<[Pocket; 2], &'static str> {
+    let mut pockets = [Pocket::EMPTY; 2];
+    for c in field.chars() {
+        let piece = Piece::from_fen_char(c)
+            .filter(|piece| piece.piece_type != PieceType::King)
+            .ok_or("Invalid piece in FEN pockets")?;
+        let pocket = &mut pockets[piece.colour as usize];
+        if pocket.count(piece.piece_type) >= zobrist::MAX_POCKET_COUNT {
+            return Err("Too many pieces in FEN pockets");
+        }
+        pocket.add(piece.piece_type);
+    }
+    Ok(pockets)
+}
+
+/// Parses three-check's counter field, `+W+B` for the checks White and Black have given.
+fn parse_checks(field: &str) -> Result<[u8; 2], &'static str> {
+    let (white, black) = field
+        .strip_prefix('+')
+        .and_then(|counts| counts.split_once('+'))
+        .ok_or("Invalid check counts in FEN")?;
+    let mut checks = [0; 2];
+    for (count, field) in checks.iter_mut().zip([white, black]) {
+        *count = field
+            .parse()
+            .ok()
+            .filter(|&count| count <= zobrist::MAX_CHECKS)
+            .ok_or("Invalid check counts in FEN")?;
+    }
+    Ok(checks)
+}
+
+/// The files of the rooks a side may still castle with, if any. In classical chess these are
+/// always the a- and h-files, in Chess960 they are wherever the rooks started.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) struct CastlingRights {
-    kingside: bool,
-    queenside: bool,
+    kingside: Option<u8>,
+    queenside: Option<u8>,
+}
+
+impl CastlingRights {
+    const NONE: Self = Self {
+        kingside: None,
+        queenside: None,
+    };
+    const CLASSICAL: Self = Self {
+        kingside: Some(7),
+        queenside: Some(0),
+    };
 }
 
 impl Default for CastlingRights {
     fn default() -> Self {
-        Self {
-            kingside: true,
-            queenside: true,
-        }
+        Self::CLASSICAL
     }
 }
 
@@ -1606,6 +3363,7 @@ mod tests {
         }
     }
 
+    #[test]
     fn board_iteration() {
         assert_eq!(
             Board::default().into_iter().flatten().collect::<Vec<_>>(),
@@ -1875,4 +3633,883 @@ mod tests {
 
         assert!(board.is_in_check(Colour::White).unwrap());
     }
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[rstest]
+    #[case::start_position(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        vec![20, 400, 8902, 197281]
+    )]
+    #[case::kiwipete(KIWIPETE, vec![48, 2039, 97862])]
+    #[case::rook_endgame("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", vec![14, 191, 2812, 43238])]
+    #[case::promotions_and_pins(
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        vec![6, 264, 9467]
+    )]
+    #[case::underpromotion_check(
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        vec![44, 1486, 62379]
+    )]
+    #[case::middlegame(
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        vec![46, 2079, 89890]
+    )]
+    #[case::chess960(
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        vec![21, 528, 12189]
+    )]
+    fn perft(#[case] fen: &str, #[case] expected: Vec<u64>) {
+        let mut board = Board::from_fen(fen).unwrap();
+        for (depth, nodes) in (1..).zip(expected) {
+            assert_eq!(board.perft(depth), nodes, "depth {depth}");
+        }
+        // make/unmake must leave the position untouched
+        assert_eq!(board.to_fen(), Board::from_fen(fen).unwrap().to_fen());
+    }
+
+    #[rstest]
+    #[case::start_position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")]
+    #[case::kiwipete(KIWIPETE)]
+    #[case::en_passant("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")]
+    #[case::black_to_move("8/8/8/4k3/8/8/8/R3K3 b Q - 12 40")]
+    fn fen_round_trip(#[case] fen: &str) {
+        assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+    }
+
+    #[rstest]
+    #[case::pawn_push(Board::new(), "e2e4")]
+    #[case::castling(Board::from_fen(KIWIPETE).unwrap(), "e1c1")]
+    #[case::promotion(Board::from_fen("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1").unwrap(), "b7b8n")]
+    fn long_algebraic_round_trip(#[case] board: Board, #[case] notation: &str) {
+        assert_eq!(board.parse_move(notation).unwrap().to_string(), notation);
+    }
+
+    #[rstest]
+    #[case::illegal("e2e5")]
+    #[case::missing_promotion("b7b8")]
+    #[case::garbage("castle")]
+    fn parse_move_rejects(#[case] notation: &str) {
+        let board = Board::from_fen("8/1P2k3/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.parse_move(notation), Err("Illegal move"));
+    }
+
+    #[rstest]
+    #[case::pawn_push(Board::new(), "e2e4", "e4")]
+    #[case::knight(Board::new(), "g1f3", "Nf3")]
+    #[case::kingside_castling(Board::from_fen(KIWIPETE).unwrap(), "e1g1", "O-O")]
+    #[case::queenside_castling(Board::from_fen(KIWIPETE).unwrap(), "e1c1", "O-O-O")]
+    #[case::by_file(
+        Board::from_fen("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").unwrap(),
+        "a1d1",
+        "Rad1"
+    )]
+    #[case::by_rank(
+        Board::from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap(),
+        "a1a3",
+        "R1a3"
+    )]
+    #[case::by_square(
+        Board::from_fen("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1").unwrap(),
+        "a1b2",
+        "Qa1b2"
+    )]
+    #[case::en_passant(
+        Board::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap(),
+        "e5f6",
+        "exf6"
+    )]
+    #[case::promotion_with_check(
+        Board::from_fen("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap(),
+        "b7b8q",
+        "b8=Q+"
+    )]
+    #[case::checkmate(
+        Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap(),
+        "d8h4",
+        "Qh4#"
+    )]
+    #[case::drop(crazyhouse("4k3/8/8/8/8/8/8/4K3[N] w - - 0 1"), "N@f3", "N@f3")]
+    fn san_round_trip(#[case] board: Board, #[case] notation: &str, #[case] san: &str) {
+        let mv = board.parse_move(notation).unwrap();
+        assert_eq!(board.san(&mv), san);
+        assert_eq!(board.parse_san(san), Ok(mv));
+    }
+
+    #[rstest]
+    #[case::zeros(Board::from_fen(KIWIPETE).unwrap(), "0-0", "e1g1")]
+    #[case::annotated(Board::new(), "Nf3!?", "g1f3")]
+    #[case::promotion_without_equals(
+        Board::from_fen("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap(),
+        "b8Q",
+        "b7b8q"
+    )]
+    fn lenient_san(#[case] board: Board, #[case] san: &str, #[case] notation: &str) {
+        assert_eq!(board.parse_san(san), board.parse_move(notation));
+    }
+
+    #[rstest]
+    #[case::ambiguous("Rd1")]
+    #[case::illegal("Rd8")]
+    #[case::garbage("castle")]
+    fn parse_san_rejects(#[case] san: &str) {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").unwrap();
+        assert_eq!(board.parse_san(san), Err("Illegal move"));
+    }
+
+    #[test]
+    fn default_board_matches_start_fen() {
+        assert_eq!(
+            Board::default().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[rstest]
+    #[case::too_few_ranks("8/8/8/8/8/8/8 w - - 0 1")]
+    #[case::rank_too_long("9/8/8/8/8/8/8/8 w - - 0 1")]
+    #[case::bad_piece("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1")]
+    #[case::bad_turn("4k3/8/8/8/8/8/8/4K3 x - - 0 1")]
+    #[case::bad_castling("4k3/8/8/8/8/8/8/4K3 w Z - 0 1")]
+    #[case::bad_en_passant("4k3/8/8/8/8/8/8/4K3 w - z9 0 1")]
+    #[case::missing_king("8/8/8/8/8/8/8/4K3 w - - 0 1")]
+    #[case::pawn_on_back_rank("P3k3/8/8/8/8/8/8/4K3 w - - 0 1")]
+    #[case::side_not_to_move_in_check("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1")]
+    fn invalid_fen(#[case] fen: &str) {
+        assert!(Board::from_fen(fen).is_err());
+    }
+
+    fn legal_destinations(board: &Board, from: Coordinate) -> Vec<Coordinate> {
+        board
+            .get_legal_moves()
+            .into_iter()
+            .filter(|mv| mv.from() == Some(from))
+            .map(|mv| mv.to())
+            .collect()
+    }
+
+    #[test]
+    fn double_check_only_allows_king_moves() {
+        // Rook on e8 and bishop on b4 both check the king on e1
+        let board = Board::from_fen("4r1k1/8/8/8/1b6/8/3N4/4K3 w - - 0 1").unwrap();
+        let moves = board.get_legal_moves();
+        assert!(!moves.is_empty());
+        assert!(
+            moves
+                .iter()
+                .all(|mv| mv.piece().piece_type() == PieceType::King)
+        );
+    }
+
+    #[test]
+    fn king_cannot_retreat_along_checking_ray() {
+        let board = Board::from_fen("4r1k1/8/8/8/8/8/4K3/8 w - - 0 1").unwrap();
+        let moves = legal_destinations(&board, "e2".parse().unwrap());
+        assert!(!moves.contains(&"e1".parse().unwrap()));
+        assert!(!moves.contains(&"e3".parse().unwrap()));
+        assert!(moves.contains(&"d1".parse().unwrap()));
+    }
+
+    #[test]
+    fn pinned_piece_stays_on_pin_ray() {
+        // The rook on e4 is pinned by the rook on e8 and may only slide along the e-file
+        let board = Board::from_fen("4r1k1/8/8/8/4R3/8/8/4K3 w - - 0 1").unwrap();
+        let mut moves = legal_destinations(&board, "e4".parse().unwrap());
+        moves.sort_by_key(|coord| coord.y());
+        assert_eq!(
+            moves,
+            ["e2", "e3", "e5", "e6", "e7", "e8"].map(|s| s.parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn pinned_knight_cannot_move() {
+        let board = Board::from_fen("6k1/8/8/8/b7/8/2N5/3K4 w - - 0 1").unwrap();
+        assert!(legal_destinations(&board, "c2".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn check_can_be_blocked_or_captured() {
+        // The bishop on a5 checks along the diagonal, the knight may capture it or block on d2
+        let board = Board::from_fen("6k1/8/8/b7/8/1N6/8/4K3 w - - 0 1").unwrap();
+        let mut moves = legal_destinations(&board, "b3".parse().unwrap());
+        moves.sort_by_key(|coord| coord.x());
+        assert_eq!(moves, ["a5", "d2"].map(|s| s.parse().unwrap()));
+    }
+
+    #[test]
+    fn en_passant_exposing_king_on_rank_is_illegal() {
+        let board = Board::from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1").unwrap();
+        assert!(!board.get_legal_moves().iter().any(|mv| mv.is_en_passant()));
+    }
+
+    #[test]
+    fn en_passant_can_capture_checking_pawn() {
+        let board = Board::from_fen("8/8/8/3pP3/4K3/8/8/7k w - d6 0 1").unwrap();
+        assert!(board.get_legal_moves().iter().any(|mv| mv.is_en_passant()));
+    }
+
+    #[rstest]
+    #[case::both_sides_free("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", 2)]
+    #[case::no_rights("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1", 0)]
+    #[case::kingside_path_attacked("r3k2r/8/8/8/8/8/6r1/R3K2R w KQ - 0 1", 1)]
+    #[case::queenside_b_file_attacked("r3k2r/8/8/8/8/8/1r6/R3K2R w KQ - 0 1", 2)]
+    #[case::in_check("r3k2r/8/8/8/8/8/4r3/R3K2R w KQ - 0 1", 0)]
+    #[case::blocked("r3k2r/8/8/8/8/8/8/RN2K1NR w KQ - 0 1", 0)]
+    fn castling_moves(#[case] fen: &str, #[case] expected: usize) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(
+            board
+                .get_legal_moves()
+                .iter()
+                .filter(|mv| mv.is_castling())
+                .count(),
+            expected
+        );
+    }
+
+    #[test]
+    fn make_and_unmake_castling_and_promotion() {
+        let mut board = Board::from_fen("r3k3/1P6/8/8/8/8/8/R3K2R w KQq - 0 1").unwrap();
+        let original = board.clone();
+        let castle = *board
+            .get_legal_moves()
+            .iter()
+            .find(|mv| mv.is_castling() && mv.to().x() == 6)
+            .unwrap();
+        board.make_move(castle).unwrap();
+        assert_eq!(board.to_fen(), "r3k3/1P6/8/8/8/8/8/R4RK1 b q - 1 1");
+        board.unmake_move();
+        assert_eq!(board, original);
+
+        let promotion = *board
+            .get_legal_moves()
+            .iter()
+            .find(|mv| {
+                mv.to() == "a8".parse().unwrap()
+                    && mv.promotion() == Some(Piece::knight(Colour::White))
+            })
+            .unwrap();
+        board.make_move(promotion).unwrap();
+        assert_eq!(board.to_fen(), "N3k3/8/8/8/8/8/8/R3K2R b KQ - 0 1");
+        board.unmake_move();
+        assert_eq!(board, original);
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(Board::chess960(518).unwrap(), Board::new());
+        assert_eq!(
+            Board::chess960(0).unwrap().to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert!(Board::chess960(960).is_err());
+
+        let mut back_ranks = (0..960)
+            .map(|index| {
+                let board = Board::chess960(index).unwrap();
+                let rank = board.to_fen().split('/').next_back().unwrap()[..8].to_string();
+                let file = |piece| {
+                    rank.match_indices(piece)
+                        .map(|(x, _)| x)
+                        .collect::<Vec<_>>()
+                };
+                let (bishops, rooks, king) = (file("B"), file("R"), file("K")[0]);
+                assert_ne!(bishops[0] % 2, bishops[1] % 2, "{rank}");
+                assert!(rooks[0] < king && king < rooks[1], "{rank}");
+                assert_eq!(board.hash(), board.compute_hash());
+                rank
+            })
+            .collect::<Vec<_>>();
+        back_ranks.sort();
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), 960);
+    }
+
+    #[rstest]
+    #[case::classical("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")]
+    #[case::inner_rook("4k3/8/8/8/8/8/8/R1R1K2R w KC - 0 1")]
+    #[case::chess960("1r2k1r1/8/8/8/8/8/8/1R2K1R1 b KQkq - 0 1")]
+    fn x_fen_round_trip(#[case] fen: &str) {
+        assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+    }
+
+    #[test]
+    fn shredder_fen_names_rook_files() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1";
+        assert_eq!(Board::new().to_shredder_fen(), start);
+        assert_eq!(Board::from_fen(start).unwrap(), Board::new());
+        assert_eq!(
+            Board::from_fen("4k3/8/8/8/8/8/8/R1R1K2R w HC - 0 1").unwrap(),
+            Board::from_fen("4k3/8/8/8/8/8/8/R1R1K2R w KC - 0 1").unwrap()
+        );
+        // A file letter must name a square beside the king
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w E - 0 1").is_err());
+    }
+
+    #[rstest]
+    #[case::king_onto_rook_square(
+        "k7/8/8/8/8/8/8/5KR1 w K - 0 1",
+        "f1g1",
+        "k7/8/8/8/8/8/8/5RK1 b - - 1 1"
+    )]
+    #[case::king_stays_put(
+        "4k3/8/8/8/8/8/8/6KR w K - 0 1",
+        "g1h1",
+        "4k3/8/8/8/8/8/8/5RK1 b - - 1 1"
+    )]
+    #[case::rook_crosses_king(
+        "4k3/8/8/8/8/8/8/1R2K1R1 w KQ - 0 1",
+        "e1b1",
+        "4k3/8/8/8/8/8/8/2KR2R1 b - - 1 1"
+    )]
+    fn chess960_castling(#[case] fen: &str, #[case] notation: &str, #[case] expected: &str) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let original = board.clone();
+        let castle = board.parse_move(notation).unwrap();
+        assert!(castle.is_castling());
+        assert_eq!(castle.to_uci(true), notation);
+        assert_eq!(Move::from_bits(castle.to_bits()), Some(castle));
+        board.make_move(castle).unwrap();
+        assert_eq!(board.to_fen(), expected);
+        assert_eq!(board.hash(), board.compute_hash());
+        board.unmake_move();
+        assert_eq!(board, original);
+    }
+
+    #[rstest]
+    // Once the rook on b1 leaves, the rook on a1 attacks the king's destination
+    #[case::rook_shields_destination("4k3/8/8/8/8/8/8/rR4K1 w Q - 0 1", 0)]
+    #[case::unshielded("r3k3/8/8/8/8/8/8/1R4K1 w Q - 0 1", 1)]
+    #[case::rook_destination_occupied("4k3/8/8/8/8/8/8/1RN3K1 w Q - 0 1", 0)]
+    #[case::king_destination_occupied("4k3/8/8/8/8/8/8/4KRN1 w K - 0 1", 0)]
+    #[case::piece_beyond_king_path("4k3/8/8/8/8/8/8/R4BKR w KQ - 0 1", 0)]
+    fn chess960_castling_rules(#[case] fen: &str, #[case] expected: usize) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(
+            board
+                .get_legal_moves()
+                .iter()
+                .filter(|mv| mv.is_castling())
+                .count(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::checkmate(
+        "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+        Some(Outcome::win(Colour::Black, Termination::Checkmate))
+    )]
+    #[case::stalemate(
+        "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        Some(Outcome::draw(Termination::Stalemate))
+    )]
+    #[case::fifty_moves(
+        "7k/8/6K1/8/8/8/8/R7 b - - 100 80",
+        Some(Outcome::draw(Termination::FiftyMoveRule))
+    )]
+    // Mate takes precedence over the fifty move rule
+    #[case::mate_on_the_hundredth_ply(
+        "7k/6Q1/6K1/8/8/8/8/8 b - - 100 80",
+        Some(Outcome::win(Colour::White, Termination::Checkmate))
+    )]
+    #[case::bare_kings(
+        "7k/8/6K1/8/8/8/8/8 b - - 0 1",
+        Some(Outcome::draw(Termination::InsufficientMaterial))
+    )]
+    #[case::lone_knight(
+        "7k/8/6K1/8/8/8/8/6N1 b - - 0 1",
+        Some(Outcome::draw(Termination::InsufficientMaterial))
+    )]
+    #[case::bishops_on_one_colour(
+        "7k/8/6K1/8/8/2b5/8/B7 b - - 0 1",
+        Some(Outcome::draw(Termination::InsufficientMaterial))
+    )]
+    #[case::bishops_on_both_colours("7k/8/6K1/8/8/8/8/B2b4 b - - 0 1", None)]
+    #[case::knight_and_bishop("7k/8/6K1/8/8/8/8/BN6 b - - 0 1", None)]
+    #[case::in_progress(KIWIPETE, None)]
+    fn outcome(#[case] fen: &str, #[case] expected: Option<Outcome>) {
+        assert_eq!(Board::from_fen(fen).unwrap().outcome(), expected);
+    }
+
+    fn crazyhouse(fen: &str) -> Board {
+        Board::from_variant_fen(Variant::Crazyhouse, fen).unwrap()
+    }
+
+    #[rstest]
+    #[case::start("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1")]
+    #[case::pockets("r1bqkb1r/pppp1ppp/2n5/4p3/4P3/8/PPPP1PPP/RNBQKB1R[Nn] w KQkq - 0 4")]
+    #[case::promoted_queen("1Q~2k3/8/8/8/8/8/8/4K3[PPrp] b - - 0 1")]
+    fn crazyhouse_fen_round_trip(#[case] fen: &str) {
+        assert_eq!(crazyhouse(fen).to_fen(), fen);
+    }
+
+    #[rstest]
+    #[case::standard_with_pockets(Variant::Standard, "4k3/8/8/8/8/8/8/4K3[N] w - - 0 1")]
+    #[case::king_in_pocket(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K3[K] w - - 0 1")]
+    #[case::unclosed_pocket(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K3[N w - - 0 1")]
+    #[case::marker_without_piece(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/~4K3[] w - - 0 1")]
+    #[case::standard_promoted(Variant::Standard, "4k3/8/8/8/8/8/8/Q~3K3 w - - 0 1")]
+    fn invalid_crazyhouse_fen(#[case] variant: Variant, #[case] fen: &str) {
+        assert!(Board::from_variant_fen(variant, fen).is_err());
+    }
+
+    #[rstest]
+    // Anywhere empty, 62 squares
+    #[case::knight("4k3/8/8/8/8/8/8/4K3[N] w - - 0 1", 62)]
+    // Never on the first or last rank
+    #[case::pawn("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1", 48)]
+    // Only between the rook and the king
+    #[case::blocking_check("4k3/8/8/8/8/8/8/r3K3[Q] w - - 0 1", 3)]
+    #[case::contact_check("4k3/8/8/8/8/8/3q4/4K3[Q] w - - 0 1", 0)]
+    #[case::opponents_pocket("4k3/8/8/8/8/8/8/4K3[n] w - - 0 1", 0)]
+    fn drops(#[case] fen: &str, #[case] expected: usize) {
+        let board = crazyhouse(fen);
+        let drops = board.get_legal_moves().into_iter().filter(Move::is_drop);
+        assert_eq!(drops.count(), expected);
+    }
+
+    #[test]
+    fn captures_fill_the_pocket() {
+        let mut board = crazyhouse("4k3/8/8/3n4/4P3/8/8/q~2RK3[] w - - 0 1");
+        let original = board.clone();
+        board.make_move(board.parse_move("e4d5").unwrap()).unwrap();
+        assert_eq!(board.pocket(Colour::White).count(PieceType::Knight), 1);
+        board.unmake_move();
+        assert_eq!(board, original);
+
+        // A promoted piece goes back to being a pawn
+        board.make_move(board.parse_move("d1a1").unwrap()).unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/3n4/4P3/8/8/R3K3[P] b - - 0 1");
+        assert_eq!(board.hash(), board.compute_hash());
+        board.unmake_move();
+        assert_eq!(board, original);
+    }
+
+    #[test]
+    fn drops_and_promotions() {
+        let mut board = crazyhouse("4k3/1P6/8/8/8/8/8/4K3[Nq] w - - 0 1");
+        let drop = board.parse_move("N@f3").unwrap();
+        assert_eq!(
+            (drop.from(), drop.to().to_string()),
+            (None, "f3".to_string())
+        );
+        assert_eq!(Move::from_bits(drop.to_bits()), Some(drop));
+        board.make_move(drop).unwrap();
+        assert_eq!(board.to_fen(), "4k3/1P6/8/8/8/5N2/8/4K3[q] b - - 1 1");
+        assert_eq!(board.hash(), board.compute_hash());
+        board.make_move(board.parse_move("Q@e2").unwrap()).unwrap();
+        board.make_move(board.parse_move("e1e2").unwrap()).unwrap();
+        board.make_move(board.parse_move("e8d7").unwrap()).unwrap();
+        board.make_move(board.parse_move("b7b8q").unwrap()).unwrap();
+        assert_eq!(board.to_fen(), "1Q~6/3k4/8/8/8/5N2/4K3/8[Q] b - - 0 3");
+        for _ in 0..5 {
+            board.unmake_move();
+        }
+        assert_eq!(board, crazyhouse("4k3/1P6/8/8/8/8/8/4K3[Nq] w - - 0 1"));
+    }
+
+    #[test]
+    fn crazyhouse_hash_is_updated_incrementally() {
+        let mut board =
+            crazyhouse("r1bqkb1r/pppp1ppp/2n5/4p3/4P3/8/PPPP1PPP/RNBQKB1R[Nn] w KQkq - 0 4");
+        for mv in board.get_legal_moves() {
+            board.make_move_unchecked(mv);
+            assert_eq!(board.hash(), board.compute_hash(), "after {mv}");
+            board.unmake_move();
+        }
+        let with_knight = board.hash();
+        let without =
+            crazyhouse("r1bqkb1r/pppp1ppp/2n5/4p3/4P3/8/PPPP1PPP/RNBQKB1R[n] w KQkq - 0 4");
+        assert_ne!(with_knight, without.hash());
+    }
+
+    #[rstest]
+    #[case::start("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0")]
+    #[case::checks_given("rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 2 3 +2+1")]
+    fn three_check_fen_round_trip(#[case] fen: &str) {
+        let board = Board::from_variant_fen(Variant::ThreeCheck, fen).unwrap();
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[rstest]
+    #[case::standard_with_checks(Variant::Standard, "4k3/8/8/8/8/8/8/4K3 w - - 0 1 +0+0")]
+    #[case::no_plus(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K3 w - - 0 1 2+1")]
+    #[case::too_many_checks(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K3 w - - 0 1 +4+0")]
+    fn invalid_three_check_fen(#[case] variant: Variant, #[case] fen: &str) {
+        assert!(Board::from_variant_fen(variant, fen).is_err());
+    }
+
+    #[test]
+    fn checks_are_counted() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w - - 0 1 +2+0";
+        let mut board = Board::from_variant_fen(Variant::ThreeCheck, fen).unwrap();
+        let original = board.clone();
+        board.make_move(board.parse_move("h1h7").unwrap()).unwrap();
+        assert_eq!(board.checks_given(Colour::White), 2);
+        assert_eq!(board.outcome(), None);
+        board.unmake_move();
+
+        board.make_move(board.parse_move("h1h8").unwrap()).unwrap();
+        assert_eq!(board.to_fen(), "4k2R/8/8/8/8/8/8/4K3 b - - 1 1 +3+0");
+        assert_eq!(board.hash(), board.compute_hash());
+        assert_eq!(
+            board.outcome(),
+            Some(Outcome::win(Colour::White, Termination::ThreeChecks))
+        );
+        board.unmake_move();
+        assert_eq!(board, original);
+    }
+
+    #[test]
+    fn checks_stop_counting_at_three() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w - - 0 1 +3+0";
+        let mut board = Board::from_variant_fen(Variant::ThreeCheck, fen).unwrap();
+        board.make_move_unchecked(board.parse_move("h1h8").unwrap());
+        assert_eq!(board.checks_given(Colour::White), 3);
+        assert_eq!(board.to_fen(), "4k2R/8/8/8/8/8/8/4K3 b - - 1 1 +3+0");
+        assert_eq!(board.hash(), board.compute_hash());
+    }
+
+    #[rstest]
+    #[case::on_the_hill("8/7p/8/8/4K3/8/8/k7 b - - 0 1", Some(Colour::White))]
+    #[case::black_on_the_hill("8/8/8/3k4/8/8/7P/K7 w - - 0 1", Some(Colour::Black))]
+    #[case::next_to_the_hill("8/8/8/8/8/4K3/7P/k7 w - - 0 1", None)]
+    fn king_of_the_hill(#[case] fen: &str, #[case] winner: Option<Colour>) {
+        let board = Board::from_variant_fen(Variant::KingOfTheHill, fen).unwrap();
+        assert_eq!(
+            board.outcome(),
+            winner.map(|winner| Outcome::win(winner, Termination::KingOfTheHill))
+        );
+        // Only a rule of the variant
+        assert_eq!(Board::from_fen(fen).unwrap().outcome(), None);
+    }
+
+    fn atomic(fen: &str) -> Board {
+        Board::from_variant_fen(Variant::Atomic, fen).unwrap()
+    }
+
+    #[rstest]
+    #[case::start(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        vec![20, 400, 8902]
+    )]
+    #[case::programfox1(
+        "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+        vec![40, 1238, 45237]
+    )]
+    #[case::programfox2(
+        "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+        vec![28, 833, 23353]
+    )]
+    fn atomic_perft(#[case] fen: &str, #[case] expected: Vec<u64>) {
+        let mut board = atomic(fen);
+        for (depth, nodes) in (1..).zip(expected) {
+            assert_eq!(board.perft(depth), nodes, "depth {depth}");
+        }
+        assert_eq!(board, atomic(fen));
+    }
+
+    #[test]
+    fn atomic_captures_explode() {
+        let fen = "r3k3/pp6/2n5/1N6/8/8/8/4K3 w q - 0 1";
+        let mut board = atomic(fen);
+        board.make_move(board.parse_move("b5a7").unwrap()).unwrap();
+        // The knight, the pawn it took and the rook beside them are gone, the b7 pawn survives
+        assert_eq!(board.to_fen(), "4k3/1p6/2n5/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(board.hash(), board.compute_hash());
+        board.unmake_move();
+        assert_eq!(board, atomic(fen));
+    }
+
+    #[rstest]
+    #[case::king_cannot_capture("4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1", "e1d2", false)]
+    #[case::blowing_up_own_king("4k3/8/8/8/8/8/3n4/3RK3 w - - 0 1", "d1d2", false)]
+    // Rxd3 would blow up Black's own king along with White's
+    #[case::touching_kings_cannot_check("8/8/8/8/3k4/r7/3K4/8 w - - 0 1", "d2d3", true)]
+    #[case::exploding_the_king_beats_check("k3r3/1p6/8/8/8/8/6B1/4K3 w - - 0 1", "g2b7", true)]
+    #[case::ignoring_check("k3r3/1p6/8/8/8/8/6B1/4K3 w - - 0 1", "g2f3", false)]
+    fn atomic_legality(#[case] fen: &str, #[case] notation: &str, #[case] legal: bool) {
+        assert_eq!(atomic(fen).parse_move(notation).is_ok(), legal);
+    }
+
+    #[test]
+    fn atomic_outcome() {
+        let mut board = atomic("k3r3/1p6/8/8/8/8/6B1/4K3 w - - 0 1");
+        assert!(board.in_check());
+        board.make_move(board.parse_move("g2b7").unwrap()).unwrap();
+        assert_eq!(
+            board.outcome(),
+            Some(Outcome::win(Colour::White, Termination::Explosion))
+        );
+        assert!(board.get_legal_moves().is_empty());
+        assert!(!atomic("8/8/8/8/3k4/3K4/8/3r4 w - - 0 1").in_check());
+        assert!(Board::from_fen("8/8/8/8/3k4/3K4/8/3r4 w - - 0 1").is_err());
+    }
+
+    fn antichess(fen: &str) -> Board {
+        Board::from_variant_fen(Variant::Antichess, fen).unwrap()
+    }
+
+    #[rstest]
+    #[case::start(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        vec![20, 400, 8067]
+    )]
+    #[case::pawn_race("8/1p6/8/8/8/8/P7/8 w - - 0 1", vec![2, 4, 4, 3, 1, 0])]
+    fn antichess_perft(#[case] fen: &str, #[case] expected: Vec<u64>) {
+        let mut board = antichess(fen);
+        for (depth, nodes) in (1..).zip(expected) {
+            assert_eq!(board.perft(depth), nodes, "depth {depth}");
+        }
+        assert_eq!(board, antichess(fen));
+    }
+
+    #[rstest]
+    // Only Kxd2 and exd3 capture
+    #[case::captures_are_compulsory("8/8/8/8/8/3p4/3pP3/4K3 w - - 0 1", &["e1d2", "e2d3"])]
+    // The king may walk into attack
+    #[case::no_check("8/8/8/8/8/8/r7/4K3 w - - 0 1", &["e1d1", "e1f1", "e1d2", "e1e2", "e1f2"])]
+    #[case::promotion_to_king(
+        "8/P7/8/8/8/8/8/8 w - - 0 1",
+        &["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7a8k"]
+    )]
+    fn antichess_moves(#[case] fen: &str, #[case] expected: &[&str]) {
+        let mut moves: Vec<_> = antichess(fen)
+            .get_legal_moves()
+            .iter()
+            .map(Move::to_string)
+            .collect();
+        moves.sort();
+        let mut expected = expected.to_vec();
+        expected.sort();
+        assert_eq!(moves, expected);
+    }
+
+    #[rstest]
+    #[case::no_pieces_left(
+        "8/8/8/8/8/8/8/r7 w - - 0 1",
+        Some(Outcome::win(Colour::White, Termination::NoPiecesLeft))
+    )]
+    // The pawn is blocked
+    #[case::stalemate(
+        "8/8/8/8/8/p7/P7/8 w - - 0 1",
+        Some(Outcome::win(Colour::White, Termination::Stalemate))
+    )]
+    #[case::in_progress("8/8/8/8/8/8/8/rK6 w - - 0 1", None)]
+    fn antichess_outcome(#[case] fen: &str, #[case] expected: Option<Outcome>) {
+        assert_eq!(antichess(fen).outcome(), expected);
+    }
+
+    #[test]
+    fn antichess_fen() {
+        // Any number of kings, or none, but no castling
+        for fen in ["8/8/8/8/8/8/8/KK5k w - - 0 1", "8/8/8/8/8/8/8/R7 b - - 0 1"] {
+            assert_eq!(antichess(fen).to_fen(), fen);
+        }
+        assert!(
+            Board::from_variant_fen(Variant::Antichess, "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1")
+                .is_err()
+        );
+    }
+
+    #[rstest]
+    #[case::horde_start(
+        Variant::Horde,
+        "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+        vec![8, 128, 1274, 23310]
+    )]
+    #[case::horde_open_flank(
+        Variant::Horde,
+        "4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1",
+        vec![30, 241, 6633]
+    )]
+    #[case::horde_en_passant(
+        Variant::Horde,
+        "k7/5p2/4p2P/3p2P1/2p2P2/1p2P2P/p2P2P1/2P2P2 w - - 0 1",
+        vec![13, 172, 2205]
+    )]
+    #[case::racing_kings_start(
+        Variant::RacingKings,
+        "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+        vec![21, 421, 11264]
+    )]
+    fn variant_perft(#[case] variant: Variant, #[case] fen: &str, #[case] expected: Vec<u64>) {
+        let mut board = Board::from_variant_fen(variant, fen).unwrap();
+        for (depth, nodes) in (1..).zip(expected) {
+            assert_eq!(board.perft(depth), nodes, "depth {depth}");
+        }
+        assert_eq!(board, Board::from_variant_fen(variant, fen).unwrap());
+    }
+
+    #[test]
+    fn horde_pawns_double_push_from_the_first_rank() {
+        let mut board =
+            Board::from_variant_fen(Variant::Horde, "4k3/8/8/8/8/1p6/8/P7 w - - 0 1").unwrap();
+        board.make_move(board.parse_move("a1a3").unwrap()).unwrap();
+        // But cannot be taken en passant
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/Pp6/8/8 b - - 0 1");
+        assert!(board.parse_move("b3a2").is_err());
+
+        let mut board =
+            Board::from_variant_fen(Variant::Horde, "4k3/8/8/8/8/1p6/P7/8 b - - 0 1").unwrap();
+        board.make_move(board.parse_move("b3a2").unwrap()).unwrap();
+        assert_eq!(
+            board.outcome(),
+            Some(Outcome::win(Colour::Black, Termination::NoPiecesLeft))
+        );
+        // Only White's pawns, and only in horde
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/P3K3 w - - 0 1").is_err());
+        assert!(Board::from_variant_fen(Variant::Horde, "4k3/8/8/8/8/8/8/p7 w - - 0 1").is_err());
+    }
+
+    #[rstest]
+    #[case::white_finishes("3K4/8/8/8/8/8/k7/8 w - - 0 1", Some(Some(Colour::White)))]
+    #[case::black_can_draw_level("3K4/k7/8/8/8/8/8/8 b - - 0 1", None)]
+    #[case::black_one_move_short("3K4/8/8/8/8/8/k7/8 b - - 0 1", Some(Some(Colour::White)))]
+    #[case::both_finish("3K1k2/8/8/8/8/8/8/8 w - - 0 1", Some(None))]
+    #[case::black_finishes("5k2/8/8/8/8/8/8/K7 w - - 0 1", Some(Some(Colour::Black)))]
+    #[case::in_progress("8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", None)]
+    fn racing_kings_outcome(#[case] fen: &str, #[case] winner: Option<Option<Colour>>) {
+        let board = Board::from_variant_fen(Variant::RacingKings, fen).unwrap();
+        let expected = winner.map(|winner| match winner {
+            Some(winner) => Outcome::win(winner, Termination::EighthRank),
+            None => Outcome::draw(Termination::EighthRank),
+        });
+        assert_eq!(board.outcome(), expected);
+    }
+
+    #[test]
+    fn racing_kings_forbids_checks() {
+        let board = Board::from_variant_fen(Variant::RacingKings, "8/8/8/k7/8/8/8/1R4K1 w - - 0 1")
+            .unwrap();
+        assert!(board.parse_move("b1a1").is_err());
+        assert!(board.parse_move("b1b5").is_err());
+        assert!(board.parse_move("b1b4").is_ok());
+        // Nor may a position with a king in check be set up
+        assert!(
+            Board::from_variant_fen(Variant::RacingKings, "8/8/8/k7/8/8/8/R5K1 b - - 0 1").is_err()
+        );
+    }
+
+    #[test]
+    fn make_move_rejects_illegal_moves() {
+        let mut board = Board::default();
+        let illegal = Move::new(
+            Piece::rook(Colour::White),
+            Coordinate::new_unchecked(0, 0),
+            Coordinate::new_unchecked(0, 4),
+            None,
+        );
+        assert_eq!(board.make_move(illegal), Err("Illegal move"));
+    }
+
+    #[test]
+    fn hash_is_updated_incrementally() {
+        fn walk(board: &mut Board, depth: u32) {
+            if depth == 0 {
+                return;
+            }
+            let before = board.hash();
+            for mv in board.get_legal_moves() {
+                board.make_move_unchecked(mv);
+                assert_eq!(board.hash(), board.compute_hash(), "after {mv:?}");
+                walk(board, depth - 1);
+                board.unmake_move();
+                assert_eq!(board.hash(), before);
+            }
+        }
+        walk(&mut Board::from_fen(KIWIPETE).unwrap(), 2);
+        walk(
+            &mut Board::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap(),
+            2,
+        );
+    }
+
+    #[test]
+    fn null_move_passes_the_turn() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        let mut board = Board::from_fen(fen).unwrap();
+        let undo = board.make_null_move();
+        assert_eq!(board.turn(), Colour::Black);
+        assert_eq!(board.en_passant(), None);
+        assert_eq!(board.hash(), board.compute_hash());
+        board.unmake_null_move(undo);
+        assert_eq!(board, Board::from_fen(fen).unwrap());
+    }
+
+    #[test]
+    fn transpositions_hash_equally() {
+        let play = |moves: [(&str, &str); 4]| {
+            let mut board = Board::default();
+            for (from, to) in moves {
+                let mv = *board
+                    .get_legal_moves()
+                    .iter()
+                    .find(|mv| {
+                        mv.from() == Some(from.parse().unwrap()) && mv.to() == to.parse().unwrap()
+                    })
+                    .unwrap();
+                board.make_move(mv).unwrap();
+            }
+            board
+        };
+        let a = play([("g1", "f3"), ("g8", "f6"), ("b1", "c3"), ("b8", "c6")]);
+        let b = play([("b1", "c3"), ("b8", "c6"), ("g1", "f3"), ("g8", "f6")]);
+        assert_eq!(a.hash(), b.hash());
+        assert_ne!(a.hash(), Board::default().hash());
+        assert_eq!(
+            Board::default().hash(),
+            Board::from_fen(&Board::default().to_fen()).unwrap().hash()
+        );
+    }
+
+    fn find_move(board: &Board, from: &str, to: &str) -> Move {
+        *board
+            .get_legal_moves()
+            .iter()
+            .find(|mv| mv.from() == Some(from.parse().unwrap()) && mv.to() == to.parse().unwrap())
+            .unwrap()
+    }
+
+    const PAWN: i32 = eval::piece_value(PieceType::Pawn);
+    const KNIGHT: i32 = eval::piece_value(PieceType::Knight);
+    const BISHOP: i32 = eval::piece_value(PieceType::Bishop);
+    const ROOK: i32 = eval::piece_value(PieceType::Rook);
+
+    #[rstest]
+    #[case::undefended_knight("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", "e4", "d5", KNIGHT)]
+    #[case::defended_pawn_by_rook("4k3/2p5/3p4/8/8/8/8/3RK3 w - - 0 1", "d1", "d6", PAWN - ROOK)]
+    #[case::rook_xray_behind_rook("3r2k1/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2", "d5", PAWN)]
+    #[case::defender_xray_behind_defender(
+        "3r2k1/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1",
+        "d2",
+        "d5",
+        PAWN - ROOK
+    )]
+    #[case::queen_xray_behind_bishop(
+        "6k1/8/5n2/3p4/8/1B6/Q7/4K3 w - - 0 1",
+        "b3",
+        "d5",
+        PAWN - BISHOP + KNIGHT
+    )]
+    #[case::knight_takes_defended_bishop(
+        "6k1/8/2p5/3b4/8/4N3/8/4K3 w - - 0 1",
+        "e3",
+        "d5",
+        BISHOP - KNIGHT
+    )]
+    #[case::king_cannot_recapture_defended("6k1/8/8/8/b2q4/8/8/3RK3 b - - 0 1", "d4", "d1", ROOK)]
+    #[case::quiet_move("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1", "a5", 0)]
+    fn static_exchange_evaluation(
+        #[case] fen: &str,
+        #[case] from: &str,
+        #[case] to: &str,
+        #[case] expected: i32,
+    ) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.see(&find_move(&board, from, to)), expected);
+    }
 }
@@ -0,0 +1,450 @@
+//! Polyglot opening books: files of 16-byte entries sorted by a Zobrist key of the position,
+//! each giving one book move and how often it should be played.
+
+mod builder;
+mod random;
+
+pub use builder::{BookBuilder, MoveStats};
+
+use crate::board::{Board, Colour, Coordinate, Move};
+use crate::piece::{Piece, PieceType};
+use random::RANDOM64;
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+/// The Polyglot key of `board`, which differs from `Board::hash` as Polyglot fixes its own
+/// random numbers. The en passant file only counts when a pawn could actually capture there.
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for (coord, piece) in board.into_iter().flatten() {
+        let kind = match piece.piece_type {
+            PieceType::Pawn => 0,
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook => 3,
+            PieceType::Queen => 4,
+            PieceType::King => 5,
+        };
+        let kind = kind * 2 + usize::from(piece.colour == Colour::White);
+        key ^= RANDOM64[kind * 64 + coord.index()];
+    }
+
+    let rights = [
+        (Colour::White, true),
+        (Colour::White, false),
+        (Colour::Black, true),
+        (Colour::Black, false),
+    ];
+    for (offset, (colour, kingside)) in rights.into_iter().enumerate() {
+        if board.can_castle(colour, kingside) {
+            key ^= RANDOM64[CASTLING_OFFSET + offset];
+        }
+    }
+
+    if let Some(target) = board.en_passant() {
+        let turn = board.turn();
+        // The capturing pawns stand beside the pawn that just moved two squares.
+        let rank = match turn {
+            Colour::White => target.y() - 1,
+            Colour::Black => target.y() + 1,
+        };
+        let can_capture = [-1, 1].into_iter().any(|dx: i8| {
+            let file = target.x() as i8 + dx;
+            (0..8).contains(&file)
+                && board.get_square(&Coordinate::new_unchecked(file as u8, rank))
+                    == Some(Piece::pawn(turn))
+        });
+        if can_capture {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + target.x() as usize];
+        }
+    }
+
+    if board.turn() == Colour::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+/// Encodes `mv` as Polyglot does: the destination square in the low six bits, then the origin
+/// square, then the promotion piece. Castling is written as the king capturing its own rook.
+pub fn encode_move(mv: &Move) -> u16 {
+    let to = mv.castling_rook().unwrap_or(mv.to());
+    let promotion = match mv.promotion().map(|piece| piece.piece_type) {
+        None => 0,
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(_) => 4,
+    };
+    // Polyglot has no notation for crazyhouse drops, they are written as a null move to `to`.
+    let from = mv.from().unwrap_or(to);
+    (to.index() | from.index() << 6 | promotion << 12) as u16
+}
+
+/// The legal move in `board` that Polyglot encodes as `raw`, if any.
+pub fn decode_move(board: &Board, raw: u16) -> Option<Move> {
+    board
+        .get_legal_moves()
+        .into_iter()
+        .find(|mv| encode_move(mv) == raw)
+}
+
+/// One 16-byte record of a Polyglot book, stored big-endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// The move, as encoded by `encode_move`.
+    pub mv: u16,
+    pub weight: u16,
+    /// Unused by most tools, kept for round trips.
+    pub learn: u32,
+}
+
+impl BookEntry {
+    pub const SIZE: usize = 16;
+
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        let [
+            k0,
+            k1,
+            k2,
+            k3,
+            k4,
+            k5,
+            k6,
+            k7,
+            m0,
+            m1,
+            w0,
+            w1,
+            l0,
+            l1,
+            l2,
+            l3,
+        ] = bytes;
+        Self {
+            key: u64::from_be_bytes([k0, k1, k2, k3, k4, k5, k6, k7]),
+            mv: u16::from_be_bytes([m0, m1]),
+            weight: u16::from_be_bytes([w0, w1]),
+            learn: u32::from_be_bytes([l0, l1, l2, l3]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+/// A book move for a position, decoded against it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookMove {
+    pub mv: Move,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+/// How to pick among the book moves for a position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Selection {
+    /// At random, each move as likely as its share of the total weight.
+    #[default]
+    WeightedRandom,
+    /// Always the move with the highest weight.
+    Best,
+}
+
+/// A Polyglot book, read from a file or anything else seekable. Entries are looked up by
+/// binary search, so the book is never read into memory.
+#[derive(Debug)]
+pub struct Book<R> {
+    reader: R,
+    len: u64,
+}
+
+impl Book<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> Book<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let bytes = reader.seek(SeekFrom::End(0))?;
+        if bytes % BookEntry::SIZE as u64 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Polyglot book size must be a multiple of 16 bytes",
+            ));
+        }
+        Ok(Self {
+            reader,
+            len: bytes / BookEntry::SIZE as u64,
+        })
+    }
+
+    /// The number of entries in the book.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn entry(&mut self, index: u64) -> io::Result<BookEntry> {
+        self.reader
+            .seek(SeekFrom::Start(index * BookEntry::SIZE as u64))?;
+        let mut bytes = [0; BookEntry::SIZE];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(BookEntry::from_bytes(bytes))
+    }
+
+    /// Every entry for `key`, in book order.
+    pub fn entries(&mut self, key: u64) -> io::Result<Vec<BookEntry>> {
+        // Binary search for the first entry with the key.
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.entry(middle)?.key < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let mut entries = Vec::new();
+        for index in low..self.len {
+            let entry = self.entry(index)?;
+            if entry.key != key {
+                break;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// The book moves for `board`, skipping any that are not legal there, as can happen with a
+    /// key collision.
+    pub fn moves(&mut self, board: &Board) -> io::Result<Vec<BookMove>> {
+        Ok(self
+            .entries(polyglot_key(board))?
+            .into_iter()
+            .filter_map(|entry| {
+                Some(BookMove {
+                    mv: decode_move(board, entry.mv)?,
+                    weight: entry.weight,
+                    learn: entry.learn,
+                })
+            })
+            .collect())
+    }
+
+    /// A book move to play in `board`, `None` when out of book.
+    pub fn choose_move(&mut self, board: &Board, selection: Selection) -> io::Result<Option<Move>> {
+        let moves = self.moves(board)?;
+        // Hashers are seeded randomly, which is random enough to vary the opening.
+        let roll = RandomState::new().build_hasher().finish();
+        Ok(choose(&moves, selection, roll))
+    }
+}
+
+/// Picks from `moves`, using `roll` as the random number for a weighted choice. Moves with zero
+/// weight are never chosen, unless every move has zero weight.
+fn choose(moves: &[BookMove], selection: Selection, roll: u64) -> Option<Move> {
+    let total: u64 = moves
+        .iter()
+        .map(|book_move| u64::from(book_move.weight))
+        .sum();
+    if selection == Selection::Best || total == 0 {
+        // The first of equally weighted moves, as `max_by_key` would give the last.
+        let best = moves.iter().map(|book_move| book_move.weight).max()?;
+        return moves
+            .iter()
+            .find(|book_move| book_move.weight == best)
+            .map(|book_move| book_move.mv);
+    }
+
+    let mut roll = roll % total;
+    for book_move in moves {
+        let weight = u64::from(book_move.weight);
+        if roll < weight {
+            return Some(book_move.mv);
+        }
+        roll -= weight;
+    }
+    unreachable!("the roll is less than the total weight")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::io::Cursor;
+
+    // Reference keys from the Polyglot book format specification.
+    #[rstest]
+    #[case(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0x463b_9618_1691_fc9c
+    )]
+    #[case(
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        0x823c_9b50_fd11_4196
+    )]
+    #[case(
+        "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        0x0756_b944_61c5_0fb0
+    )]
+    #[case(
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+        0x662f_afb9_65db_29d4
+    )]
+    #[case(
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        0x22a4_8b5a_8e47_ff78
+    )]
+    #[case(
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+        0x652a_607c_a3f2_42c1
+    )]
+    #[case(
+        "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+        0x00fd_d303_c946_bdd9
+    )]
+    #[case(
+        "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+        0x3c81_23ea_7b06_7637
+    )]
+    #[case(
+        "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+        0x5c3f_9b82_9b27_9560
+    )]
+    fn reference_keys(#[case] fen: &str, #[case] key: u64) {
+        assert_eq!(polyglot_key(&Board::from_fen(fen).unwrap()), key);
+    }
+
+    #[rstest]
+    #[case::quiet(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "g1f3",
+        0x0195
+    )]
+    #[case::white_kingside("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1", 0x0107)]
+    #[case::white_queenside("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1c1", 0x0100)]
+    #[case::black_kingside("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1", "e8g8", 0x0f3f)]
+    #[case::promotion("8/P3k3/8/8/8/8/8/4K3 w - - 0 1", "a7a8n", 0x1c38)]
+    #[case::queen_promotion("8/P3k3/8/8/8/8/8/4K3 w - - 0 1", "a7a8q", 0x4c38)]
+    fn move_encoding(#[case] fen: &str, #[case] uci: &str, #[case] raw: u16) {
+        let board = Board::from_fen(fen).unwrap();
+        let mv = board.parse_move(uci).unwrap();
+        assert_eq!(encode_move(&mv), raw);
+        assert_eq!(decode_move(&board, raw), Some(mv));
+    }
+
+    fn book(entries: &[BookEntry]) -> Book<Cursor<Vec<u8>>> {
+        let bytes = entries.iter().flat_map(BookEntry::to_bytes).collect();
+        Book::new(Cursor::new(bytes)).unwrap()
+    }
+
+    fn entry(board: &Board, uci: &str, weight: u16) -> BookEntry {
+        BookEntry {
+            key: polyglot_key(board),
+            mv: encode_move(&board.parse_move(uci).unwrap()),
+            weight,
+            learn: 0,
+        }
+    }
+
+    #[test]
+    fn finds_every_move_for_a_position() {
+        let start = Board::new();
+        let mut after_e4 = Board::new();
+        after_e4
+            .make_move(after_e4.parse_move("e2e4").unwrap())
+            .unwrap();
+        let mut entries = vec![
+            entry(&start, "e2e4", 10),
+            entry(&start, "d2d4", 30),
+            entry(&start, "c2c4", 0),
+            entry(&after_e4, "c7c5", 5),
+            BookEntry {
+                key: 1,
+                mv: 0,
+                weight: 1,
+                learn: 0,
+            },
+        ];
+        entries.sort_by_key(|entry| entry.key);
+        let mut book = book(&entries);
+        assert_eq!(book.len(), 5);
+
+        let moves: Vec<_> = book
+            .moves(&start)
+            .unwrap()
+            .iter()
+            .map(|book_move| (book_move.mv.to_string(), book_move.weight))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                ("e2e4".to_string(), 10),
+                ("d2d4".to_string(), 30),
+                ("c2c4".to_string(), 0)
+            ]
+        );
+        assert_eq!(
+            book.choose_move(&start, Selection::Best).unwrap(),
+            start.parse_move("d2d4").ok()
+        );
+        let reply = book.choose_move(&after_e4, Selection::WeightedRandom);
+        assert_eq!(reply.unwrap().unwrap().to_string(), "c7c5");
+
+        let out_of_book = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(book.moves(&out_of_book).unwrap(), []);
+        assert_eq!(
+            book.choose_move(&out_of_book, Selection::Best).unwrap(),
+            None
+        );
+    }
+
+    #[rstest]
+    #[case(0, Some("e2e4"))]
+    #[case(9, Some("e2e4"))]
+    #[case(10, Some("d2d4"))]
+    #[case(39, Some("d2d4"))]
+    #[case(40, Some("e2e4"))]
+    fn weighted_choice(#[case] roll: u64, #[case] expected: Option<&str>) {
+        let board = Board::new();
+        let book_move = |uci: &str, weight| BookMove {
+            mv: board.parse_move(uci).unwrap(),
+            weight,
+            learn: 0,
+        };
+        let moves = [
+            book_move("e2e4", 10),
+            book_move("c2c4", 0),
+            book_move("d2d4", 30),
+        ];
+        let chosen = choose(&moves, Selection::WeightedRandom, roll);
+        assert_eq!(chosen.map(|mv| mv.to_string()).as_deref(), expected);
+        assert_eq!(choose(&[], Selection::WeightedRandom, roll), None);
+    }
+
+    #[test]
+    fn rejects_truncated_books() {
+        assert!(Book::new(Cursor::new(vec![0; 20])).is_err());
+        assert!(Book::new(Cursor::new(Vec::new())).unwrap().is_empty());
+    }
+}
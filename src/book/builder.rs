@@ -0,0 +1,336 @@
+//! Building Polyglot books from games.
+
+use super::{BookEntry, encode_move, polyglot_key};
+use crate::board::{Board, Colour, Move};
+use crate::game::{self, Game, GameResult};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// How often a move was played in a position, and how those games ended for the side that
+/// played it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Including games with an unknown result.
+    pub games: u32,
+}
+
+impl MoveStats {
+    /// Two points for a win and one for a draw, as Polyglot weights its own books. A move that
+    /// only ever lost is never played from the book.
+    pub fn score(&self) -> u64 {
+        2 * u64::from(self.wins) + u64::from(self.draws)
+    }
+}
+
+/// Accumulates the moves of many games into a Polyglot book.
+#[derive(Clone, Debug)]
+pub struct BookBuilder {
+    max_ply: u32,
+    colour: Option<Colour>,
+    /// Move statistics by Polyglot key, then by encoded move.
+    positions: HashMap<u64, HashMap<u16, MoveStats>>,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BookBuilder {
+    pub const DEFAULT_MAX_PLY: u32 = 20;
+
+    pub fn new() -> Self {
+        Self {
+            max_ply: Self::DEFAULT_MAX_PLY,
+            colour: None,
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Only the first `plies` moves of each game are added.
+    pub fn set_max_ply(&mut self, plies: u32) {
+        self.max_ply = plies;
+    }
+
+    /// Only adds the moves of one side, for a repertoire book. `None` adds both.
+    pub fn set_colour(&mut self, colour: Option<Colour>) {
+        self.colour = colour;
+    }
+
+    /// The number of distinct positions added so far.
+    pub fn positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The statistics for `mv` in `board`, if it has been added.
+    pub fn stats(&self, board: &Board, mv: &Move) -> Option<MoveStats> {
+        self.positions
+            .get(&polyglot_key(board))?
+            .get(&encode_move(mv))
+            .copied()
+    }
+
+    /// Replays a game from `start`, adding each of its moves up to the ply limit. Nothing is
+    /// added if one of those moves is illegal.
+    pub fn add_game(
+        &mut self,
+        start: &Board,
+        moves: &[Move],
+        result: GameResult,
+    ) -> Result<(), &'static str> {
+        let mut board = start.clone();
+        let mut played = Vec::new();
+        for &mv in moves.iter().take(self.max_ply as usize) {
+            let key = polyglot_key(&board);
+            let mover = board.turn();
+            board.make_move(mv)?;
+            if self.colour.is_none_or(|colour| colour == mover) {
+                played.push((key, encode_move(&mv), mover));
+            }
+        }
+
+        for (key, mv, mover) in played {
+            let stats = self
+                .positions
+                .entry(key)
+                .or_default()
+                .entry(mv)
+                .or_default();
+            stats.games += 1;
+            match result {
+                GameResult::Draw => stats.draws += 1,
+                GameResult::Unknown => {}
+                result if result.winner() == Some(mover) => stats.wins += 1,
+                _ => stats.losses += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds every game of a PGN collection, with the result of each taken from its `Result`
+    /// tag or movetext, and returns how many were added. Games that cannot be read, such as
+    /// those with an illegal move, are skipped.
+    pub fn add_pgn(&mut self, pgn: &str) -> usize {
+        let mut added = 0;
+        for text in game::split_games(pgn) {
+            let Ok(game) = Game::from_pgn(text) else {
+                continue;
+            };
+            let moves: Vec<_> = game.moves().iter().map(|game_move| game_move.mv).collect();
+            if self.add_game(game.start(), &moves, game.result()).is_ok() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// The book's entries, sorted by key and then by weight, best first, as Polyglot requires.
+    /// Moves that scored nothing are left out, and weights are scaled down per position when
+    /// they would not fit in 16 bits.
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let mut entries = Vec::new();
+        for (&key, moves) in &self.positions {
+            let max = moves.values().map(MoveStats::score).max().unwrap_or(0);
+            let scale = |score: u64| {
+                if max <= u64::from(u16::MAX) {
+                    score as u16
+                } else {
+                    (score * u64::from(u16::MAX) / max).max(1) as u16
+                }
+            };
+            entries.extend(moves.iter().filter(|(_, stats)| stats.score() > 0).map(
+                |(&mv, stats)| BookEntry {
+                    key,
+                    mv,
+                    weight: scale(stats.score()),
+                    learn: 0,
+                },
+            ));
+        }
+        entries.sort_by_key(|entry| (entry.key, Reverse(entry.weight), entry.mv));
+        entries
+    }
+
+    /// Writes the book in Polyglot's `.bin` format.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        for entry in self.entries() {
+            writer.write_all(&entry.to_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{Book, Selection};
+    use std::io::Cursor;
+
+    fn moves(notation: &str) -> Vec<Move> {
+        let mut board = Board::new();
+        notation
+            .split_whitespace()
+            .map(|uci| {
+                let mv = board.parse_move(uci).unwrap();
+                board.make_move(mv).unwrap();
+                mv
+            })
+            .collect()
+    }
+
+    fn after(notation: &str) -> Board {
+        let mut board = Board::new();
+        for mv in moves(notation) {
+            board.make_move(mv).unwrap();
+        }
+        board
+    }
+
+    fn builder() -> BookBuilder {
+        let mut builder = BookBuilder::new();
+        let games = [
+            ("e2e4 e7e5 g1f3", GameResult::WhiteWins),
+            ("e2e4 c7c5 g1f3", GameResult::BlackWins),
+            ("e2e4 e7e5 f1c4", GameResult::Draw),
+            ("d2d4 d7d5", GameResult::BlackWins),
+            ("c2c4", GameResult::Unknown),
+        ];
+        for (notation, result) in games {
+            builder
+                .add_game(&Board::new(), &moves(notation), result)
+                .unwrap();
+        }
+        builder
+    }
+
+    #[test]
+    fn accumulates_results_per_move() {
+        let builder = builder();
+        let start = Board::new();
+        let e4 = start.parse_move("e2e4").unwrap();
+        assert_eq!(
+            builder.stats(&start, &e4),
+            Some(MoveStats {
+                wins: 1,
+                draws: 1,
+                losses: 1,
+                games: 3
+            })
+        );
+        let c5 = after("e2e4").parse_move("c7c5").unwrap();
+        let c5_stats = builder.stats(&after("e2e4"), &c5).unwrap();
+        assert_eq!((c5_stats.wins, c5_stats.score()), (1, 2));
+
+        // e4 scores 3, d4 nothing and c4 has no known result
+        let weights: Vec<_> = builder
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.key == polyglot_key(&start))
+            .map(|entry| entry.weight)
+            .collect();
+        assert_eq!(weights, [3]);
+    }
+
+    #[test]
+    fn entries_are_sorted() {
+        let entries = builder().entries();
+        assert!(entries.windows(2).all(|pair| {
+            (pair[0].key, Reverse(pair[0].weight)) <= (pair[1].key, Reverse(pair[1].weight))
+        }));
+    }
+
+    #[test]
+    fn written_book_can_be_read() {
+        let mut bytes = Vec::new();
+        builder().write(&mut bytes).unwrap();
+        let mut book = Book::new(Cursor::new(bytes)).unwrap();
+
+        let start = Board::new();
+        let best = book.choose_move(&start, Selection::Best).unwrap();
+        assert_eq!(best.unwrap().to_string(), "e2e4");
+        let after_e4 = after("e2e4");
+        let replies: Vec<_> = book
+            .moves(&after_e4)
+            .unwrap()
+            .iter()
+            .map(|book_move| (book_move.mv.to_string(), book_move.weight))
+            .collect();
+        assert_eq!(replies, [("c7c5".to_string(), 2), ("e7e5".to_string(), 1)]);
+    }
+
+    #[test]
+    fn colour_filter_and_ply_limit() {
+        let mut builder = BookBuilder::new();
+        builder.set_colour(Some(Colour::White));
+        builder.set_max_ply(2);
+        builder
+            .add_game(
+                &Board::new(),
+                &moves("e2e4 e7e5 g1f3"),
+                GameResult::WhiteWins,
+            )
+            .unwrap();
+        // Only 1. e4 is White's within the first two plies
+        assert_eq!(builder.positions(), 1);
+        assert!(
+            builder
+                .stats(&Board::new(), &Board::new().parse_move("e2e4").unwrap())
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn rejects_illegal_games() {
+        let mut builder = BookBuilder::new();
+        let mut game = moves("e2e4 e7e5");
+        game.push(game[0]);
+        assert_eq!(
+            builder.add_game(&Board::new(), &game, GameResult::Draw),
+            Err("Illegal move")
+        );
+        assert_eq!(builder.positions(), 0);
+    }
+
+    #[test]
+    fn builds_from_pgn_collections() {
+        let pgn = "[Event \"One\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+                   [Event \"Two\"]\n[Result \"0-1\"]\n\n1. e4 c5 {Sicilian} 2. Nf3 0-1\n\n\
+                   [Event \"Illegal\"]\n\n1. e4 e4 *\n\n\
+                   [Event \"Three\"]\n[Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n";
+        let mut builder = BookBuilder::new();
+        builder.set_colour(Some(Colour::Black));
+        builder.set_max_ply(2);
+        assert_eq!(builder.add_pgn(pgn), 3);
+
+        // Only Black's replies within the first two plies were added
+        assert_eq!(builder.positions(), 2);
+        let after_e4 = after("e2e4");
+        let stats =
+            |board: &Board, uci: &str| builder.stats(board, &board.parse_move(uci).unwrap());
+        assert_eq!(stats(&after_e4, "e7e5").map(|s| s.losses), Some(1));
+        assert_eq!(stats(&after_e4, "c7c5").map(|s| s.wins), Some(1));
+        assert_eq!(stats(&after("d2d4"), "d7d5").map(|s| s.draws), Some(1));
+        assert_eq!(stats(&Board::new(), "e2e4"), None);
+    }
+
+    #[test]
+    fn large_weights_are_scaled() {
+        let mut builder = BookBuilder::new();
+        let start = Board::new();
+        let e4 = moves("e2e4");
+        let d4 = moves("d2d4");
+        for _ in 0..40_000 {
+            builder
+                .add_game(&start, &e4, GameResult::WhiteWins)
+                .unwrap();
+        }
+        builder.add_game(&start, &d4, GameResult::Draw).unwrap();
+        let weights: Vec<_> = builder.entries().iter().map(|entry| entry.weight).collect();
+        assert_eq!(weights, [u16::MAX, 1]);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::board::Board;
+use crate::search::{SearchLimits, SearchResult, Searcher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// Runs searches on a background thread, so a protocol front end can keep reading commands
+/// while the engine thinks, and stop it when told to.
+#[derive(Debug)]
+pub(crate) struct Engine {
+    /// `None` while the searcher is out on the worker thread.
+    searcher: Option<Searcher>,
+    worker: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        let searcher = Searcher::new();
+        Self {
+            stop: searcher.stop_flag(),
+            searcher: Some(searcher),
+            worker: None,
+        }
+    }
+}
+
+impl Engine {
+    /// The searcher, stopping any search in progress first.
+    pub(crate) fn searcher(&mut self) -> &mut Searcher {
+        self.stop();
+        self.searcher
+            .as_mut()
+            .expect("the worker hands the searcher back when it finishes")
+    }
+
+    /// Starts searching `board` in the background, stopping any search already running.
+    ///
+    /// `on_iteration` and `on_finish` are called from the worker thread, with the result of
+    /// each completed iteration and the final result. With `wait_for_stop` the final result is
+    /// held back until `stop` is called even if the search ends on its own, as UCI requires for
+    /// `go infinite`.
+    pub(crate) fn start(
+        &mut self,
+        mut board: Board,
+        limits: SearchLimits,
+        wait_for_stop: bool,
+        on_iteration: impl FnMut(&SearchResult) + Send + 'static,
+        on_finish: impl FnOnce(SearchResult) + Send + 'static,
+    ) {
+        self.stop();
+        let mut searcher = self.searcher.take().expect("no search is running");
+        self.stop.store(false, Ordering::SeqCst);
+        let stop = Arc::clone(&self.stop);
+        self.worker = Some(thread::spawn(move || {
+            let result = searcher.search_with(&mut board, limits, on_iteration);
+            while wait_for_stop && !stop.load(Ordering::SeqCst) {
+                thread::park();
+            }
+            on_finish(result);
+            searcher
+        }));
+    }
+
+    /// Stops the search in progress, if any, and waits for it to report its result.
+    pub(crate) fn stop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            worker.thread().unpark();
+            self.searcher = Some(worker.join().expect("the search thread panicked"));
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,507 @@
+use crate::board::{Board, Colour, Coordinate, DROP_TYPES};
+use crate::piece::{Piece, PieceType};
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+/// A pair of middlegame and endgame scores, blended by game phase once the whole position has
+/// been evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const ZERO: Self = Self::new(0, 0);
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Interpolates between the middlegame and endgame score, `phase` running from
+    /// `MAX_PHASE` (all pieces on the board) down to 0 (bare kings and pawns).
+    pub const fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Score {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        Self::new(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+/// Game phase with every minor and major piece still on the board.
+pub const MAX_PHASE: i32 = 24;
+
+const fn phase_weight(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn | PieceType::King => 0,
+        PieceType::Knight | PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 4,
+    }
+}
+
+/// Nominal value of a piece in centipawns, as used for exchanges and move ordering.
+pub const fn piece_value(piece_type: PieceType) -> i32 {
+    material(piece_type).mg
+}
+
+const fn material(piece_type: PieceType) -> Score {
+    match piece_type {
+        PieceType::Pawn => Score::new(82, 94),
+        PieceType::Knight => Score::new(337, 281),
+        PieceType::Bishop => Score::new(365, 297),
+        PieceType::Rook => Score::new(477, 512),
+        PieceType::Queen => Score::new(1025, 936),
+        // The king can never be traded, giving it a value just keeps the tables uniform.
+        PieceType::King => Score::new(0, 0),
+    }
+}
+
+const BISHOP_PAIR: Score = Score::new(30, 50);
+const DOUBLED_PAWN: Score = Score::new(-10, -20);
+const ISOLATED_PAWN: Score = Score::new(-10, -15);
+/// Passed pawn bonus indexed by how many ranks the pawn has advanced from its starting rank.
+const PASSED_PAWN: [Score; 7] = [
+    Score::new(0, 0),
+    Score::new(5, 10),
+    Score::new(10, 20),
+    Score::new(15, 35),
+    Score::new(30, 60),
+    Score::new(50, 100),
+    Score::new(80, 150),
+];
+/// Bonus per friendly pawn sheltering the king, and penalty per open file beside it.
+const KING_SHIELD_PAWN: Score = Score::new(12, 0);
+const KING_OPEN_FILE: Score = Score::new(-20, 0);
+
+const fn mobility_weight(piece_type: PieceType) -> Score {
+    match piece_type {
+        PieceType::Knight => Score::new(4, 4),
+        PieceType::Bishop => Score::new(5, 5),
+        PieceType::Rook => Score::new(2, 4),
+        PieceType::Queen => Score::new(1, 2),
+        PieceType::Pawn | PieceType::King => Score::new(0, 0),
+    }
+}
+
+// Piece-square tables, laid out as seen from White's side of the board: the first row is the
+// eighth rank and the last row is the first rank. Black's pieces read them mirrored.
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+fn piece_square(piece: Piece, coord: &Coordinate) -> Score {
+    let rank = match piece.colour {
+        Colour::White => 7 - coord.y(),
+        Colour::Black => coord.y(),
+    };
+    let index = (rank * 8 + coord.x()) as usize;
+    let (mg, eg) = match piece.piece_type {
+        PieceType::Pawn => (&PAWN_MG, &PAWN_EG),
+        PieceType::Knight => (&KNIGHT, &KNIGHT),
+        PieceType::Bishop => (&BISHOP, &BISHOP),
+        PieceType::Rook => (&ROOK, &ROOK),
+        PieceType::Queen => (&QUEEN, &QUEEN),
+        PieceType::King => (&KING_MG, &KING_EG),
+    };
+    Score::new(mg[index], eg[index])
+}
+
+/// The individual terms of a static evaluation. Each term is White's score minus Black's, before
+/// tapering; `total` combines and tapers them and flips the sign for the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Evaluation {
+    pub material: Score,
+    pub piece_squares: Score,
+    pub bishop_pair: Score,
+    pub mobility: Score,
+    pub pawn_structure: Score,
+    pub king_safety: Score,
+    pub phase: i32,
+    pub turn: Colour,
+}
+
+impl Evaluation {
+    fn terms(&self) -> [(&'static str, Score); 6] {
+        [
+            ("material", self.material),
+            ("piece squares", self.piece_squares),
+            ("bishop pair", self.bishop_pair),
+            ("mobility", self.mobility),
+            ("pawn structure", self.pawn_structure),
+            ("king safety", self.king_safety),
+        ]
+    }
+
+    /// The combined score from White's perspective, tapered by game phase.
+    pub fn white_total(&self) -> i32 {
+        self.terms()
+            .iter()
+            .fold(Score::ZERO, |acc, (_, score)| acc + *score)
+            .taper(self.phase)
+    }
+
+    /// The combined score in centipawns from the side to move's perspective.
+    pub fn total(&self) -> i32 {
+        self.white_total() * colour_sign(self.turn)
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<16}{:>8}{:>8}{:>8}", "term", "mg", "eg", "total")?;
+        for (name, score) in self.terms() {
+            writeln!(
+                f,
+                "{:<16}{:>8}{:>8}{:>8}",
+                name,
+                score.mg,
+                score.eg,
+                score.taper(self.phase)
+            )?;
+        }
+        writeln!(f, "phase {}/{}", self.phase, MAX_PHASE)?;
+        write!(f, "total (side to move) {}", self.total())
+    }
+}
+
+/// Scores `board` in centipawns from the side to move's perspective.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_terms(board).total()
+}
+
+/// Evaluates `board`, keeping each term separate for inspection.
+pub fn evaluate_terms(board: &Board) -> Evaluation {
+    let mut eval = Evaluation {
+        material: Score::ZERO,
+        piece_squares: Score::ZERO,
+        bishop_pair: Score::ZERO,
+        mobility: Score::ZERO,
+        pawn_structure: Score::ZERO,
+        king_safety: Score::ZERO,
+        phase: 0,
+        turn: board.turn(),
+    };
+    let mut bishops = [0; 2];
+    // Pawn counts per file for each colour, used by the pawn structure and king safety terms.
+    let mut pawn_files = [[0u8; 8]; 2];
+
+    for (coord, piece) in board.into_iter().flatten() {
+        let sign = colour_sign(piece.colour);
+        eval.material += material(piece.piece_type) * sign;
+        eval.piece_squares += piece_square(piece, &coord) * sign;
+        eval.phase += phase_weight(piece.piece_type);
+        match piece.piece_type {
+            PieceType::Bishop => bishops[piece.colour as usize] += 1,
+            PieceType::Pawn => pawn_files[piece.colour as usize][coord.x() as usize] += 1,
+            _ => {}
+        }
+        let weight = mobility_weight(piece.piece_type);
+        if weight != Score::ZERO {
+            let moves = board.pseudo_moves(&coord).map_or(0, |moves| moves.len());
+            eval.mobility += weight * moves as i32 * sign;
+        }
+    }
+    // Pieces in a crazyhouse pocket are worth as much as on the board, they can be dropped
+    // anywhere.
+    for colour in [Colour::White, Colour::Black] {
+        let pocket = board.pocket(colour);
+        for piece_type in DROP_TYPES {
+            eval.material +=
+                material(piece_type) * (pocket.count(piece_type) as i32 * colour_sign(colour));
+        }
+    }
+    eval.phase = eval.phase.min(MAX_PHASE);
+
+    for colour in [Colour::White, Colour::Black] {
+        let sign = colour_sign(colour);
+        if bishops[colour as usize] >= 2 {
+            eval.bishop_pair += BISHOP_PAIR * sign;
+        }
+        eval.pawn_structure += pawn_structure(board, colour, &pawn_files) * sign;
+        eval.king_safety += king_safety(board, colour, &pawn_files[colour as usize]) * sign;
+    }
+    eval
+}
+
+const fn colour_sign(colour: Colour) -> i32 {
+    match colour {
+        Colour::White => 1,
+        Colour::Black => -1,
+    }
+}
+
+fn pawn_structure(board: &Board, colour: Colour, pawn_files: &[[u8; 8]; 2]) -> Score {
+    let own = &pawn_files[colour as usize];
+    let mut score = Score::ZERO;
+    for (file, &count) in own.iter().enumerate() {
+        if count > 1 {
+            score += DOUBLED_PAWN * (count as i32 - 1);
+        }
+        let left = file.checked_sub(1).map_or(0, |f| own[f]);
+        let right = own.get(file + 1).copied().unwrap_or(0);
+        if count > 0 && left == 0 && right == 0 {
+            score += ISOLATED_PAWN * count as i32;
+        }
+    }
+
+    for (coord, piece) in board.into_iter().flatten() {
+        if piece == Piece::pawn(colour) && is_passed(board, colour, &coord) {
+            let advanced = match colour {
+                Colour::White => coord.y() - 1,
+                Colour::Black => 6 - coord.y(),
+            };
+            score += PASSED_PAWN[advanced as usize];
+        }
+    }
+    score
+}
+
+/// A pawn is passed when no enemy pawn stands in front of it on its own or an adjacent file.
+fn is_passed(board: &Board, colour: Colour, coord: &Coordinate) -> bool {
+    let ahead = match colour {
+        Colour::White => (coord.y() + 1)..8,
+        Colour::Black => 0..coord.y(),
+    };
+    let files = coord.x().saturating_sub(1)..=(coord.x() + 1).min(7);
+    !files.into_iter().any(|x| {
+        ahead.clone().any(|y| {
+            Coordinate::new(x, y).is_ok_and(|square| {
+                board.get_square(&square) == Some(Piece::pawn(colour.opposite()))
+            })
+        })
+    })
+}
+
+fn king_safety(board: &Board, colour: Colour, own_pawn_files: &[u8; 8]) -> Score {
+    let Some((king_pos, _)) = board
+        .into_iter()
+        .flatten()
+        .find(|(_, piece)| *piece == Piece::king(colour))
+    else {
+        return Score::ZERO;
+    };
+    let forward: i8 = match colour {
+        Colour::White => 1,
+        Colour::Black => -1,
+    };
+
+    let mut score = Score::ZERO;
+    for dx in -1..=1 {
+        // Count pawns on the two ranks directly in front of the king.
+        for dy in [forward, 2 * forward] {
+            if king_pos
+                .try_apply_delta((dx, dy))
+                .is_ok_and(|square| board.get_square(&square) == Some(Piece::pawn(colour)))
+            {
+                score += KING_SHIELD_PAWN;
+            }
+        }
+        let file = king_pos.x() as i8 + dx;
+        if (0..8).contains(&file) && own_pawn_files[file as usize] == 0 {
+            score += KING_OPEN_FILE;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn start_position_is_balanced() {
+        let eval = evaluate_terms(&Board::default());
+        assert_eq!(eval.phase, MAX_PHASE);
+        assert_eq!(eval.total(), 0);
+        assert_eq!(eval.material, Score::ZERO);
+    }
+
+    #[rstest]
+    #[case::white_to_move("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")]
+    #[case::endgame("8/5pk1/6p1/8/3P4/8/5PPP/6K1 w - - 0 40")]
+    fn evaluation_flips_with_side_to_move(#[case] fen: &str) {
+        let white = Board::from_fen(fen).unwrap();
+        let black = Board::from_fen(&fen.replace(" w ", " b ")).unwrap();
+        assert_eq!(evaluate(&white), -evaluate(&black));
+    }
+
+    #[test]
+    fn extra_material_is_positive_for_its_owner() {
+        // White is a rook up
+        let board = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 w - - 0 1").unwrap();
+        assert!(evaluate(&board) > 300);
+        let board = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 b - - 0 1").unwrap();
+        assert!(evaluate(&board) < -300);
+    }
+
+    #[test]
+    fn bishop_pair_is_rewarded() {
+        let board = Board::from_fen("2b1k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        assert_eq!(evaluate_terms(&board).bishop_pair, BISHOP_PAIR);
+    }
+
+    #[rstest]
+    #[case::doubled(
+        "4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1",
+        DOUBLED_PAWN + ISOLATED_PAWN * 2 + PASSED_PAWN[1]
+    )]
+    #[case::passed_on_sixth("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1", ISOLATED_PAWN + PASSED_PAWN[4])]
+    #[case::blocked_by_adjacent_file("4k3/4p3/8/3P4/8/8/8/4K3 w - - 0 1", Score::ZERO)]
+    fn pawn_structure_terms(#[case] fen: &str, #[case] expected: Score) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(evaluate_terms(&board).pawn_structure, expected);
+    }
+
+    #[test]
+    fn sheltered_king_is_safer_than_exposed_king() {
+        let sheltered = Board::from_fen("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let exposed = Board::from_fen("6k1/8/8/8/8/5PPP/8/3K4 w - - 0 1").unwrap();
+        assert!(
+            evaluate_terms(&sheltered).king_safety.mg > evaluate_terms(&exposed).king_safety.mg
+        );
+    }
+
+    #[test]
+    fn report_lists_every_term() {
+        let report = evaluate_terms(&Board::default()).to_string();
+        for term in [
+            "material",
+            "piece squares",
+            "bishop pair",
+            "mobility",
+            "pawn structure",
+            "king safety",
+        ] {
+            assert!(report.contains(term), "missing {term}");
+        }
+    }
+}
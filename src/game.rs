@@ -0,0 +1,509 @@
+//! Whole games, as opposed to single positions.
+
+mod pgn;
+
+pub use pgn::split_games;
+
+use crate::board::{Board, Colour, Move};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How a game ended, as recorded in PGN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// Still in progress, abandoned or unknown.
+    #[default]
+    Unknown,
+}
+
+impl GameResult {
+    pub fn win_for(colour: Colour) -> Self {
+        match colour {
+            Colour::White => Self::WhiteWins,
+            Colour::Black => Self::BlackWins,
+        }
+    }
+
+    /// The winner, `None` for a draw or an unknown result.
+    pub fn winner(self) -> Option<Colour> {
+        match self {
+            Self::WhiteWins => Some(Colour::White),
+            Self::BlackWins => Some(Colour::Black),
+            Self::Draw | Self::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unknown => "*",
+        })
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(Self::WhiteWins),
+            "0-1" => Ok(Self::BlackWins),
+            "1/2-1/2" => Ok(Self::Draw),
+            "*" => Ok(Self::Unknown),
+            _ => Err("Expected 1-0, 0-1, 1/2-1/2 or *"),
+        }
+    }
+}
+
+/// Why a game ended: by a rule of the game, or because of the players.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    FiftyMoveRule,
+    /// The same position a third time.
+    Repetition,
+    /// Too little material left for either side to mate.
+    InsufficientMaterial,
+    /// A third check in three-check.
+    ThreeChecks,
+    /// A king reaching the centre in King of the Hill.
+    KingOfTheHill,
+    /// A king blown up in atomic chess.
+    Explosion,
+    /// A side losing its last piece: a win in antichess, a loss in horde.
+    NoPiecesLeft,
+    /// A king reaching the eighth rank in Racing Kings.
+    EighthRank,
+    Resignation,
+    TimeForfeit,
+    /// A draw agreed by the players.
+    Agreement,
+}
+
+impl Termination {
+    pub const ALL: [Self; 13] = [
+        Self::Checkmate,
+        Self::Stalemate,
+        Self::FiftyMoveRule,
+        Self::Repetition,
+        Self::InsufficientMaterial,
+        Self::ThreeChecks,
+        Self::KingOfTheHill,
+        Self::Explosion,
+        Self::NoPiecesLeft,
+        Self::EighthRank,
+        Self::Resignation,
+        Self::TimeForfeit,
+        Self::Agreement,
+    ];
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Checkmate => "Checkmate",
+            Self::Stalemate => "Stalemate",
+            Self::FiftyMoveRule => "Fifty move rule",
+            Self::Repetition => "Threefold repetition",
+            Self::InsufficientMaterial => "Insufficient material",
+            Self::ThreeChecks => "Three checks",
+            Self::KingOfTheHill => "King of the hill",
+            Self::Explosion => "King exploded",
+            Self::NoPiecesLeft => "No pieces left",
+            Self::EighthRank => "King reached the eighth rank",
+            Self::Resignation => "Resignation",
+            Self::TimeForfeit => "Time forfeit",
+            Self::Agreement => "Agreement",
+        })
+    }
+}
+
+/// Parses the `Display` form, case-insensitively.
+impl FromStr for Termination {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|termination| termination.to_string().eq_ignore_ascii_case(s))
+            .ok_or("Unknown termination")
+    }
+}
+
+/// How a finished game ended: its result and the rule that decided it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Outcome {
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl Outcome {
+    pub fn win(winner: Colour, termination: Termination) -> Self {
+        Self {
+            result: GameResult::win_for(winner),
+            termination,
+        }
+    }
+
+    pub fn draw(termination: Termination) -> Self {
+        Self {
+            result: GameResult::Draw,
+            termination,
+        }
+    }
+}
+
+/// A move of a game, as written in its SAN.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameMove {
+    pub mv: Move,
+    pub san: String,
+    /// When the move was made, counted from the start of the game, if known.
+    pub timestamp: Option<Duration>,
+}
+
+/// A game: the position it started from, the moves played since, who played them and how it
+/// ended.
+///
+/// The game keeps a cursor into its moves like an editor's undo history. Undoing moves the
+/// cursor back without forgetting anything, so the moves can be redone, and playing a move
+/// anywhere but at the end replaces the rest of the game.
+#[derive(Clone, Debug)]
+pub struct Game {
+    start: Board,
+    /// The position after the first `cursor` moves.
+    board: Board,
+    moves: Vec<GameMove>,
+    cursor: usize,
+    /// PGN tags other than those the game works out itself, in the order they were set.
+    tags: Vec<(String, String)>,
+    /// The result if it was not decided on the board, as by a resignation.
+    declared: Option<(GameResult, Option<Termination>)>,
+    chess960: bool,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// A game of standard chess from the start position.
+    pub fn new() -> Self {
+        Self::from_board(Board::new())
+    }
+
+    /// A game starting from `start`, in whichever variant it is played. It counts as Chess960
+    /// if castling is set up as only Chess960 allows, see `set_chess960`.
+    pub fn from_board(start: Board) -> Self {
+        Self {
+            chess960: start.has_chess960_castling(),
+            board: start.clone(),
+            start,
+            moves: Vec::new(),
+            cursor: 0,
+            tags: Vec::new(),
+            declared: None,
+        }
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    /// Whether the game is Chess960, as written in the PGN `Variant` tag.
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Marks the game as Chess960 or not. Only needed for Chess960 start positions that look
+    /// like standard chess, such as number 518 or one without castling rights.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    /// The position at the cursor.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Every move of the game, including any undone ones after the cursor.
+    pub fn moves(&self) -> &[GameMove] {
+        &self.moves
+    }
+
+    /// The number of moves played up to the cursor.
+    pub fn ply(&self) -> usize {
+        self.cursor
+    }
+
+    /// Plays `mv` at the cursor, replacing any moves after it.
+    pub fn play(&mut self, mv: Move) -> Result<(), &'static str> {
+        self.play_at(mv, None)
+    }
+
+    /// Plays `mv` at the cursor, recording when it was made.
+    pub fn play_at(&mut self, mv: Move, timestamp: Option<Duration>) -> Result<(), &'static str> {
+        // `Board::san` plays the move on a copy, so it must be known to be legal first.
+        if !self.board.get_legal_moves().contains(&mv) {
+            return Err("Illegal move");
+        }
+        let san = self.board.san(&mv);
+        self.board.make_move_unchecked(mv);
+        self.moves.truncate(self.cursor);
+        self.moves.push(GameMove { mv, san, timestamp });
+        self.cursor += 1;
+        // A result declared for the old line no longer holds.
+        self.declared = None;
+        Ok(())
+    }
+
+    /// Plays the move written in SAN at the cursor.
+    pub fn play_san(&mut self, san: &str) -> Result<(), &'static str> {
+        let mv = self.board.parse_san(san)?;
+        self.play(mv)
+    }
+
+    /// Moves the cursor back a move, returning the move taken back.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.cursor.checked_sub(1).map(|ply| self.moves[ply].mv)?;
+        self.board.unmake_move();
+        self.cursor -= 1;
+        Some(mv)
+    }
+
+    /// Moves the cursor forward over an undone move, returning it.
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.moves.get(self.cursor)?.mv;
+        self.board.make_move_unchecked(mv);
+        self.cursor += 1;
+        Some(mv)
+    }
+
+    /// Moves the cursor to just after the first `ply` moves, 0 being the start position.
+    pub fn go_to(&mut self, ply: usize) -> Result<(), &'static str> {
+        if ply > self.moves.len() {
+            return Err("The game is not that long");
+        }
+        while self.cursor > ply {
+            self.undo();
+        }
+        while self.cursor < ply {
+            self.redo();
+        }
+        Ok(())
+    }
+
+    /// The value of a PGN tag, such as `White` or `Event`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a PGN tag, replacing any earlier value. The tags describing the position and
+    /// result, `Result`, `Termination`, `FEN`, `SetUp` and `Variant`, are worked out from the
+    /// game instead and never written from here.
+    pub fn set_tag(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        match self.tags.iter_mut().find(|(tag, _)| *tag == name) {
+            Some((_, old)) => *old = value,
+            None => self.tags.push((name, value)),
+        }
+    }
+
+    /// Records a result the moves do not show, such as a resignation or a draw by agreement.
+    /// Playing another move clears it.
+    pub fn set_result(&mut self, result: GameResult, termination: Option<Termination>) {
+        self.declared = Some((result, termination));
+    }
+
+    /// The result: as declared, or else as reached on the board after the last move.
+    pub fn result(&self) -> GameResult {
+        match self.declared {
+            Some((result, _)) => result,
+            None => self
+                .final_outcome()
+                .map_or(GameResult::Unknown, |outcome| outcome.result),
+        }
+    }
+
+    /// Why the game ended, if it has and that is known.
+    pub fn termination(&self) -> Option<Termination> {
+        match self.declared {
+            Some((_, termination)) => termination,
+            None => self.final_outcome().map(|outcome| outcome.termination),
+        }
+    }
+
+    /// The outcome after the last move, wherever the cursor is: on the board, or by the final
+    /// position having come up for the third time.
+    fn final_outcome(&self) -> Option<Outcome> {
+        let mut board = self.start.clone();
+        let mut hashes = vec![board.hash()];
+        for game_move in &self.moves {
+            board.make_move_unchecked(game_move.mv);
+            hashes.push(board.hash());
+        }
+        board.outcome().or_else(|| {
+            let repetitions = hashes.iter().filter(|&&hash| hash == board.hash()).count();
+            (repetitions >= 3).then(|| Outcome::draw(Termination::Repetition))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Variant;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1-0", GameResult::WhiteWins)]
+    #[case("0-1", GameResult::BlackWins)]
+    #[case("1/2-1/2", GameResult::Draw)]
+    #[case("*", GameResult::Unknown)]
+    fn result_round_trip(#[case] notation: &str, #[case] result: GameResult) {
+        assert_eq!(notation.parse(), Ok(result));
+        assert_eq!(result.to_string(), notation);
+    }
+
+    #[test]
+    fn rejects_unknown_results() {
+        assert!("2-0".parse::<GameResult>().is_err());
+    }
+
+    #[test]
+    fn terminations_round_trip() {
+        for termination in Termination::ALL {
+            assert_eq!(termination.to_string().parse(), Ok(termination));
+        }
+        assert_eq!("time forfeit".parse(), Ok(Termination::TimeForfeit));
+    }
+
+    fn play(sans: &str) -> Game {
+        let mut game = Game::new();
+        for san in sans.split_whitespace() {
+            game.play_san(san).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn records_moves_in_san() {
+        let game = play("e4 e5 Nf3 Nc6 Bb5");
+        let sans: Vec<_> = game.moves().iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(game.ply(), 5);
+        assert_eq!(game.board().move_list().len(), 5);
+        assert_eq!(game.result(), GameResult::Unknown);
+    }
+
+    #[test]
+    fn undo_redo_and_jumping() {
+        let mut game = play("e4 e5 Nf3 Nc6");
+        let after_e5 = {
+            let mut board = Board::new();
+            for uci in ["e2e4", "e7e5"] {
+                board.make_move(board.parse_move(uci).unwrap()).unwrap();
+            }
+            board
+        };
+        assert_eq!(
+            game.undo().map(|mv| mv.to_string()),
+            Some("b8c6".to_string())
+        );
+        game.undo();
+        assert_eq!(game.board(), &after_e5);
+        assert_eq!(
+            game.redo().map(|mv| mv.to_string()),
+            Some("g1f3".to_string())
+        );
+        assert_eq!(game.moves().len(), 4);
+
+        game.go_to(0).unwrap();
+        assert_eq!(game.board(), game.start());
+        assert_eq!(game.undo(), None);
+        game.go_to(4).unwrap();
+        assert_eq!(game.redo(), None);
+        assert!(game.go_to(5).is_err());
+
+        // Playing a different move after undoing replaces the rest of the game
+        game.go_to(2).unwrap();
+        game.play_san("Bc4").unwrap();
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.moves()[2].san, "Bc4");
+        assert!(game.redo().is_none());
+    }
+
+    #[test]
+    fn result_comes_from_the_board_or_the_players() {
+        let mut game = play("f3 e5 g4 Qh4#");
+        assert_eq!(game.result(), GameResult::BlackWins);
+        assert_eq!(game.termination(), Some(Termination::Checkmate));
+        // Wherever the cursor is
+        game.go_to(1).unwrap();
+        assert_eq!(game.result(), GameResult::BlackWins);
+
+        let mut game = play("e4 e5");
+        game.set_result(GameResult::WhiteWins, Some(Termination::Resignation));
+        assert_eq!(game.result(), GameResult::WhiteWins);
+        assert_eq!(game.termination(), Some(Termination::Resignation));
+        game.play_san("Nf3").unwrap();
+        assert_eq!(game.result(), GameResult::Unknown);
+        assert_eq!(game.termination(), None);
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = play("Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1");
+        assert_eq!(game.result(), GameResult::Unknown);
+        game.play_san("Ng8").unwrap();
+        assert_eq!(game.result(), GameResult::Draw);
+        assert_eq!(game.termination(), Some(Termination::Repetition));
+        game.go_to(3).unwrap();
+        assert_eq!(game.termination(), Some(Termination::Repetition));
+        game.play_san("Nc6").unwrap();
+        assert_eq!(game.termination(), None);
+    }
+
+    #[test]
+    fn tags_and_timestamps() {
+        let mut game = Game::new();
+        game.set_tag("White", "Alice");
+        game.set_tag("White", "Bob");
+        assert_eq!(game.tag("White"), Some("Bob"));
+        assert_eq!(game.tag("Black"), None);
+
+        let e4 = game.board().parse_san("e4").unwrap();
+        game.play_at(e4, Some(Duration::from_secs(3))).unwrap();
+        assert_eq!(game.moves()[0].timestamp, Some(Duration::from_secs(3)));
+        assert_eq!(game.play(e4), Err("Illegal move"));
+    }
+
+    #[test]
+    fn rejects_moves_from_another_position() {
+        let with_knight_in_hand = Board::from_variant_fen(
+            Variant::Crazyhouse,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[N] w KQkq - 0 1",
+        )
+        .unwrap();
+        let drop = with_knight_in_hand.parse_san("N@f3").unwrap();
+
+        let mut game = Game::from_board(Board::start(Variant::Crazyhouse));
+        assert_eq!(game.play(drop), Err("Illegal move"));
+        assert!(game.moves().is_empty());
+        assert_eq!(game.board(), game.start());
+    }
+}
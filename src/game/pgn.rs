@@ -0,0 +1,454 @@
+//! Reading and writing games as PGN.
+
+use super::{Game, GameResult, Termination};
+use crate::board::{Board, Colour};
+use crate::variant::Variant;
+use std::time::Duration;
+
+/// The Seven Tag Roster, less `Result`, with the values written when a tag is missing.
+const ROSTER: [(&str, &str); 6] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+];
+
+/// Tags worked out from the game rather than stored with its other tags.
+const DERIVED: [&str; 5] = ["Result", "Termination", "Variant", "SetUp", "FEN"];
+
+/// The `Variant` tag of Chess960 games, which are otherwise standard chess.
+const CHESS960: &str = "Chess960";
+
+/// Movetext lines are kept under 80 characters, as the PGN standard asks.
+const LINE_LENGTH: usize = 79;
+
+impl Game {
+    /// Writes the game in PGN, including moves after the cursor. The time each move took is
+    /// written as an `[%emt]` comment.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        let mut write_tag = |name: &str, value: &str| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+        };
+        for (name, default) in ROSTER {
+            write_tag(name, self.tag(name).unwrap_or(default));
+        }
+        let result = self.result();
+        write_tag("Result", &result.to_string());
+        let variant = self.start.variant();
+        if self.chess960 {
+            write_tag("Variant", CHESS960);
+        } else if variant != Variant::Standard {
+            write_tag("Variant", variant.pgn_name());
+        }
+        // Chess960 positions always need their FEN, with the castling rooks named by file.
+        let fen = if self.chess960 {
+            self.start.to_shredder_fen()
+        } else {
+            self.start.to_fen()
+        };
+        if self.chess960 || fen != Board::start(variant).to_fen() {
+            write_tag("SetUp", "1");
+            write_tag("FEN", &fen);
+        }
+        if let Some(termination) = self.termination() {
+            write_tag("Termination", &termination.to_string());
+        }
+        for (name, value) in &self.tags {
+            if !ROSTER.iter().any(|(roster, _)| roster == name) && !DERIVED.contains(&name.as_str())
+            {
+                write_tag(name, value);
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut number = self.start.fullmove_number();
+        let mut colour = self.start.turn();
+        let mut after_comment = true;
+        let mut clock = Duration::ZERO;
+        for game_move in &self.moves {
+            match colour {
+                Colour::White => tokens.push(format!("{number}.")),
+                Colour::Black if after_comment => tokens.push(format!("{number}...")),
+                Colour::Black => {}
+            }
+            tokens.push(game_move.san.clone());
+            after_comment = false;
+            if let Some(timestamp) = game_move.timestamp {
+                let emt = format_duration(timestamp.saturating_sub(clock));
+                tokens.push(format!("{{[%emt {emt}]}}"));
+                clock = timestamp;
+                after_comment = true;
+            }
+            if colour == Colour::Black {
+                number += 1;
+            }
+            colour = colour.opposite();
+        }
+        tokens.push(result.to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            pgn.push_str(&token);
+            line_length += token.len();
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Reads a single game from PGN. Variations, NAGs and comments other than `[%emt]` times are
+    /// skipped. The game is left with its cursor at the end.
+    pub fn from_pgn(pgn: &str) -> Result<Self, &'static str> {
+        let mut tags = Vec::new();
+        let mut lines = pgn.lines().map(str::trim).peekable();
+        while let Some(line) = lines.next_if(|line| line.is_empty() || line.starts_with('[')) {
+            if !line.is_empty() {
+                tags.push(parse_tag(line).ok_or("Invalid PGN tag")?);
+            }
+        }
+        let movetext: Vec<_> = lines.filter(|line| !line.starts_with('%')).collect();
+        let movetext = movetext.join("\n");
+
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let (variant, chess960) = match tag("Variant") {
+            Some(name) if name.eq_ignore_ascii_case(CHESS960) => (Variant::Standard, true),
+            // Standard chess from a set-up position, as some servers write it.
+            Some(name) if name.eq_ignore_ascii_case("From Position") => (Variant::Standard, false),
+            Some(name) => (name.parse()?, false),
+            None => (Variant::Standard, false),
+        };
+        let start = match tag("FEN") {
+            Some(fen) => Board::from_variant_fen(variant, fen)?,
+            None => Board::start(variant),
+        };
+        let mut game = Self::from_board(start);
+        game.chess960 |= chess960;
+        for (name, value) in &tags {
+            if !DERIVED.contains(&name.as_str()) {
+                game.set_tag(name.as_str(), value.as_str());
+            }
+        }
+
+        let mut result = match tag("Result") {
+            Some(result) => result.parse()?,
+            None => GameResult::Unknown,
+        };
+        let mut clock = Duration::ZERO;
+        let mut chars = movetext.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if let (Some(emt), Some(last)) = (parse_emt(&comment), game.moves.last_mut()) {
+                        clock += emt;
+                        last.timestamp = Some(clock);
+                    }
+                }
+                ';' => {
+                    chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+                }
+                '(' => {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.next().ok_or("Unterminated variation in PGN")? {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            '{' => chars.by_ref().take_while(|&c| c != '}').for_each(drop),
+                            _ => {}
+                        }
+                    }
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut word = c.to_string();
+                    while let Some(c) =
+                        chars.next_if(|c| !c.is_whitespace() && !"{;()".contains(*c))
+                    {
+                        word.push(c);
+                    }
+                    if word.starts_with('$') || word.chars().all(|c| "!?".contains(c)) {
+                        continue;
+                    }
+                    if let Ok(token) = word.parse() {
+                        result = token;
+                        continue;
+                    }
+                    let san = strip_move_number(&word);
+                    if !san.is_empty() {
+                        game.play_san(san)?;
+                    }
+                }
+            }
+        }
+
+        if result != GameResult::Unknown {
+            let derived = game
+                .final_outcome()
+                .filter(|outcome| outcome.result == result)
+                .map(|outcome| outcome.termination);
+            let termination = tag("Termination")
+                .and_then(|termination| termination.parse::<Termination>().ok())
+                .or(derived);
+            game.set_result(result, termination);
+        }
+        Ok(game)
+    }
+}
+
+/// Splits a PGN file holding several games into the text of each, to be read by
+/// `Game::from_pgn`. A game ends where the tags of the next one begin.
+pub fn split_games(pgn: &str) -> Vec<&str> {
+    let mut games = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_movetext = false;
+    for line in pgn.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let line = line.trim();
+        if line.starts_with('[') {
+            if in_movetext {
+                games.push(&pgn[start..line_start]);
+                start = line_start;
+                in_movetext = false;
+            }
+        } else if !line.is_empty() {
+            in_movetext = true;
+        }
+    }
+    if !pgn[start..].trim().is_empty() {
+        games.push(&pgn[start..]);
+    }
+    games
+}
+
+/// Splits `[Name "value"]` into its name and unescaped value.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let (name, value) = line
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some((name.to_string(), unescaped))
+}
+
+/// Drops a leading move number such as `12.` or `12...` from a movetext word. Castling written
+/// with zeros has no dot, so is left alone.
+fn strip_move_number(word: &str) -> &str {
+    let rest = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        word
+    }
+}
+
+/// Formats a duration as `h:mm:ss`, with milliseconds if there are any.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let mut formatted = format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    if duration.subsec_millis() > 0 {
+        formatted.push_str(&format!(".{:03}", duration.subsec_millis()));
+    }
+    formatted
+}
+
+/// Reads the time from an `[%emt h:mm:ss]` command in a comment.
+fn parse_emt(comment: &str) -> Option<Duration> {
+    let (_, rest) = comment.split_once("[%emt")?;
+    let (time, _) = rest.split_once(']')?;
+    let (time, millis) = match time.trim().split_once('.') {
+        Some((time, fraction)) => {
+            let digits: String = fraction.chars().chain("000".chars()).take(3).collect();
+            (time, digits.parse().ok()?)
+        }
+        None => (time.trim(), 0),
+    };
+    let mut seconds = 0;
+    for part in time.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(Duration::from_secs(seconds) + Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const SCHOLARS_MATE: &str = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[Date "2024.05.01"]
+[Round "?"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+[Termination "Checkmate"]
+[TimeControl "180+2"]
+
+1. e4 {[%emt 0:00:01]} 1... e5 {[%emt 0:00:02]} 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7#
+1-0
+"#;
+
+    #[test]
+    fn exports_pgn() {
+        let mut game = Game::new();
+        game.set_tag("Event", r#"Casual "blitz""#);
+        game.set_tag("Date", "2024.05.01");
+        game.set_tag("White", "Alice");
+        game.set_tag("Black", "Bob");
+        game.set_tag("TimeControl", "180+2");
+        for (san, seconds) in [("e4", Some(1)), ("e5", Some(3))] {
+            let mv = game.board().parse_san(san).unwrap();
+            game.play_at(mv, seconds.map(Duration::from_secs)).unwrap();
+        }
+        for san in ["Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"] {
+            game.play_san(san).unwrap();
+        }
+        assert_eq!(game.to_pgn(), SCHOLARS_MATE);
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let game = Game::from_pgn(SCHOLARS_MATE).unwrap();
+        assert_eq!(game.tag("Event"), Some(r#"Casual "blitz""#));
+        assert_eq!(game.tag("Result"), None);
+        assert_eq!(game.ply(), 7);
+        assert_eq!(game.moves()[1].timestamp, Some(Duration::from_secs(3)));
+        assert_eq!(game.moves()[2].timestamp, None);
+        assert_eq!(game.termination(), Some(Termination::Checkmate));
+        assert_eq!(game.to_pgn(), SCHOLARS_MATE);
+    }
+
+    #[test]
+    fn imports_annotated_movetext() {
+        let game = Game::from_pgn(
+            "[Result \"0-1\"]\n\
+             [Termination \"Time forfeit\"]\n\
+             \n\
+             1. e4 $1 e5!? {A comment (with brackets)} 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3)\n\
+             ; a rest-of-line comment 3. d4\n\
+             2... Nc6 3.Bb5 a6 4. O-O 0-1",
+        )
+        .unwrap();
+        let sans: Vec<_> = game.moves().iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "O-O"]);
+        assert_eq!(game.result(), GameResult::BlackWins);
+        assert_eq!(game.termination(), Some(Termination::TimeForfeit));
+    }
+
+    #[test]
+    fn custom_start_position() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 10";
+        let mut game = Game::from_board(Board::from_fen(fen).unwrap());
+        game.play_san("Kd7").unwrap();
+        game.play_san("e4").unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 10\"]\n"));
+        assert!(pgn.ends_with("\n\n10... Kd7 11. e4 *\n"));
+
+        let game = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(game.start().to_fen(), fen);
+        assert_eq!(game.ply(), 2);
+        assert_eq!(game.result(), GameResult::Unknown);
+    }
+
+    #[test]
+    fn variant_games() {
+        let mut game = Game::from_board(Board::start(Variant::Crazyhouse));
+        for san in ["e4", "d5", "exd5", "Qxd5", "P@e4"] {
+            game.play_san(san).unwrap();
+        }
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[Variant \"Crazyhouse\"]\n"));
+        assert!(!pgn.contains("[FEN"));
+        let imported = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(imported.board(), game.board());
+    }
+
+    #[rstest]
+    #[case::three_check("Three-check", Variant::ThreeCheck)]
+    #[case::king_of_the_hill("King of the Hill", Variant::KingOfTheHill)]
+    #[case::racing_kings("Racing Kings", Variant::RacingKings)]
+    #[case::from_position("From Position", Variant::Standard)]
+    fn reads_variant_tags(#[case] name: &str, #[case] variant: Variant) {
+        let game = Game::from_pgn(&format!("[Variant \"{name}\"]\n\n*")).unwrap();
+        assert_eq!(game.start(), &Board::start(variant));
+        assert!(!game.is_chess960());
+    }
+
+    #[test]
+    fn chess960_games() {
+        let mut game = Game::from_board(Board::chess960(0).unwrap());
+        assert!(game.is_chess960());
+        game.play_san("g3").unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.contains(
+            "[Variant \"Chess960\"]\n\
+             [SetUp \"1\"]\n\
+             [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n"
+        ));
+        let imported = Game::from_pgn(&pgn).unwrap();
+        assert!(imported.is_chess960());
+        assert_eq!(imported.board(), game.board());
+
+        // The classical setup is only Chess960 when the tag says so
+        let mut game = Game::new();
+        game.set_chess960(true);
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[Variant \"Chess960\"]\n[SetUp \"1\"]\n"));
+        assert!(Game::from_pgn(&pgn).unwrap().is_chess960());
+        assert!(!Game::new().is_chess960());
+    }
+
+    #[test]
+    fn splits_collections() {
+        let collection = format!("{SCHOLARS_MATE}\n[Event \"Next\"]\n\n1. d4 *\n\n1. c4 *\n");
+        let games = split_games(&collection);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].trim_end(), SCHOLARS_MATE.trim_end());
+        assert_eq!(games[1], "[Event \"Next\"]\n\n1. d4 *\n\n1. c4 *\n");
+        assert!(split_games(" \n").is_empty());
+    }
+
+    #[rstest]
+    #[case::duration(Duration::from_secs(3725), "1:02:05")]
+    #[case::millis(Duration::from_millis(1500), "0:00:01.500")]
+    fn emt_round_trip(#[case] duration: Duration, #[case] formatted: &str) {
+        assert_eq!(format_duration(duration), formatted);
+        assert_eq!(parse_emt(&format!("[%emt {formatted}]")), Some(duration));
+    }
+
+    #[rstest]
+    #[case::illegal_move("1. e4 e5 2. Ke3 *")]
+    #[case::bad_tag("[Event Casual]\n\n1. e4 *")]
+    #[case::unterminated_variation("1. e4 (1. d4 *")]
+    fn from_pgn_rejects(#[case] pgn: &str) {
+        assert!(Game::from_pgn(pgn).is_err());
+    }
+}
@@ -1,2 +1,19 @@
 mod board;
+pub mod book;
+mod engine;
+pub mod eval;
+pub mod game;
+pub mod mate;
+mod movepick;
 mod piece;
+pub mod search;
+pub mod syzygy;
+pub mod timeman;
+pub mod tt;
+pub mod uci;
+pub mod variant;
+pub mod xboard;
+mod zobrist;
+
+pub use board::{Board, Colour, Coordinate, Move};
+pub use piece::{Piece, PieceType};
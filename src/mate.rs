@@ -0,0 +1,225 @@
+//! An exhaustive solver for "mate in N" problems, proving or disproving forced mate with the
+//! legal move generator alone, without evaluation or pruning that could miss a defence.
+
+use crate::board::{Board, Move};
+use std::collections::HashSet;
+
+/// An attacking move that forces mate, with every defence against it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MateNode {
+    pub mv: Move,
+    /// Every legal reply, each with a continuation that still mates in time. Empty when `mv`
+    /// itself mates.
+    pub defences: Vec<Defence>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Defence {
+    pub mv: Move,
+    pub continuation: MateNode,
+}
+
+impl MateNode {
+    /// Attacking moves until mate against the most stubborn defence, counting this one.
+    pub fn mate_in(&self) -> u32 {
+        1 + self
+            .defences
+            .iter()
+            .map(|defence| defence.continuation.mate_in())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The solution of a mate problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MateSolution {
+    /// Every first move that forces mate within the limit, fastest first, each with its full
+    /// solution tree.
+    pub keys: Vec<MateNode>,
+}
+
+impl MateSolution {
+    /// A sound problem has a single key. Any other first move that also mates in time is a
+    /// cook.
+    pub fn is_cooked(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    /// The fastest key, with its solution tree.
+    pub fn key(&self) -> &MateNode {
+        &self.keys[0]
+    }
+}
+
+/// Proves that the side to move can force mate in at most `moves` moves, returning every key
+/// and its solution tree, or `None` if there is no forced mate in that many moves.
+pub fn solve_mate(board: &Board, moves: u32) -> Option<MateSolution> {
+    let mut solver = Solver::default();
+    let mut board = board.clone();
+    let mut keys: Vec<_> = board
+        .get_legal_moves()
+        .into_iter()
+        .filter_map(|mv| solver.shortest_mate_after(&mut board, mv, moves))
+        .collect();
+    if keys.is_empty() {
+        return None;
+    }
+    keys.sort_by_key(MateNode::mate_in);
+    Some(MateSolution { keys })
+}
+
+#[derive(Default)]
+struct Solver {
+    /// Positions, with the attacker to move, known to have no mate in the given number of moves.
+    disproved: HashSet<(u64, u32)>,
+}
+
+impl Solver {
+    /// Finds a move that mates in at most `moves` moves, preferring the fastest.
+    fn find_mate(&mut self, board: &mut Board, moves: u32) -> Option<MateNode> {
+        if self.disproved.contains(&(board.hash(), moves)) {
+            return None;
+        }
+        let candidates = board.get_legal_moves();
+        for limit in 1..=moves {
+            for &mv in &candidates {
+                if let Some(node) = self.mate_after(board, mv, limit) {
+                    return Some(node);
+                }
+            }
+        }
+        self.disproved.insert((board.hash(), moves));
+        None
+    }
+
+    fn shortest_mate_after(&mut self, board: &mut Board, mv: Move, moves: u32) -> Option<MateNode> {
+        (1..=moves).find_map(|limit| self.mate_after(board, mv, limit))
+    }
+
+    /// Whether playing `mv` forces mate within `moves` moves, counting `mv`.
+    fn mate_after(&mut self, board: &mut Board, mv: Move, moves: u32) -> Option<MateNode> {
+        board.make_move_unchecked(mv);
+        let node = self.prove(board, mv, moves);
+        board.unmake_move();
+        node
+    }
+
+    /// With `mv` just played, checks every defence has an answer that mates in time.
+    fn prove(&mut self, board: &mut Board, mv: Move, moves: u32) -> Option<MateNode> {
+        let replies = board.get_legal_moves();
+        if replies.is_empty() {
+            // Stalemate is no win.
+            return board.in_check().then_some(MateNode {
+                mv,
+                defences: Vec::new(),
+            });
+        }
+        if moves == 1 {
+            return None;
+        }
+
+        let mut defences = Vec::with_capacity(replies.len());
+        for reply in replies {
+            board.make_move_unchecked(reply);
+            let continuation = self.find_mate(board, moves - 1);
+            board.unmake_move();
+            defences.push(Defence {
+                mv: reply,
+                continuation: continuation?,
+            });
+        }
+        Some(MateNode { mv, defences })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Checks the tree really is a forced mate: every legal reply is answered, and every line
+    /// ends in checkmate within `moves`.
+    fn assert_sound(board: &mut Board, node: &MateNode, moves: u32) {
+        assert!(moves > 0, "{} does not mate in time", node.mv);
+        board.make_move(node.mv).unwrap();
+        let mut replies: Vec<_> = board.get_legal_moves();
+        if replies.is_empty() {
+            assert!(board.in_check());
+        }
+        let mut defended: Vec<_> = node.defences.iter().map(|defence| defence.mv).collect();
+        let key = |mv: &Move| mv.to_string();
+        replies.sort_by_key(key);
+        defended.sort_by_key(key);
+        assert_eq!(replies, defended);
+
+        for defence in &node.defences {
+            board.make_move(defence.mv).unwrap();
+            assert_sound(board, &defence.continuation, moves - 1);
+            board.unmake_move();
+        }
+        board.unmake_move();
+    }
+
+    #[rstest]
+    #[case::back_rank("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1, "a1a8", 1)]
+    #[case::rook_and_king("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2, "c6b6", 2)]
+    #[case::black_to_move("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", 1, "a8a1", 1)]
+    #[case::longer_allowance("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2, "a1a8", 1)]
+    fn solves(#[case] fen: &str, #[case] moves: u32, #[case] key: &str, #[case] mate_in: u32) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let solution = solve_mate(&board, moves).unwrap();
+        assert!(solution.keys.iter().any(|node| node.mv.to_string() == key));
+        assert_eq!(solution.key().mate_in(), mate_in);
+        for node in &solution.keys {
+            assert_sound(&mut board, node, moves);
+        }
+    }
+
+    #[test]
+    fn full_tree_includes_every_defence() {
+        let board = Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let solution = solve_mate(&board, 2).unwrap();
+        let key = solution
+            .keys
+            .iter()
+            .find(|node| node.mv.to_string() == "c6b6")
+            .unwrap();
+        // After Kb6 the king can only go to b8, where Rh8 mates
+        let mut defences: Vec<_> = key.defences.iter().map(|d| d.mv.to_string()).collect();
+        defences.sort();
+        assert_eq!(defences, ["a8b8"]);
+        assert_eq!(key.defences[0].continuation.mv.to_string(), "h1h8");
+        assert!(key.defences[0].continuation.defences.is_empty());
+    }
+
+    #[rstest]
+    #[case::start_position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 2)]
+    #[case::too_few_moves("k7/8/2K5/8/8/8/8/7R w - - 0 1", 1)]
+    #[case::insufficient_material("k7/8/1K6/8/8/8/8/2B5 w - - 0 1", 2)]
+    fn proves_no_mate(#[case] fen: &str, #[case] moves: u32) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(solve_mate(&board, moves), None);
+    }
+
+    #[test]
+    fn flags_cooks() {
+        // Both rooks mate on the back rank
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/RR4K1 w - - 0 1").unwrap();
+        let solution = solve_mate(&board, 1).unwrap();
+        assert!(solution.is_cooked());
+        let mut keys: Vec<_> = solution
+            .keys
+            .iter()
+            .map(|node| node.mv.to_string())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["a1a8", "b1b8"]);
+
+        let sound = solve_mate(
+            &Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap(),
+            1,
+        );
+        assert!(!sound.unwrap().is_cooked());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::board::{Board, Move};
+use crate::eval;
+use crate::piece::PieceType;
+use crate::search::MAX_PLY;
+
+/// History scores are kept within this bound, so a move that was good long ago cannot outweigh
+/// what the search has learned since.
+const MAX_HISTORY: i32 = 16_384;
+
+/// What the search has learned about quiet moves, shared between all the nodes of a search.
+#[derive(Clone, Debug)]
+pub(crate) struct Heuristics {
+    /// Two quiet moves per ply that recently caused a beta cutoff at that ply.
+    killers: Vec<[Option<Move>; 2]>,
+    /// The quiet move that last refuted each move, indexed by the refuted move's piece and
+    /// target square.
+    countermoves: Vec<Option<Move>>,
+    /// Butterfly history, how often each quiet move caused a cutoff, indexed by colour and
+    /// from and to squares.
+    history: Vec<i32>,
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self {
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            countermoves: vec![None; 12 * 64],
+            history: vec![0; 2 * 64 * 64],
+        }
+    }
+}
+
+impl Heuristics {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Prepares for a new search. Killers only make sense for the position they were found
+    /// from, but history is still a good guide, so it is only faded.
+    pub(crate) fn new_search(&mut self) {
+        self.killers.fill([None; 2]);
+        for score in &mut self.history {
+            *score /= 2;
+        }
+    }
+
+    pub(crate) fn killers(&self, ply: u32) -> [Option<Move>; 2] {
+        self.killers[ply as usize]
+    }
+
+    /// The remembered reply to `previous`, the move that led to the current position.
+    pub(crate) fn countermove(&self, previous: Option<&Move>) -> Option<Move> {
+        previous.and_then(|previous| {
+            self.countermoves[previous.piece().index() * 64 + previous.to().index()]
+        })
+    }
+
+    fn history_index(mv: &Move) -> usize {
+        // Drops are kept as if moving from their destination square.
+        let from = mv.from().unwrap_or(mv.to());
+        (mv.piece().colour() as usize * 64 + from.index()) * 64 + mv.to().index()
+    }
+
+    pub(crate) fn history(&self, mv: &Move) -> i32 {
+        self.history[Self::history_index(mv)]
+    }
+
+    /// Rewards the quiet move `best` for causing a beta cutoff at `ply`, and penalises the quiet
+    /// moves in `tried` that were searched before it without success.
+    pub(crate) fn update(
+        &mut self,
+        best: Move,
+        tried: &[Move],
+        previous: Option<&Move>,
+        ply: u32,
+        depth: u32,
+    ) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(best) {
+            killers[1] = killers[0];
+            killers[0] = Some(best);
+        }
+        if let Some(previous) = previous {
+            self.countermoves[previous.piece().index() * 64 + previous.to().index()] = Some(best);
+        }
+
+        let bonus = (depth * depth).min(MAX_HISTORY as u32) as i32;
+        self.add_history(&best, bonus);
+        for mv in tried {
+            self.add_history(mv, -bonus);
+        }
+    }
+
+    fn add_history(&mut self, mv: &Move, bonus: i32) {
+        // Scales the bonus down as the score nears the bound, so it can never be exceeded.
+        let score = &mut self.history[Self::history_index(mv)];
+        *score += bonus - *score * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    FirstKiller,
+    SecondKiller,
+    Countermove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the legal moves of a position one at a time, best guesses first, generating each
+/// group only once the previous one is exhausted so a cutoff early on skips the rest.
+///
+/// The order is: the hash move, captures and promotions that do not lose material by static
+/// exchange evaluation (most valuable victim, least valuable attacker first), the killer moves,
+/// the countermove, the remaining quiet moves by history score, and finally the losing
+/// captures.
+#[derive(Clone, Debug)]
+pub(crate) struct MovePicker {
+    stage: Stage,
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    countermove: Option<Move>,
+    /// Scored moves of the current stage, the ones before `index` have been handed out.
+    moves: Vec<(Move, i32)>,
+    index: usize,
+    bad_captures: Vec<(Move, i32)>,
+    tactical_only: bool,
+}
+
+impl MovePicker {
+    pub(crate) fn new(
+        hash_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        countermove: Option<Move>,
+    ) -> Self {
+        Self {
+            stage: Stage::HashMove,
+            hash_move,
+            killers,
+            countermove,
+            moves: Vec::new(),
+            index: 0,
+            bad_captures: Vec::new(),
+            tactical_only: false,
+        }
+    }
+
+    /// A picker for the quiescence search, producing only the captures and promotions that do
+    /// not lose material.
+    pub(crate) fn quiescence() -> Self {
+        Self {
+            tactical_only: true,
+            ..Self::new(None, [None; 2], None)
+        }
+    }
+
+    pub(crate) fn next(&mut self, board: &Board, heuristics: &Heuristics) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(mv) = self.hash_move
+                        && (!self.tactical_only || mv.is_tactical())
+                        && board.is_legal(&mv)
+                    {
+                        return Some(mv);
+                    }
+                    self.hash_move = None;
+                }
+                Stage::GenerateCaptures => {
+                    self.moves = board
+                        .tactical_moves()
+                        .into_iter()
+                        .map(|mv| (mv, mvv_lva(&mv)))
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match self.select() {
+                    Some((mv, _)) if Some(mv) == self.hash_move => {}
+                    Some((mv, score)) => {
+                        if board.see(&mv) >= 0 {
+                            return Some(mv);
+                        }
+                        self.bad_captures.push((mv, score));
+                    }
+                    None if self.tactical_only => self.stage = Stage::Done,
+                    None => self.stage = Stage::FirstKiller,
+                },
+                Stage::FirstKiller | Stage::SecondKiller => {
+                    let killer = self.killers[(self.stage == Stage::SecondKiller) as usize];
+                    self.stage = if self.stage == Stage::FirstKiller {
+                        Stage::SecondKiller
+                    } else {
+                        Stage::Countermove
+                    };
+                    if let Some(mv) = killer
+                        && self.is_fresh_quiet(&mv)
+                        && board.is_legal(&mv)
+                    {
+                        return Some(mv);
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::GenerateQuiets;
+                    if let Some(mv) = self.countermove
+                        && self.is_fresh_quiet(&mv)
+                        && !self.killers.contains(&Some(mv))
+                        && board.is_legal(&mv)
+                    {
+                        return Some(mv);
+                    }
+                    self.countermove = None;
+                }
+                Stage::GenerateQuiets => {
+                    self.moves = board
+                        .quiet_moves()
+                        .into_iter()
+                        .map(|mv| (mv, heuristics.history(&mv)))
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.select() {
+                    Some((mv, _))
+                        if Some(mv) == self.hash_move
+                            || Some(mv) == self.countermove
+                            || self.killers.contains(&Some(mv)) => {}
+                    Some((mv, _)) => return Some(mv),
+                    None => {
+                        self.moves = std::mem::take(&mut self.bad_captures);
+                        self.index = 0;
+                        self.stage = Stage::BadCaptures;
+                    }
+                },
+                Stage::BadCaptures => match self.select() {
+                    Some((mv, _)) => return Some(mv),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Whether `mv` is a quiet move that has not already been handed out.
+    fn is_fresh_quiet(&self, mv: &Move) -> bool {
+        !mv.is_tactical() && Some(*mv) != self.hash_move
+    }
+
+    /// Takes the best scoring move left in the current stage. Selecting one move at a time is
+    /// cheaper than sorting when a cutoff comes after the first few.
+    fn select(&mut self) -> Option<(Move, i32)> {
+        let remaining = self.moves.get(self.index..)?;
+        let best = (0..remaining.len()).max_by_key(|&i| (remaining[i].1, usize::MAX - i))?;
+        self.moves.swap(self.index, self.index + best);
+        self.index += 1;
+        Some(self.moves[self.index - 1])
+    }
+}
+
+/// Most valuable victim, least valuable attacker: captures of big pieces by small ones first.
+/// Promotions count the value they add as part of the victim.
+fn mvv_lva(mv: &Move) -> i32 {
+    let victim = mv
+        .captured()
+        .map_or(0, |piece| eval::piece_value(piece.piece_type()));
+    let promotion = mv.promotion().map_or(0, |piece| {
+        eval::piece_value(piece.piece_type()) - eval::piece_value(PieceType::Pawn)
+    });
+    let attacker = eval::piece_value(mv.piece().piece_type());
+    (victim + promotion) * 8 - attacker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn find_move(board: &Board, from: &str, to: &str) -> Move {
+        let (from, to) = (from.parse().unwrap(), to.parse().unwrap());
+        board
+            .get_legal_moves()
+            .into_iter()
+            .find(|mv| {
+                mv.from() == Some(from)
+                    && mv.to() == to
+                    && mv
+                        .promotion()
+                        .is_none_or(|p| p.piece_type() == PieceType::Queen)
+            })
+            .unwrap()
+    }
+
+    fn pick_all(board: &Board, mut picker: MovePicker, heuristics: &Heuristics) -> Vec<Move> {
+        let mut moves = Vec::new();
+        while let Some(mv) = picker.next(board, heuristics) {
+            moves.push(mv);
+        }
+        moves
+    }
+
+    #[test]
+    fn yields_every_legal_move_once() {
+        let board = Board::from_fen(KIWIPETE).unwrap();
+        let hash_move = find_move(&board, "e2", "a6");
+        let killer = find_move(&board, "e1", "g1");
+        let countermove = find_move(&board, "a2", "a3");
+        let picker = MovePicker::new(
+            Some(hash_move),
+            [Some(killer), Some(hash_move)],
+            Some(countermove),
+        );
+
+        let mut picked = pick_all(&board, picker, &Heuristics::default());
+        assert_eq!(picked[0], hash_move);
+        // Then the winning captures, followed by the killer and the countermove
+        let killer_index = picked.iter().position(|mv| *mv == killer).unwrap();
+        assert!(picked[1..killer_index].iter().all(Move::is_tactical));
+        assert_eq!(picked[killer_index + 1], countermove);
+        let mut legal = board.get_legal_moves();
+        assert_eq!(picked.len(), legal.len());
+        let key = |mv: &Move| format!("{mv:?}");
+        picked.sort_by_key(key);
+        legal.sort_by_key(key);
+        assert_eq!(picked, legal);
+    }
+
+    #[test]
+    fn skips_remembered_moves_that_are_illegal_here() {
+        let board = Board::new();
+        let other = Board::from_fen(KIWIPETE).unwrap();
+        let foreign = find_move(&other, "e5", "f7");
+        let picker = MovePicker::new(Some(foreign), [Some(foreign), None], Some(foreign));
+        let picked = pick_all(&board, picker, &Heuristics::default());
+        assert_eq!(picked.len(), 20);
+        assert!(!picked.contains(&foreign));
+    }
+
+    #[test]
+    fn captures_come_before_quiets_and_losing_captures_last() {
+        // Qxb7 wins a pawn, Rxd5 loses the rook for one
+        let board = Board::from_fen("4k3/1p6/2p5/3p4/8/8/8/1Q1RK3 w - - 0 1").unwrap();
+        let picked = pick_all(
+            &board,
+            MovePicker::new(None, [None; 2], None),
+            &Heuristics::default(),
+        );
+        assert_eq!(picked[0], find_move(&board, "b1", "b7"));
+        assert_eq!(picked.last(), Some(&find_move(&board, "d1", "d5")));
+    }
+
+    #[test]
+    fn quiescence_only_yields_winning_captures() {
+        let board = Board::from_fen("4k3/1p6/2p5/3p4/8/8/8/1Q1RK3 w - - 0 1").unwrap();
+        let picked = pick_all(&board, MovePicker::quiescence(), &Heuristics::default());
+        assert_eq!(picked, vec![find_move(&board, "b1", "b7")]);
+    }
+
+    #[test]
+    fn history_orders_quiet_moves() {
+        let board = Board::new();
+        let mut heuristics = Heuristics::default();
+        let g1f3 = find_move(&board, "g1", "f3");
+        let e2e4 = find_move(&board, "e2", "e4");
+        heuristics.update(e2e4, &[g1f3], None, 3, 4);
+        assert!(heuristics.history(&e2e4) > 0);
+        assert!(heuristics.history(&g1f3) < 0);
+
+        let picked = pick_all(&board, MovePicker::new(None, [None; 2], None), &heuristics);
+        assert_eq!(picked.first(), Some(&e2e4));
+        assert_eq!(picked.last(), Some(&g1f3));
+    }
+
+    #[test]
+    fn cutoffs_update_killers_and_countermoves() {
+        let mut board = Board::new();
+        let e2e4 = find_move(&board, "e2", "e4");
+        board.make_move(e2e4).unwrap();
+        let b8c6 = find_move(&board, "b8", "c6");
+        let g8f6 = find_move(&board, "g8", "f6");
+
+        let mut heuristics = Heuristics::default();
+        heuristics.update(b8c6, &[], Some(&e2e4), 1, 2);
+        heuristics.update(g8f6, &[], Some(&e2e4), 1, 2);
+        assert_eq!(heuristics.killers(1), [Some(g8f6), Some(b8c6)]);
+        assert_eq!(heuristics.countermove(Some(&e2e4)), Some(g8f6));
+        assert_eq!(heuristics.killers(2), [None; 2]);
+    }
+}
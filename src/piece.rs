@@ -2,7 +2,7 @@ use crate::board::Colour;
 use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum PieceType {
+pub enum PieceType {
     Pawn,
     Knight,
     Bishop,
@@ -25,51 +25,124 @@ impl fmt::Display for PieceType {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct Piece {
+pub struct Piece {
     pub(crate) piece_type: PieceType,
     pub(crate) colour: Colour,
 }
 
 impl Piece {
-    pub(crate) const fn pawn(colour: Colour) -> Self {
+    pub const fn pawn(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::Pawn,
             colour,
         }
     }
 
-    pub(crate) const fn knight(colour: Colour) -> Self {
+    pub const fn knight(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::Knight,
             colour,
         }
     }
 
-    pub(crate) const fn bishop(colour: Colour) -> Self {
+    pub const fn bishop(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::Bishop,
             colour,
         }
     }
 
-    pub(crate) const fn rook(colour: Colour) -> Self {
+    pub const fn rook(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::Rook,
             colour,
         }
     }
 
-    pub(crate) const fn queen(colour: Colour) -> Self {
+    pub const fn queen(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::Queen,
             colour,
         }
     }
 
-    pub(crate) const fn king(colour: Colour) -> Self {
+    pub const fn king(colour: Colour) -> Self {
         Self {
             piece_type: PieceType::King,
             colour,
         }
     }
+
+    pub const fn piece_type(&self) -> PieceType {
+        self.piece_type
+    }
+
+    pub const fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    /// A distinct index in `0..12` for each piece, for tables indexed by piece.
+    pub(crate) const fn index(self) -> usize {
+        self.colour as usize * 6 + self.piece_type as usize
+    }
+
+    /// The piece with the given `index`, the inverse of `Piece::index`.
+    pub(crate) const fn from_index(index: usize) -> Option<Self> {
+        let colour = match index / 6 {
+            0 => Colour::White,
+            1 => Colour::Black,
+            _ => return None,
+        };
+        let piece_type = match index % 6 {
+            0 => PieceType::Pawn,
+            1 => PieceType::Knight,
+            2 => PieceType::Bishop,
+            3 => PieceType::Rook,
+            4 => PieceType::Queen,
+            _ => PieceType::King,
+        };
+        Some(Self { piece_type, colour })
+    }
+
+    /// Whether the piece moves along rays rather than by fixed steps.
+    pub(crate) const fn is_slider(&self) -> bool {
+        matches!(
+            self.piece_type,
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen
+        )
+    }
+
+    /// Parses a FEN piece letter, upper case for white and lower case for black.
+    pub(crate) fn from_fen_char(c: char) -> Option<Self> {
+        let piece_type = match c.to_ascii_lowercase() {
+            'p' => PieceType::Pawn,
+            'n' => PieceType::Knight,
+            'b' => PieceType::Bishop,
+            'r' => PieceType::Rook,
+            'q' => PieceType::Queen,
+            'k' => PieceType::King,
+            _ => return None,
+        };
+        let colour = if c.is_ascii_uppercase() {
+            Colour::White
+        } else {
+            Colour::Black
+        };
+        Some(Self { piece_type, colour })
+    }
+
+    pub(crate) fn to_fen_char(self) -> char {
+        let c = match self.piece_type {
+            PieceType::Pawn => 'p',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+        };
+        match self.colour {
+            Colour::White => c.to_ascii_uppercase(),
+            Colour::Black => c,
+        }
+    }
 }
@@ -0,0 +1,1274 @@
+use crate::board::{Board, Move};
+use crate::eval;
+use crate::game::Outcome;
+use crate::movepick::{Heuristics, MovePicker};
+use crate::piece::PieceType;
+use crate::syzygy::{Tablebase, Wdl};
+use crate::timeman::{Clock, TimeManager};
+use crate::tt::{Bound, TranspositionTable};
+use std::cmp::Reverse;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Deepest ply the search will ever reach, bounding mate distances and the iterative deepening
+/// loop.
+pub const MAX_PLY: u32 = 128;
+/// Larger than any score the search can return.
+pub const INFINITY: i32 = 32_000;
+/// Score for delivering mate on the current move. Mates further away score `MATE - ply`.
+pub const MATE: i32 = 31_000;
+/// Any score at or beyond this magnitude is a forced mate.
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+/// Score for a position the tablebases say is won, below any mate. Wins found further from the
+/// root score `TB_WIN - ply`, so the search still heads for the nearest.
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32 - 1;
+
+/// How long to search for. The search stops at whichever limit is reached first, and runs to
+/// `MAX_PLY` if none are set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// A fixed time for this move.
+    pub time: Option<Duration>,
+    /// Playing on a clock, the time manager decides how long to think.
+    pub clock: Option<Clock>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+
+    pub fn time(time: Duration) -> Self {
+        Self {
+            time: Some(time),
+            ..Default::default()
+        }
+    }
+
+    pub fn clock(clock: Clock) -> Self {
+        Self {
+            clock: Some(clock),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    /// `None` only when the side to move has no legal moves.
+    pub best_move: Option<Move>,
+    /// Centipawns from the side to move's perspective, or a mate score.
+    pub score: i32,
+    /// The deepest fully completed iteration.
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// The best lines found, best first. The first matches `score` and `pv`, there are more
+    /// only with MultiPV.
+    pub lines: Vec<PvLine>,
+    /// Time spent searching so far.
+    pub elapsed: Duration,
+}
+
+/// One of the lines found by a MultiPV search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PvLine {
+    /// Centipawns from the side to move's perspective, or a mate score.
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+impl PvLine {
+    /// Moves until mate, as in `SearchResult::mate_in`.
+    pub fn mate_in(&self) -> Option<i32> {
+        mate_in(self.score)
+    }
+}
+
+impl SearchResult {
+    /// Nodes searched per second.
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1000 / self.elapsed.as_millis().max(1)) as u64
+    }
+
+    /// Moves until mate if the score is a forced mate, positive when the side to move is
+    /// mating and negative when it is being mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        mate_in(self.score)
+    }
+}
+
+/// Converts a mate score into full moves until mate, signed as in `SearchResult::mate_in`.
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// Which selective search techniques are enabled. Everything is on by default, the switches exist
+/// to measure what each is worth in self-play.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Skip the turn at reduced depth, and cut off if the opponent still cannot get back below
+    /// beta. Never with only pawns left, where passing could be better than any move.
+    pub null_move: bool,
+    /// Search quiet moves late in the move order less deeply, unless they prove to be good.
+    pub late_move_reductions: bool,
+    /// Near the horizon, skip quiet moves that cannot bring the evaluation up to alpha.
+    pub futility: bool,
+    /// Near the horizon, cut off when the evaluation is above beta by a safe margin.
+    pub reverse_futility: bool,
+    /// Search a ply deeper when in check.
+    pub check_extensions: bool,
+    /// Search each iteration in a narrow window around the previous score, widening on failure.
+    pub aspiration_windows: bool,
+}
+
+impl SearchOptions {
+    /// Plain alpha-beta with quiescence search.
+    pub fn none() -> Self {
+        Self {
+            null_move: false,
+            late_move_reductions: false,
+            futility: false,
+            reverse_futility: false,
+            check_extensions: false,
+            aspiration_windows: false,
+        }
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            futility: true,
+            reverse_futility: true,
+            check_extensions: true,
+            aspiration_windows: true,
+        }
+    }
+}
+
+/// Futility margins per ply of remaining depth.
+const FUTILITY_MARGIN: i32 = 150;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+/// Futility and reverse futility pruning only apply this close to the horizon.
+const FUTILITY_DEPTH: u32 = 3;
+const REVERSE_FUTILITY_DEPTH: u32 = 6;
+const NULL_MOVE_DEPTH: u32 = 3;
+const LMR_DEPTH: u32 = 3;
+/// Moves searched at full depth before late move reductions start.
+const LMR_FULL_DEPTH_MOVES: u32 = 3;
+const ASPIRATION_DEPTH: u32 = 4;
+/// Half the width of the first aspiration window, doubled on every failure.
+const ASPIRATION_WINDOW: i32 = 25;
+
+/// Nodes are added to the total shared between threads in batches of this size, which is also
+/// how often the clock and stop flags are checked.
+const NODE_BATCH: u64 = 1024;
+
+/// An alpha-beta searcher. Keeping one around between searches lets it reuse what it learned,
+/// its transposition table carries over from one move of a game to the next.
+///
+/// With more than one thread it runs a lazy SMP search: helper threads search the same position
+/// alongside the main thread, sharing only the transposition table. They start at staggered
+/// depths, so their move ordering diverges and they fill the table with results the main thread
+/// can use. Only the main thread's result is reported. A single thread searches
+/// deterministically, a given position and set of limits always giving the same result.
+#[derive(Debug)]
+pub struct Searcher {
+    tt: TranspositionTable,
+    /// The main thread's move ordering heuristics, kept between searches.
+    heuristics: Heuristics,
+    threads: usize,
+    multi_pv: usize,
+    options: SearchOptions,
+    tablebase: Option<Arc<Tablebase>>,
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Searcher {
+    pub const MAX_THREADS: usize = 256;
+
+    pub fn new() -> Self {
+        Self::with_hash_size(TranspositionTable::DEFAULT_SIZE_MB)
+    }
+
+    /// Creates a searcher whose transposition table uses `size_mb` megabytes.
+    pub fn with_hash_size(size_mb: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(size_mb),
+            heuristics: Heuristics::default(),
+            threads: 1,
+            multi_pv: 1,
+            options: SearchOptions::default(),
+            tablebase: None,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Resizes the transposition table, discarding its contents.
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt.resize(size_mb);
+    }
+
+    /// Sets how many threads search in parallel, clamped to `1..=MAX_THREADS`.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, Self::MAX_THREADS);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Sets how many of the best root moves to find lines for, at least one. Each line is
+    /// searched with the moves of the lines before it excluded at the root.
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.max(1);
+    }
+
+    pub fn multi_pv(&self) -> usize {
+        self.multi_pv
+    }
+
+    pub fn set_options(&mut self, options: SearchOptions) {
+        self.options = options;
+    }
+
+    pub fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    /// Endgame tablebases to consult. At a root they cover, only the moves that keep the best
+    /// result within the fifty-move rule are searched. In the tree, positions they cover score
+    /// their result exactly, probed right after captures and pawn moves, when the fifty-move
+    /// counter is back to zero.
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    pub fn tablebase(&self) -> Option<&Arc<Tablebase>> {
+        self.tablebase.as_ref()
+    }
+
+    /// Forgets everything learned so far, for when a new game starts.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.heuristics.clear();
+    }
+
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    /// A flag that stops the search in progress when set, from any thread. It is not cleared by
+    /// the search, so clear it before starting the next one.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop_flag)
+    }
+
+    /// Searches `board` by iterative deepening until a limit is hit, returning the result of
+    /// the deepest completed iteration. The board is restored before returning.
+    pub fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        self.search_with(board, limits, |_| {})
+    }
+
+    /// Like `search`, calling `on_iteration` with the result of each completed iteration, e.g.
+    /// to report progress to a GUI. Node counts include every thread.
+    pub fn search_with(
+        &mut self,
+        board: &mut Board,
+        limits: SearchLimits,
+        on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.tt.new_search();
+        self.heuristics.new_search();
+        let mut time_manager = limits.clock.as_ref().map(TimeManager::new);
+        let tablebase = self.tablebase.as_deref();
+        let shared = SharedState {
+            tt: &self.tt,
+            options: self.options,
+            tablebase,
+            root_moves: tablebase
+                .filter(|tablebase| tablebase.covers(board))
+                .and_then(|tablebase| tablebase.root_moves(board).ok())
+                .filter(|moves| !moves.is_empty()),
+            limits,
+            time_limit: limits
+                .time
+                .into_iter()
+                .chain(time_manager.as_ref().map(TimeManager::hard_limit))
+                .min(),
+            start: Instant::now(),
+            stop_flag: &self.stop_flag,
+            finished: AtomicBool::new(false),
+            nodes: AtomicU64::new(0),
+        };
+        let max_depth = limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
+
+        let heuristics = mem::take(&mut self.heuristics);
+        let (mut result, heuristics) = thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.threads)
+                .map(|index| {
+                    let shared = &shared;
+                    let mut board = board.clone();
+                    scope.spawn(move || {
+                        let mut helper = Worker::new(shared, Heuristics::default());
+                        // Half the helpers start a ply deeper than the main thread.
+                        let first_depth = (1 + index as u32 % 2).min(max_depth);
+                        let mut score = None;
+                        for depth in first_depth..=max_depth {
+                            score = Some(helper.search_root(&mut board, depth, score).0);
+                            if helper.stopped {
+                                break;
+                            }
+                        }
+                        helper.nodes
+                    })
+                })
+                .collect();
+
+            let mut main = Worker::new(&shared, heuristics);
+            let mut result = main.iterative_deepening(
+                board,
+                max_depth,
+                self.multi_pv,
+                time_manager.as_mut(),
+                on_iteration,
+            );
+            shared.finished.store(true, Ordering::Relaxed);
+            for helper in helpers {
+                result.nodes += helper.join().expect("a search thread panicked");
+            }
+            (result, main.heuristics)
+        });
+        self.heuristics = heuristics;
+        result.elapsed = shared.start.elapsed();
+        result
+    }
+}
+
+/// What the threads of one search share.
+struct SharedState<'a> {
+    tt: &'a TranspositionTable,
+    options: SearchOptions,
+    tablebase: Option<&'a Tablebase>,
+    /// The root moves the tablebases allow, `None` to search them all.
+    root_moves: Option<Vec<Move>>,
+    limits: SearchLimits,
+    /// The tighter of the fixed move time and the time manager's hard limit.
+    time_limit: Option<Duration>,
+    start: Instant,
+    /// Set from outside to stop the search.
+    stop_flag: &'a AtomicBool,
+    /// Set once the main thread is done, to stop the helpers.
+    finished: AtomicBool,
+    /// Nodes searched by all threads, added in batches of `NODE_BATCH`.
+    nodes: AtomicU64,
+}
+
+/// One search thread.
+struct Worker<'a> {
+    shared: &'a SharedState<'a>,
+    heuristics: Heuristics,
+    nodes: u64,
+    stopped: bool,
+    root_depth: u32,
+    // The principal variation of the last completed iteration, searched first in the next.
+    prev_pv: Vec<Move>,
+    /// Root moves skipped because earlier MultiPV lines already start with them.
+    excluded_root_moves: Vec<Move>,
+    /// The ply of the last null move on the current line, so two are never played in a row.
+    null_move_ply: Option<u32>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(shared: &'a SharedState<'a>, heuristics: Heuristics) -> Self {
+        Self {
+            shared,
+            heuristics,
+            nodes: 0,
+            stopped: false,
+            root_depth: 0,
+            prev_pv: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            null_move_ply: None,
+        }
+    }
+
+    /// The main thread's iterative deepening loop, which decides when the search is over.
+    fn iterative_deepening(
+        &mut self,
+        board: &mut Board,
+        max_depth: u32,
+        multi_pv: usize,
+        mut time_manager: Option<&mut TimeManager>,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let mut root_moves = board.get_legal_moves();
+        root_moves.retain(|mv| self.allowed_at_root(mv));
+        // A checkmated or stalemated root still gets one line, with no moves.
+        let multi_pv = multi_pv.min(root_moves.len()).max(1);
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+            elapsed: Duration::ZERO,
+        };
+
+        for depth in 1..=max_depth {
+            let mut lines = Vec::with_capacity(multi_pv);
+            for index in 0..multi_pv {
+                let previous = result.lines.get(index);
+                if let Some(previous) = previous {
+                    self.prev_pv.clone_from(&previous.pv);
+                }
+                let (score, pv) = self.search_root(board, depth, previous.map(|line| line.score));
+                if self.stopped {
+                    break;
+                }
+                self.excluded_root_moves.extend(pv.first());
+                lines.push(PvLine { score, pv });
+            }
+            self.excluded_root_moves.clear();
+            // An interrupted iteration has only looked at some root moves, so its result cannot
+            // be trusted over the previous one.
+            if self.stopped {
+                break;
+            }
+            // A later line can score better than an earlier one, having been searched with a
+            // fuller transposition table.
+            lines.sort_by_key(|line| Reverse(line.score));
+
+            let best = &lines[0];
+            result.best_move = best.pv.first().copied().or(result.best_move);
+            result.score = best.score;
+            result.depth = depth;
+            result.pv.clone_from(&best.pv);
+            result.lines = lines;
+            result.nodes = self.total_nodes();
+            result.elapsed = self.shared.start.elapsed();
+            on_iteration(&result);
+
+            // No deeper search can find a shorter mate.
+            if result.lines.iter().all(|line| {
+                line.mate_in()
+                    .is_some_and(|moves| moves.unsigned_abs() * 2 <= depth)
+            }) {
+                break;
+            }
+            if let Some(time_manager) = &mut time_manager {
+                time_manager.update(result.best_move, result.score);
+                if !time_manager.should_continue(result.elapsed) {
+                    break;
+                }
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    /// Searches the root position to `depth`, returning the score and principal variation.
+    /// `expected` is the score of the previous iteration, to centre an aspiration window on.
+    fn search_root(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        expected: Option<i32>,
+    ) -> (i32, Vec<Move>) {
+        self.root_depth = depth;
+        let mut pv = Vec::new();
+        let expected = expected.filter(|score| {
+            self.shared.options.aspiration_windows
+                && depth >= ASPIRATION_DEPTH
+                && score.abs() < MATE_BOUND
+        });
+        let Some(expected) = expected else {
+            let score = self.negamax(board, depth, 0, -INFINITY, INFINITY, &mut pv);
+            if !self.stopped {
+                self.prev_pv.clone_from(&pv);
+            }
+            return (score, pv);
+        };
+
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = expected - delta;
+        let mut beta = expected + delta;
+        let score = loop {
+            let score = self.negamax(board, depth, 0, alpha, beta, &mut pv);
+            if self.stopped {
+                break score;
+            }
+            // Widen only the side that failed, the other bound still holds.
+            delta *= 2;
+            if score <= alpha {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                break score;
+            }
+        };
+        if !self.stopped {
+            self.prev_pv.clone_from(&pv);
+        }
+        (score, pv)
+    }
+
+    fn allowed_at_root(&self, mv: &Move) -> bool {
+        !self.excluded_root_moves.contains(mv)
+            && self
+                .shared
+                .root_moves
+                .as_ref()
+                .is_none_or(|moves| moves.contains(mv))
+    }
+
+    /// The tablebase score of `board`, if it is in the tables. Only probed with the fifty-move
+    /// counter at zero, where a win the rule does not spoil is a win.
+    fn probe_tablebase(&self, board: &Board, ply: u32) -> Option<i32> {
+        let tablebase = self.shared.tablebase?;
+        if board.halfmove_clock() != 0 || !tablebase.covers(board) {
+            return None;
+        }
+        Some(match tablebase.probe_wdl(board).ok()? {
+            Wdl::Win => TB_WIN - ply as i32,
+            Wdl::Loss => -TB_WIN + ply as i32,
+            Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => 0,
+        })
+    }
+
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODE_BATCH) {
+            self.shared.nodes.fetch_add(NODE_BATCH, Ordering::Relaxed);
+        }
+    }
+
+    /// Nodes searched by every thread so far, counting this thread's exactly.
+    fn total_nodes(&self) -> u64 {
+        self.shared.nodes.load(Ordering::Relaxed) + self.nodes % NODE_BATCH
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped {
+            let shared = self.shared;
+            let out_of_nodes = shared
+                .limits
+                .nodes
+                .is_some_and(|nodes| self.total_nodes() >= nodes);
+            // Reading the clock or the shared flags on every node is measurable, so only do it
+            // periodically.
+            let interrupted = self.nodes.is_multiple_of(NODE_BATCH)
+                && (shared.stop_flag.load(Ordering::Relaxed)
+                    || shared.finished.load(Ordering::Relaxed)
+                    || shared
+                        .time_limit
+                        .is_some_and(|time| shared.start.elapsed() >= time));
+            self.stopped = out_of_nodes || interrupted;
+        }
+        self.stopped
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        let options = self.shared.options;
+        let in_check = board.in_check();
+        if in_check && options.check_extensions {
+            depth += 1;
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+        // Always finish depth 1 so there is a move to play, whatever the limits.
+        if self.root_depth > 1 && self.should_stop() {
+            return 0;
+        }
+        self.count_node();
+
+        if ply > 0
+            && let Some(outcome) = board.variant().outcome(board)
+        {
+            return outcome_score(board, outcome, ply);
+        }
+        // A position repeated once inside the tree, or since the root, is scored as the draw
+        // either side could force by repeating it again.
+        if ply > 0 && (board.halfmove_clock() >= 100 || board.is_repetition()) {
+            return 0;
+        }
+
+        // Mate distance pruning: no line from here can beat a mate already found nearer the root.
+        alpha = alpha.max(-MATE + ply as i32);
+        beta = beta.min(MATE - ply as i32 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+
+        let original_alpha = alpha;
+        let tt_entry = self.shared.tt.probe(board.hash(), ply);
+        if let Some(entry) = tt_entry
+            && ply > 0
+            && entry.depth >= depth
+        {
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            if cutoff {
+                if entry.bound == Bound::Exact {
+                    pv.extend(self.tt_line(board, depth));
+                }
+                return entry.score;
+            }
+        }
+        if ply > 0
+            && let Some(score) = self.probe_tablebase(board, ply)
+        {
+            return score;
+        }
+
+        // Only nodes searched with a null window can be pruned, the principal variation is always
+        // searched in full.
+        let is_pv = beta - alpha > 1;
+        let static_eval = (!is_pv && !in_check).then(|| eval::evaluate(board));
+        if let Some(static_eval) = static_eval
+            && options.reverse_futility
+            && depth <= REVERSE_FUTILITY_DEPTH
+            && beta.abs() < MATE_BOUND
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+
+        if let Some(static_eval) = static_eval
+            && options.null_move
+            && ply > 0
+            && depth >= NULL_MOVE_DEPTH
+            && static_eval >= beta
+            && self.null_move_ply.is_none_or(|null_ply| null_ply + 1 < ply)
+            && has_non_pawn_material(board)
+        {
+            let reduction = 2 + depth / 4;
+            let previous_null_move = self.null_move_ply.replace(ply);
+            let undo = board.make_null_move();
+            let score = -self.negamax(
+                board,
+                depth.saturating_sub(1 + reduction),
+                ply + 1,
+                -beta,
+                -beta + 1,
+                &mut Vec::new(),
+            );
+            board.unmake_null_move(undo);
+            self.null_move_ply = previous_null_move;
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                // A mate found after passing is not to be trusted.
+                return if score >= MATE_BOUND { beta } else { score };
+            }
+        }
+        let futile = static_eval.is_some_and(|static_eval| {
+            options.futility
+                && depth <= FUTILITY_DEPTH
+                && alpha.abs() < MATE_BOUND
+                && static_eval + FUTILITY_MARGIN * depth as i32 <= alpha
+        });
+
+        // Search the hash move first, falling back to the previous iteration's PV.
+        let hash_move = tt_entry
+            .and_then(|entry| entry.best_move)
+            .or_else(|| self.prev_pv.get(ply as usize).copied());
+        let previous = board.move_list().last().copied();
+        let mut picker = MovePicker::new(
+            hash_move,
+            self.heuristics.killers(ply),
+            self.heuristics.countermove(previous.as_ref()),
+        );
+
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        let mut quiets_tried = Vec::new();
+        let mut legal_moves = 0;
+        while let Some(mv) = picker.next(board, &self.heuristics) {
+            if ply == 0 && !self.allowed_at_root(&mv) {
+                continue;
+            }
+            legal_moves += 1;
+            board.make_move_unchecked(mv);
+            let quiet = !mv.is_tactical() && !board.in_check();
+            if futile && quiet && legal_moves > 1 {
+                board.unmake_move();
+                continue;
+            }
+
+            let reduce = options.late_move_reductions
+                && quiet
+                && !in_check
+                && depth >= LMR_DEPTH
+                && legal_moves > LMR_FULL_DEPTH_MOVES;
+            let mut score = 0;
+            if reduce {
+                let reduction = if legal_moves > 2 * LMR_FULL_DEPTH_MOVES && depth >= 6 {
+                    2
+                } else {
+                    1
+                };
+                score = -self.negamax(
+                    board,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    &mut child_pv,
+                );
+            }
+            // Without a reduction, or when the reduced search suggests the move is good after all.
+            if !reduce || (score > alpha && !self.stopped) {
+                score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            }
+            board.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend_from_slice(&child_pv);
+                    if alpha >= beta {
+                        if !mv.is_tactical() {
+                            self.heuristics.update(
+                                mv,
+                                &quiets_tried,
+                                previous.as_ref(),
+                                ply,
+                                depth,
+                            );
+                        }
+                        break;
+                    }
+                }
+            }
+            if !mv.is_tactical() {
+                quiets_tried.push(mv);
+            }
+        }
+
+        if legal_moves == 0 {
+            return outcome_score(board, board.variant().no_moves_outcome(board), ply);
+        }
+
+        // With root moves excluded the score is not the position's, and must not be stored.
+        if ply == 0 && !self.excluded_root_moves.is_empty() {
+            return best;
+        }
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.shared
+            .tt
+            .store(board.hash(), depth, bound, best, best_move, ply);
+        best
+    }
+
+    /// Searches captures and promotions until the position is quiet, so the static evaluation is
+    /// never taken in the middle of an exchange. Captures that lose material by static exchange
+    /// evaluation are skipped, and when in check every evasion is searched instead.
+    fn quiescence(&mut self, board: &mut Board, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if self.root_depth > 1 && self.should_stop() {
+            return 0;
+        }
+        self.count_node();
+
+        if ply >= MAX_PLY {
+            return eval::evaluate(board);
+        }
+        if let Some(outcome) = board.variant().outcome(board) {
+            return outcome_score(board, outcome, ply);
+        }
+
+        let in_check = board.in_check();
+        let mut best = -INFINITY;
+        // In check every evasion is searched, there may be no capture that saves the king.
+        let mut picker = if in_check {
+            MovePicker::new(None, [None; 2], None)
+        } else {
+            // Stand pat: the side to move is assumed to be able to do at least as well as the
+            // static evaluation by playing a quiet move.
+            best = eval::evaluate(board);
+            if best >= beta {
+                return best;
+            }
+            alpha = alpha.max(best);
+            MovePicker::quiescence()
+        };
+
+        while let Some(mv) = picker.next(board, &self.heuristics) {
+            board.make_move_unchecked(mv);
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha);
+            board.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if in_check && best == -INFINITY {
+            return outcome_score(board, board.variant().no_moves_outcome(board), ply);
+        }
+        best
+    }
+
+    /// Follows hash moves from the current position to rebuild the principal variation below an
+    /// exact transposition table hit.
+    fn tt_line(&self, board: &mut Board, depth: u32) -> Vec<Move> {
+        let mut line = Vec::new();
+        while (line.len() as u32) < depth {
+            let Some(mv) = self
+                .shared
+                .tt
+                .probe(board.hash(), 0)
+                .and_then(|entry| entry.best_move)
+                .filter(|mv| board.is_legal(mv))
+            else {
+                break;
+            };
+            board.make_move_unchecked(mv);
+            line.push(mv);
+        }
+        for _ in &line {
+            board.unmake_move();
+        }
+        line
+    }
+}
+
+/// The score of a game that ended `ply` plies from the root, from the side to move's point of
+/// view. Wins and losses score as mates do, so the nearest win and the furthest loss are preferred.
+fn outcome_score(board: &Board, outcome: Outcome, ply: u32) -> i32 {
+    match outcome.result.winner() {
+        Some(winner) if winner == board.turn() => MATE - ply as i32,
+        Some(_) => -MATE + ply as i32,
+        None => 0,
+    }
+}
+
+/// Whether the side to move has a piece other than pawns and the king. Without one, zugzwang is
+/// common enough that null move pruning cannot be trusted.
+fn has_non_pawn_material(board: &Board) -> bool {
+    board.into_iter().flatten().any(|(_, piece)| {
+        piece.colour == board.turn()
+            && !matches!(piece.piece_type, PieceType::Pawn | PieceType::King)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Variant;
+    use rstest::rstest;
+
+    fn search_fen(fen: &str, limits: SearchLimits) -> SearchResult {
+        let mut board = Board::from_fen(fen).unwrap();
+        let before = board.clone();
+        let result = Searcher::new().search(&mut board, limits);
+        assert_eq!(board, before, "search must restore the board");
+        result
+    }
+
+    #[rstest]
+    #[case::back_rank("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1", "a8", 1)]
+    #[case::scholars_mate(
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        "h5",
+        "f7",
+        1
+    )]
+    #[case::queen_and_king("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1", "b1", "b8", 1)]
+    fn finds_mate(#[case] fen: &str, #[case] from: &str, #[case] to: &str, #[case] moves: i32) {
+        let result = search_fen(fen, SearchLimits::depth(4));
+        let best = result.best_move.unwrap();
+        assert_eq!(best.from(), Some(from.parse().unwrap()));
+        assert_eq!(best.to(), to.parse().unwrap());
+        assert_eq!(result.mate_in(), Some(moves));
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        let result = search_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", SearchLimits::depth(4));
+        assert_eq!(result.mate_in(), Some(2));
+        assert_eq!(result.pv.len(), 3);
+    }
+
+    #[test]
+    fn reports_being_mated() {
+        // 1... Kb8 2. Rh8#
+        let result = search_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1", SearchLimits::depth(4));
+        assert_eq!(result.mate_in(), Some(-1));
+        assert_eq!(result.pv.len(), 2);
+    }
+
+    #[rstest]
+    // Black's queen is hanging, but checking again wins outright
+    #[case::third_check(
+        Variant::ThreeCheck,
+        "4k3/8/8/8/8/8/q7/4K2R w - - 0 1 +2+0",
+        "h1",
+        "h8"
+    )]
+    #[case::king_of_the_hill(Variant::KingOfTheHill, "r3k3/8/8/8/8/4K3/8/8 w - - 0 1", "e3", "e4")]
+    #[case::explosion(Variant::Atomic, "k3r3/1p6/8/8/8/8/6B1/4K3 w - - 0 1", "g2", "b7")]
+    // Giving up the last piece wins
+    #[case::antichess(Variant::Antichess, "8/8/8/8/8/8/1p6/R7 w - - 0 1", "a1", "c1")]
+    #[case::racing_kings(Variant::RacingKings, "1N6/K7/8/8/8/8/8/7k w - - 0 1", "a7", "a8")]
+    fn finds_variant_wins(
+        #[case] variant: Variant,
+        #[case] fen: &str,
+        #[case] from: &str,
+        #[case] to: &str,
+    ) {
+        let mut board = Board::from_variant_fen(variant, fen).unwrap();
+        let result = Searcher::new().search(&mut board, SearchLimits::depth(3));
+        let best = result.best_move.unwrap();
+        assert_eq!(best.from(), Some(from.parse().unwrap()));
+        assert_eq!(best.to(), to.parse().unwrap());
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn repeats_to_save_a_lost_position() {
+        let mut board = Board::from_fen("6k1/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+        for uci in ["b1c1", "g8h8", "c1b1"] {
+            board.make_move(board.parse_move(uci).unwrap()).unwrap();
+        }
+        // Only going back to g8 repeats a position, everything else loses to the queen
+        let result = Searcher::new().search(&mut board, SearchLimits::depth(3));
+        assert_eq!(result.best_move.unwrap().to_string(), "h8g8");
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn captures_hanging_queen() {
+        let result = search_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", SearchLimits::depth(2));
+        assert_eq!(result.best_move.unwrap().to(), "d5".parse().unwrap());
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn quiescence_sees_the_recapture() {
+        // At depth 1 Qxd5 wins a pawn, until cxd5 is searched
+        let result = search_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", SearchLimits::depth(1));
+        assert_ne!(result.best_move.unwrap().to(), "d5".parse().unwrap());
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn no_move_when_checkmated() {
+        let result = search_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", SearchLimits::depth(3));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE);
+    }
+
+    #[test]
+    fn stalemate_scores_zero() {
+        let result = search_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", SearchLimits::depth(3));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn principal_variation_is_playable() {
+        let mut board = Board::default();
+        let result = Searcher::new().search(&mut board, SearchLimits::depth(3));
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.first().copied(), result.best_move);
+        for mv in result.pv {
+            board.make_move(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn node_limit_is_respected() {
+        let result = search_fen(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            SearchLimits::nodes(2_000),
+        );
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 2_000 + 64);
+    }
+
+    #[test]
+    fn time_limit_is_respected() {
+        let start = Instant::now();
+        let result = search_fen(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            SearchLimits::time(Duration::from_millis(100)),
+        );
+        assert!(result.best_move.is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn clock_is_never_overstepped() {
+        let start = Instant::now();
+        let clock = Clock::new(Duration::from_millis(400), Duration::ZERO);
+        let result = search_fen(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            SearchLimits::clock(clock),
+        );
+        assert!(result.best_move.is_some());
+        assert!(start.elapsed() < clock.remaining);
+    }
+
+    #[test]
+    fn table_survives_between_searches() {
+        let mut board = Board::default();
+        let mut searcher = Searcher::new();
+        let first = searcher.search(&mut board, SearchLimits::depth(3));
+        let second = searcher.search(&mut board, SearchLimits::depth(3));
+        assert_eq!(first.score, second.score);
+        assert!(second.nodes < first.nodes);
+
+        searcher.clear();
+        assert_eq!(searcher.tt().hashfull(), 0);
+    }
+
+    #[test]
+    fn single_thread_is_deterministic() {
+        let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+        let first = search_fen(fen, SearchLimits::nodes(5_000));
+        let second = search_fen(fen, SearchLimits::nodes(5_000));
+        assert_eq!(
+            (
+                first.best_move,
+                first.score,
+                first.depth,
+                first.nodes,
+                first.pv
+            ),
+            (
+                second.best_move,
+                second.score,
+                second.depth,
+                second.nodes,
+                second.pv
+            )
+        );
+    }
+
+    #[test]
+    fn helper_threads_share_the_work() {
+        let mut board = Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let before = board.clone();
+        let mut searcher = Searcher::new();
+        searcher.set_threads(4);
+        let result = searcher.search(&mut board, SearchLimits::depth(6));
+        assert_eq!(board, before);
+        assert_eq!(result.mate_in(), Some(2));
+        assert_eq!(result.pv.len(), 3);
+
+        // Helpers count towards node limits
+        let mut board = Board::default();
+        let result = searcher.search(&mut board, SearchLimits::nodes(10_000));
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 10_000 + 4 * NODE_BATCH);
+    }
+
+    #[test]
+    fn multi_pv_ranks_distinct_root_moves() {
+        let mut board = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new();
+        searcher.set_multi_pv(3);
+        let result = searcher.search(&mut board, SearchLimits::depth(3));
+
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv, result.pv);
+        assert_eq!(result.lines[0].score, result.score);
+        assert_eq!(result.best_move.unwrap().to(), "d5".parse().unwrap());
+        let firsts: Vec<_> = result.lines.iter().map(|line| line.pv[0]).collect();
+        assert!(firsts[1..].iter().all(|mv| *mv != firsts[0]) && firsts[1] != firsts[2]);
+        assert!(
+            result
+                .lines
+                .windows(2)
+                .all(|pair| pair[0].score >= pair[1].score)
+        );
+        assert!(result.lines[1].score < result.score - 500);
+    }
+
+    #[test]
+    fn multi_pv_is_limited_by_legal_moves() {
+        let mut board = Board::from_fen("k7/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new();
+        searcher.set_multi_pv(5);
+        let result = searcher.search(&mut board, SearchLimits::depth(2));
+        assert_eq!(result.lines.len(), 3);
+
+        searcher.set_multi_pv(1);
+        let result = searcher.search(&mut board, SearchLimits::depth(2));
+        assert_eq!(result.lines.len(), 1);
+    }
+
+    #[test]
+    fn selectivity_searches_deeper() {
+        let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut plain = Searcher::new();
+        plain.set_options(SearchOptions::none());
+        let plain = plain.search(&mut board, SearchLimits::nodes(8_000));
+        let selective = Searcher::new().search(&mut board, SearchLimits::nodes(8_000));
+        assert!(
+            selective.depth > plain.depth,
+            "{} <= {}",
+            selective.depth,
+            plain.depth
+        );
+    }
+
+    #[rstest]
+    #[case::plain(SearchOptions::none())]
+    #[case::null_move(SearchOptions { null_move: true, ..SearchOptions::none() })]
+    #[case::late_move_reductions(SearchOptions { late_move_reductions: true, ..SearchOptions::none() })]
+    #[case::futility(SearchOptions { futility: true, ..SearchOptions::none() })]
+    #[case::reverse_futility(SearchOptions { reverse_futility: true, ..SearchOptions::none() })]
+    #[case::check_extensions(SearchOptions { check_extensions: true, ..SearchOptions::none() })]
+    #[case::aspiration_windows(SearchOptions { aspiration_windows: true, ..SearchOptions::none() })]
+    #[case::all(SearchOptions::default())]
+    fn options_keep_the_search_sound(#[case] options: SearchOptions) {
+        let mut searcher = Searcher::new();
+        searcher.set_options(options);
+        let mut board = Board::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let result = searcher.search(&mut board, SearchLimits::depth(6));
+        assert_eq!(result.mate_in(), Some(2));
+
+        let mut board = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let result = searcher.search(&mut board, SearchLimits::depth(5));
+        assert_eq!(result.best_move.unwrap().to(), "d5".parse().unwrap());
+    }
+
+    #[rstest]
+    #[case::pieces("4k3/8/8/8/8/8/4P3/3NK3 w - - 0 1", true)]
+    #[case::only_pawns("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false)]
+    #[case::opponent_pieces("3nk3/8/8/8/8/8/4P3/4K3 w - - 0 1", false)]
+    fn zugzwang_guard(#[case] fen: &str, #[case] expected: bool) {
+        assert_eq!(
+            has_non_pawn_material(&Board::from_fen(fen).unwrap()),
+            expected
+        );
+    }
+
+    #[test]
+    fn thread_count_is_clamped() {
+        let mut searcher = Searcher::new();
+        searcher.set_threads(0);
+        assert_eq!(searcher.threads(), 1);
+        searcher.set_threads(10_000);
+        assert_eq!(searcher.threads(), Searcher::MAX_THREADS);
+    }
+
+    #[test]
+    fn transpositions_keep_mate_distance() {
+        // The mate is found through different move orders, each stored at different plies
+        let result = search_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", SearchLimits::depth(6));
+        assert_eq!(result.mate_in(), Some(2));
+    }
+
+    #[rstest]
+    #[case(MATE - 1, Some(1))]
+    #[case(MATE - 3, Some(2))]
+    #[case(-MATE, Some(0))]
+    #[case(-MATE + 2, Some(-1))]
+    #[case(150, None)]
+    fn mate_in_conversion(#[case] score: i32, #[case] expected: Option<i32>) {
+        assert_eq!(mate_in(score), expected);
+    }
+
+    #[test]
+    fn scores_tablebase_positions() {
+        let tablebase = crate::syzygy::tests::kqk_tablebase();
+        let mut searcher = Searcher::new();
+        searcher.set_tablebase(Some(Arc::new(tablebase)));
+        // Taking the rook reaches a won KQvK
+        let mut board = Board::from_fen("k7/8/8/8/8/8/r7/KQ6 w - - 0 1").unwrap();
+        let result = searcher.search(&mut board, SearchLimits::depth(3));
+        assert_eq!(result.score, TB_WIN - 1);
+        assert!(result.best_move.unwrap().captured().is_some());
+        assert_eq!(result.mate_in(), None);
+    }
+
+    #[test]
+    fn tablebases_filter_root_moves() {
+        let mut searcher = Searcher::new();
+        searcher.set_tablebase(Some(Arc::new(crate::syzygy::tests::kqk_tablebase())));
+        searcher.set_multi_pv(100);
+        let mut board = Board::from_fen("k7/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+        let result = searcher.search(&mut board, SearchLimits::depth(1));
+        let lines: Vec<_> = result
+            .lines
+            .iter()
+            .map(|line| line.pv[0].to_string())
+            .collect();
+        assert_eq!(lines.len(), board.get_legal_moves().len() - 2);
+        assert!(!lines.contains(&"b1b7".to_string()));
+        assert!(!lines.contains(&"b1b8".to_string()));
+    }
+}
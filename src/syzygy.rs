@@ -0,0 +1,498 @@
+//! Probing Syzygy endgame tablebases, which know the outcome of every position with few enough
+//! pieces.
+//!
+//! WDL tables (`.rtbw`) give the result with perfect play, telling apart wins that the
+//! fifty-move rule turns into draws. DTZ tables (`.rtbz`) give the distance to the next capture
+//! or pawn move on the way to that result, which is what it takes to actually win within the
+//! rule. Tables hold no positions with castling rights, and leave captures, en passant in
+//! particular, to a short search done while probing.
+
+mod table;
+
+use crate::board::{Board, Colour, Move};
+use crate::piece::PieceType;
+use crate::variant::Variant;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Neg;
+use std::path::Path;
+use table::{Material, Table, TableKind};
+
+/// A position's result with perfect play, for the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss,
+    /// Lost with perfect play, but drawn by the fifty-move rule.
+    BlessedLoss,
+    Draw,
+    /// Won with perfect play, but drawn by the fifty-move rule.
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Self::Loss,
+            -1 => Self::BlessedLoss,
+            0 => Self::Draw,
+            1 => Self::CursedWin,
+            2.. => Self::Win,
+        }
+    }
+
+    fn value(self) -> i32 {
+        match self {
+            Self::Loss => -2,
+            Self::BlessedLoss => -1,
+            Self::Draw => 0,
+            Self::CursedWin => 1,
+            Self::Win => 2,
+        }
+    }
+
+    /// The DTZ just before a capture or pawn move reaching a position with this result.
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Self::Loss => -1,
+            Self::BlessedLoss => -101,
+            Self::Draw => 0,
+            Self::CursedWin => 101,
+            Self::Win => 1,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_value(-self.value())
+    }
+}
+
+/// The tables found in one or more directories. Files are only read when first probed, and
+/// then kept in memory.
+#[derive(Debug, Default)]
+pub struct Tablebase {
+    /// WDL and, if present, DTZ tables by their material, stronger side as white.
+    tables: HashMap<Material, (Table, Option<Table>)>,
+    max_pieces: usize,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tables in `dir`, returning how many were found. A DTZ table without its WDL
+    /// table is ignored.
+    pub fn add_directory(&mut self, dir: impl AsRef<Path>) -> io::Result<usize> {
+        let dir = dir.as_ref();
+        let mut added = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(TableKind::Wdl.extension()) {
+                continue;
+            }
+            let Some(material) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(Material::parse)
+            else {
+                continue;
+            };
+            let dtz_path = path.with_extension(TableKind::Dtz.extension());
+            let dtz = dtz_path
+                .is_file()
+                .then(|| Table::new(TableKind::Dtz, dtz_path, material));
+            let wdl = Table::new(TableKind::Wdl, path, material);
+            self.tables.insert(material, (wdl, dtz));
+            self.max_pieces = self.max_pieces.max(material.piece_count());
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// The most pieces, kings included, of any table found.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether `board` could be in the tables: standard chess with few enough pieces and no
+    /// castling rights.
+    pub fn covers(&self, board: &Board) -> bool {
+        board.variant() == Variant::Standard
+            && board.into_iter().flatten().count() <= self.max_pieces
+            && !has_castling_rights(board)
+    }
+
+    /// The result of `board` with perfect play.
+    pub fn probe_wdl(&self, board: &Board) -> Result<Wdl, &'static str> {
+        self.check(board)?;
+        Ok(self.search(&mut board.clone(), false)?.0)
+    }
+
+    /// Plies to the next capture or pawn move with perfect play, positive when the side to move
+    /// wins and negative when it loses, and 0 for a draw. Wins and losses the fifty-move rule
+    /// turns into draws count 100 more. A position that is won or lost right after a capture or
+    /// pawn move gives ±1, or ±101 if the rule turns it into a draw.
+    pub fn probe_dtz(&self, board: &Board) -> Result<i32, &'static str> {
+        self.check(board)?;
+        self.dtz(&mut board.clone())
+    }
+
+    /// The root moves that keep the best result reachable within the fifty-move rule. When
+    /// winning, moves that still win in time, or if none would, those closest to zeroing.
+    /// When losing, all moves unless the rule is within reach, then those that hold out the
+    /// longest. When drawing, moves that keep the draw.
+    pub fn root_moves(&self, board: &Board) -> Result<Vec<Move>, &'static str> {
+        self.check(board)?;
+        let mut board = board.clone();
+        let root_dtz = self.dtz(&mut board)?;
+        let halfmove_clock = board.halfmove_clock() as i32;
+
+        // The DTZ of each move, counted from the root.
+        let mut moves = Vec::new();
+        for mv in board.get_legal_moves() {
+            board.make_move_unchecked(mv);
+            let dtz = if board.in_check() && board.get_legal_moves().is_empty() {
+                Ok(1)
+            } else if board.halfmove_clock() == 0 {
+                self.search(&mut board, false)
+                    .map(|(wdl, _)| (-wdl).dtz_before_zeroing())
+            } else {
+                self.dtz(&mut board).map(|dtz| -dtz - dtz.signum())
+            };
+            board.unmake_move();
+            moves.push((mv, dtz?));
+        }
+
+        Ok(keep_root_moves(moves, root_dtz, halfmove_clock))
+    }
+
+    fn check(&self, board: &Board) -> Result<(), &'static str> {
+        if board.variant() != Variant::Standard {
+            Err("Tablebases only cover standard chess")
+        } else if has_castling_rights(board) {
+            Err("Tablebases do not cover castling")
+        } else if board.into_iter().flatten().count() > self.max_pieces {
+            Err("Too many pieces for the tablebases")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Looks `board` up in its table, with the colours swapped if black is the stronger side.
+    fn probe_table(
+        &self,
+        board: &Board,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Result<Option<i32>, &'static str> {
+        let material = Material::from_board(board);
+        if material.piece_count() == 2 {
+            return Ok(Some(0));
+        }
+        let (wdl_table, dtz_table) = self
+            .tables
+            .get(&material)
+            .or_else(|| self.tables.get(&material.flipped()))
+            .ok_or("Missing tablebase")?;
+        let table = match kind {
+            TableKind::Wdl => wdl_table,
+            TableKind::Dtz => dtz_table.as_ref().ok_or("Missing DTZ tablebase")?,
+        };
+        table.probe(board, wdl)
+    }
+
+    /// The result of `board`, searching captures, and with `pawn_moves` pawn moves too, as the
+    /// tables may hold a "don't care" value when the best move zeroes the fifty-move counter.
+    /// Also says whether the best move zeroes it, in which case the DTZ table cannot be trusted.
+    fn search(&self, board: &mut Board, pawn_moves: bool) -> Result<(Wdl, bool), &'static str> {
+        let moves = board.get_legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &mv in &moves {
+            let pawn_move = pawn_moves && mv.piece().piece_type == PieceType::Pawn;
+            if mv.captured().is_none() && !pawn_move {
+                continue;
+            }
+            searched += 1;
+            board.make_move_unchecked(mv);
+            let result = self.search(board, false);
+            board.unmake_move();
+            let wdl = -result?.0;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Ok((wdl, true));
+                }
+            }
+        }
+
+        // With every move searched the table is not needed, and may be wrong: it knows nothing
+        // of en passant.
+        let all_searched = searched > 0 && searched == moves.len();
+        let wdl = if all_searched {
+            best
+        } else {
+            let value = self.probe_table(board, TableKind::Wdl, Wdl::Draw)?;
+            Wdl::from_value(value.unwrap_or(0))
+        };
+        if best >= wdl {
+            Ok((best, best > Wdl::Draw || all_searched))
+        } else {
+            Ok((wdl, false))
+        }
+    }
+
+    fn dtz(&self, board: &mut Board) -> Result<i32, &'static str> {
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if zeroing {
+            return Ok(wdl.dtz_before_zeroing());
+        }
+        let sign = wdl.value().signum();
+        let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+        if let Some(dtz) = self.probe_table(board, TableKind::Dtz, wdl)? {
+            return Ok((dtz + if cursed { 100 } else { 0 }) * sign);
+        }
+
+        // The table only has the other side to move, so look a move ahead for the best DTZ.
+        let mut best = None;
+        for mv in board.get_legal_moves() {
+            let zeroing = mv.captured().is_some() || mv.piece().piece_type == PieceType::Pawn;
+            board.make_move_unchecked(mv);
+            let result = if zeroing {
+                self.search(board, false)
+                    .map(|(wdl, _)| -(wdl.dtz_before_zeroing()))
+            } else {
+                self.dtz(board).map(|dtz| -dtz)
+            };
+            let mates = board.in_check() && board.get_legal_moves().is_empty();
+            board.unmake_move();
+            let mut dtz = result?;
+
+            if dtz == 1 && mates {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == sign && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // No legal moves: mated.
+        Ok(best.unwrap_or(-1))
+    }
+}
+
+/// Filters moves by their DTZ from the root, as `Tablebase::root_moves` describes.
+fn keep_root_moves<T>(moves: Vec<(T, i32)>, root_dtz: i32, halfmove_clock: i32) -> Vec<T> {
+    let dtzs = || moves.iter().map(|&(_, dtz)| dtz);
+    let keep: Box<dyn Fn(i32) -> bool> = if root_dtz > 0 {
+        // Any win that zeroes in time will do, otherwise the fastest.
+        let best = dtzs().filter(|&dtz| dtz > 0).min().unwrap_or(0);
+        let max = if best + halfmove_clock <= 99 {
+            99 - halfmove_clock
+        } else {
+            best
+        };
+        Box::new(move |dtz| dtz > 0 && dtz <= max)
+    } else if root_dtz < 0 {
+        let best = dtzs().min().unwrap_or(0);
+        if -best * 2 + halfmove_clock < 100 {
+            Box::new(|_| true)
+        } else {
+            Box::new(move |dtz| dtz == best)
+        }
+    } else {
+        Box::new(|dtz| dtz == 0)
+    };
+    moves
+        .into_iter()
+        .filter(|&(_, dtz)| keep(dtz))
+        .map(|(mv, _)| mv)
+        .collect()
+}
+
+fn has_castling_rights(board: &Board) -> bool {
+    [Colour::White, Colour::Black]
+        .into_iter()
+        .any(|colour| board.can_castle(colour, true) || board.can_castle(colour, false))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    /// Made-up KQvK tables with a single value each: White to move wins, and zeroes in 9 moves,
+    /// Black to move loses. Unlike real tables they are wrong about stalemates and hanging the
+    /// queen, which is what the probing search is for. Each file is the magic number, a header
+    /// byte, the piece order and a single-value pairs block, padded to 80 bytes.
+    const KQK_TABLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/syzygy");
+
+    pub(crate) fn kqk_tablebase() -> Tablebase {
+        let mut tablebase = Tablebase::new();
+        assert_eq!(tablebase.add_directory(KQK_TABLES).unwrap(), 1);
+        tablebase
+    }
+
+    /// A scratch directory, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[rstest]
+    #[case::stronger_to_move("k7/8/8/8/8/8/8/KQ6 w - - 0 1", Wdl::Win)]
+    #[case::weaker_to_move("k7/8/1K6/8/8/8/8/7Q b - - 0 1", Wdl::Loss)]
+    #[case::colours_swapped("kq6/8/8/8/8/8/8/K7 b - - 0 1", Wdl::Win)]
+    #[case::queen_hangs("8/8/8/8/8/8/1Q6/k6K b - - 0 1", Wdl::Draw)]
+    #[case::bare_kings("8/8/8/8/8/8/8/k6K w - - 0 1", Wdl::Draw)]
+    fn probes_wdl(#[case] fen: &str, #[case] wdl: Wdl) {
+        let tablebase = kqk_tablebase();
+        assert_eq!(tablebase.probe_wdl(&board(fen)), Ok(wdl));
+    }
+
+    #[test]
+    fn probes_dtz() {
+        let tablebase = kqk_tablebase();
+        // Stored in moves for White to move, one ply more to get there
+        assert_eq!(
+            tablebase.probe_dtz(&board("k7/8/8/8/8/8/8/KQ6 w - - 0 1")),
+            Ok(19)
+        );
+        // Not stored for Black to move, so found a move ahead
+        assert_eq!(
+            tablebase.probe_dtz(&board("k7/8/1K6/8/8/8/8/7Q b - - 0 1")),
+            Ok(-20)
+        );
+        assert_eq!(
+            tablebase.probe_dtz(&board("8/8/8/8/8/8/1Q6/k6K b - - 0 1")),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn refuses_what_is_not_covered() {
+        let tablebase = kqk_tablebase();
+        assert_eq!(tablebase.max_pieces(), 3);
+        let rook = board("k7/8/8/8/8/8/8/KR6 w - - 0 1");
+        assert!(tablebase.covers(&rook));
+        assert_eq!(tablebase.probe_wdl(&rook), Err("Missing tablebase"));
+        assert!(tablebase.probe_wdl(&Board::new()).is_err());
+        let castling = board("k7/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert!(!tablebase.covers(&castling));
+        assert!(tablebase.probe_wdl(&castling).is_err());
+    }
+
+    #[test]
+    fn root_moves_keep_the_win() {
+        let tablebase = kqk_tablebase();
+        let moves: Vec<_> = tablebase
+            .root_moves(&board("k7/8/8/8/8/8/8/KQ6 w - - 0 1"))
+            .unwrap()
+            .iter()
+            .map(Move::to_string)
+            .collect();
+        assert!(moves.contains(&"a1b2".to_string()));
+        // Both let the king take the queen
+        assert!(!moves.contains(&"b1b7".to_string()));
+        assert!(!moves.contains(&"b1b8".to_string()));
+    }
+
+    #[test]
+    fn rejects_tables_of_the_other_kind() {
+        let dir = TempDir::new("syzygy-swapped");
+        let tables = Path::new(KQK_TABLES);
+        fs::copy(tables.join("KQvK.rtbw"), dir.0.join("KQvK.rtbz")).unwrap();
+        fs::copy(tables.join("KQvK.rtbz"), dir.0.join("KQvK.rtbw")).unwrap();
+        let mut tablebase = Tablebase::new();
+        assert_eq!(tablebase.add_directory(&dir.0).unwrap(), 1);
+        assert!(
+            tablebase
+                .probe_wdl(&board("k7/8/8/8/8/8/8/KQ6 w - - 0 1"))
+                .is_err()
+        );
+    }
+
+    /// Known results from the published tables, read from the directory `SYZYGY_PATH` names.
+    #[test]
+    #[ignore = "needs the real KQvK and KRvK tables in the directory named by SYZYGY_PATH"]
+    fn probes_real_tables() {
+        let mut tablebase = Tablebase::new();
+        let dir = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH names the tables' directory");
+        assert!(tablebase.add_directory(dir).unwrap() >= 2);
+
+        let wdls = [
+            ("k7/8/8/8/8/8/8/KQ6 w - - 0 1", Wdl::Win),
+            ("k7/8/1K6/8/8/8/8/7Q b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/8/8/1kQ5/7K b - - 0 1", Wdl::Draw),
+            ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("k7/8/8/8/8/8/8/KR6 w - - 0 1", Wdl::Win),
+            ("8/8/8/8/8/8/1kR5/7K b - - 0 1", Wdl::Draw),
+            ("kr6/8/8/8/8/8/8/K7 b - - 0 1", Wdl::Win),
+        ];
+        for (fen, wdl) in wdls {
+            assert_eq!(tablebase.probe_wdl(&board(fen)), Ok(wdl), "{fen}");
+        }
+
+        let mate_in_one = board("k7/2Q5/1K6/8/8/8/8/8 w - - 0 1");
+        assert!(tablebase.probe_dtz(&mate_in_one).unwrap() > 0);
+        assert!(
+            tablebase
+                .probe_dtz(&board("k7/8/1K6/8/8/8/8/7Q b - - 0 1"))
+                .unwrap()
+                < 0
+        );
+        let moves = tablebase.root_moves(&mate_in_one).unwrap();
+        assert!(moves.iter().any(|mv| mv.to_string() == "c7b7"));
+        for mv in moves {
+            let mut after = mate_in_one.clone();
+            after.make_move(mv).unwrap();
+            if !after.get_legal_moves().is_empty() {
+                assert_eq!(tablebase.probe_wdl(&after), Ok(Wdl::Loss), "{mv}");
+            }
+        }
+    }
+
+    #[rstest]
+    #[case::any_timely_win(1, 0, &[5, 30, -3, 0], &[5, 30])]
+    #[case::win_before_the_rule(1, 80, &[5, 30, -3, 0], &[5])]
+    #[case::cursed_win(101, 98, &[5, 30, -3, 0], &[5])]
+    #[case::loss_far_from_the_rule(-1, 10, &[-5, -30, -3], &[-5, -30, -3])]
+    #[case::loss_near_the_rule(-1, 60, &[-5, -30, -3], &[-30])]
+    #[case::draw(0, 0, &[0, -4, 0], &[0, 0])]
+    fn root_moves_respect_the_fifty_move_rule(
+        #[case] root_dtz: i32,
+        #[case] halfmove_clock: i32,
+        #[case] dtzs: &[i32],
+        #[case] kept: &[i32],
+    ) {
+        let moves = dtzs.iter().map(|&dtz| (dtz, dtz)).collect();
+        assert_eq!(keep_root_moves(moves, root_dtz, halfmove_clock), kept);
+    }
+}
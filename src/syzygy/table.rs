@@ -0,0 +1,1217 @@
+//! The Syzygy file format: how a table file is laid out, how a position maps to an index into
+//! it, and how the compressed value at that index is decoded.
+//!
+//! A table stores one value per position, in blocks of canonical Huffman codes whose symbols
+//! each expand, by recursive pairing, into a run of values. Positions are indexed up to the
+//! symmetries of the board, so the pieces are first mirrored into a canonical corner and then
+//! numbered group by group, a group being pieces of the same kind that can swap squares.
+
+use super::Wdl;
+use crate::board::{Board, Colour};
+use crate::piece::{Piece, PieceType};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// The most pieces any Syzygy table has, kings included.
+pub(super) const MAX_PIECES: usize = 7;
+
+/// Flags of a table's pairs data, only the last ever set for WDL tables.
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// Flags in the first byte of a file.
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+impl TableKind {
+    pub(super) fn extension(self) -> &'static str {
+        match self {
+            Self::Wdl => "rtbw",
+            Self::Dtz => "rtbz",
+        }
+    }
+
+    fn magic(self) -> [u8; 4] {
+        match self {
+            Self::Wdl => [0x71, 0xe8, 0x23, 0x5d],
+            Self::Dtz => [0xd7, 0x66, 0x0c, 0xa5],
+        }
+    }
+}
+
+/// Piece counts per colour, which pick the table a position is in. Table files are named after
+/// them, stronger side first, e.g. `KRPvKR`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) struct Material {
+    /// Indexed by colour, then by `PIECE_ORDER`.
+    counts: [[u8; 6]; 2],
+}
+
+/// The order pieces are listed in a table name.
+const PIECE_ORDER: [PieceType; 6] = [
+    PieceType::King,
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
+const PIECE_LETTERS: [char; 6] = ['K', 'Q', 'R', 'B', 'N', 'P'];
+
+impl Material {
+    pub(super) fn from_board(board: &Board) -> Self {
+        let mut counts = [[0; 6]; 2];
+        for (_, piece) in board.into_iter().flatten() {
+            counts[colour_index(piece.colour)][order_index(piece.piece_type)] += 1;
+        }
+        Self { counts }
+    }
+
+    /// Parses a table name such as `KRPvKR`, with one king a side.
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+        for (colour, side) in [white, black].into_iter().enumerate() {
+            if !side.starts_with('K') {
+                return None;
+            }
+            for letter in side.chars() {
+                let index = PIECE_LETTERS.iter().position(|&l| l == letter)?;
+                counts[colour][index] += 1;
+            }
+        }
+        let material = Self { counts };
+        (counts[0][0] == 1 && counts[1][0] == 1 && material.piece_count() <= MAX_PIECES)
+            .then_some(material)
+    }
+
+    /// The same material with the colours swapped.
+    pub(super) fn flipped(&self) -> Self {
+        Self {
+            counts: [self.counts[1], self.counts[0]],
+        }
+    }
+
+    pub(super) fn piece_count(&self) -> usize {
+        self.counts
+            .iter()
+            .flatten()
+            .map(|&count| count as usize)
+            .sum()
+    }
+
+    fn pawns(&self, colour: usize) -> u8 {
+        self.counts[colour][order_index(PieceType::Pawn)]
+    }
+}
+
+fn colour_index(colour: Colour) -> usize {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => 1,
+    }
+}
+
+fn order_index(piece_type: PieceType) -> usize {
+    PIECE_ORDER
+        .iter()
+        .position(|&other| other == piece_type)
+        .expect("every piece type is listed")
+}
+
+/// A piece as the files encode it: 1 to 6 for pawn to king, plus 8 for black.
+fn piece_code(piece: Piece) -> u8 {
+    let kind = match piece.piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6,
+    };
+    kind | if piece.colour == Colour::Black { 8 } else { 0 }
+}
+
+/// Rank minus file: zero on the a1-h8 diagonal, negative below it.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+/// Mirrors a square in the a1-h8 diagonal.
+fn flip_diagonal(square: usize) -> usize {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+/// Lookup tables for the position index, the same for every table.
+struct Indexing {
+    /// `binomial[k][n]` ways to choose `k` of `n` squares.
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Numbers a2-h7 so that the pawn with the highest number, nearest the edge and then the
+    /// lowest rank, leads.
+    map_pawns: [usize; 64],
+    /// Index of the leading pawn's square, by number of leading pawns, restarting on each file.
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    /// Positions of the leading pawns, by their number and their file, a to d.
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+    /// Numbers the squares below the a1-h8 diagonal.
+    map_b1h1h7: [usize; 64],
+    /// Numbers the a1-d1-d4 triangle, the squares on the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The 462 ways to place two kings with the first in the a1-d1-d4 triangle.
+    map_kk: [[u64; 64]; 10],
+}
+
+impl Indexing {
+    fn get() -> &'static Self {
+        static INDEXING: OnceLock<Indexing> = OnceLock::new();
+        INDEXING.get_or_init(Self::new)
+    }
+
+    fn new() -> Self {
+        let mut map_b1h1h7 = [0; 64];
+        let below = (0..64).filter(|&square| off_diagonal(square) < 0);
+        for (code, square) in below.enumerate() {
+            map_b1h1h7[square] = code;
+        }
+
+        // The triangle's squares below the diagonal come first, in square order.
+        let triangle = |square: usize| square % 8 <= 3 && square <= 27 && off_diagonal(square) <= 0;
+        let mut map_a1d1d4 = [0; 64];
+        let (below, diagonal): (Vec<_>, Vec<_>) = (0..=27)
+            .filter(|&square| triangle(square))
+            .partition(|&square| off_diagonal(square) < 0);
+        for (code, square) in below.into_iter().chain(diagonal).enumerate() {
+            map_a1d1d4[square] = code;
+        }
+
+        // With the first king on the diagonal, the second is never above it. Both kings on the
+        // diagonal come last.
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (idx, row) in map_kk.iter_mut().enumerate() {
+            let Some(first) =
+                (0..=27).find(|&square| triangle(square) && map_a1d1d4[square] == idx)
+            else {
+                continue;
+            };
+            for (second, entry) in row.iter_mut().enumerate() {
+                let touching =
+                    (first % 8).abs_diff(second % 8) <= 1 && (first / 8).abs_diff(second / 8) <= 1;
+                if touching || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((idx, second));
+                } else {
+                    *entry = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; 64]; MAX_PIECES];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // A leading pawn on a2 leaves 47 squares for the others, and each rank further up two
+        // fewer, as neither that file nor its mirror can hold a pawn below it.
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; MAX_PIECES];
+        let mut lead_pawns_size = [[0; 4]; MAX_PIECES];
+        let mut available = 48;
+        for lead_pawns in 1..MAX_PIECES - 1 {
+            for (file, size) in lead_pawns_size[lead_pawns].iter_mut().enumerate() {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        available -= 1;
+                        map_pawns[square] = available;
+                        available -= 1;
+                        map_pawns[square ^ 7] = available;
+                    }
+                    lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += binomial[lead_pawns - 1][map_pawns[square]];
+                }
+                *size = idx;
+            }
+        }
+
+        Self {
+            binomial,
+            map_pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+        }
+    }
+}
+
+/// Where the values for one side to move and leading file are, and how to decode them.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    /// The pieces in the order the table encodes them, which defines the groups.
+    pieces: [u8; MAX_PIECES],
+    /// Pieces per group, ending with a zero.
+    group_len: [usize; MAX_PIECES + 1],
+    /// What each group's index is multiplied by. After the last group, the table size.
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: usize,
+    /// How many values apart the entries of the sparse index are.
+    span: u64,
+    num_blocks: usize,
+    min_sym_len: u8,
+    /// Offsets into the file.
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    sparse_index_size: usize,
+    block_length_size: usize,
+    /// The lowest code of each length, left-aligned in 64 bits.
+    base64: Vec<u64>,
+    /// Values each symbol expands to, minus one.
+    symlen: Vec<u8>,
+    /// Where the DTZ values of a win, loss, cursed win and blessed loss start in the map.
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    /// Reads the Huffman code, returning where the next record starts.
+    fn read_sizes(&mut self, bytes: &[u8], mut pos: usize) -> Result<usize, &'static str> {
+        self.flags = byte(bytes, pos);
+        pos += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            // Every position has the same value, stored in place of the code length.
+            self.min_sym_len = byte(bytes, pos);
+            return Ok(pos + 1);
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0);
+        let size = self.group_idx[groups.unwrap_or(MAX_PIECES)];
+        self.block_size = 1 << byte(bytes, pos).min(32);
+        self.span = 1 << byte(bytes, pos + 1).min(32);
+        self.sparse_index_size = size.div_ceil(self.span) as usize;
+        let padding = byte(bytes, pos + 2) as usize;
+        self.num_blocks = read_u32_le(bytes, pos + 3) as usize;
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = byte(bytes, pos + 7);
+        self.min_sym_len = byte(bytes, pos + 8);
+        pos += 9;
+        if self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 32 {
+            return Err("Corrupt tablebase file");
+        }
+
+        // Longer codes have lower values, so the lowest code of each length can be worked out
+        // from the one after it.
+        self.lowest_sym = pos;
+        let lengths = (max_sym_len - self.min_sym_len) as usize + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = |i: usize| u64::from(read_u16_le(bytes, self.lowest_sym + 2 * i));
+            self.base64[i] = (self.base64[i + 1] + lowest(i)).wrapping_sub(lowest(i + 1)) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base <<= 64 - i - self.min_sym_len as usize;
+        }
+        pos += 2 * lengths;
+
+        let symbols = read_u16_le(bytes, pos) as usize;
+        pos += 2;
+        self.btree = pos;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.expand(bytes, symbol, &mut visited, 0)?;
+            }
+        }
+        Ok(pos + 3 * symbols + (symbols & 1))
+    }
+
+    /// How many values `symbol` stands for, minus one, filling in its children's on the way.
+    fn expand(
+        &mut self,
+        bytes: &[u8],
+        symbol: usize,
+        visited: &mut [bool],
+        depth: usize,
+    ) -> Result<u8, &'static str> {
+        visited[symbol] = true;
+        let right = self.right(bytes, symbol);
+        if right == 0xfff {
+            return Ok(0);
+        }
+        let left = self.left(bytes, symbol);
+        if left >= self.symlen.len() || right >= self.symlen.len() || depth > self.symlen.len() {
+            return Err("Corrupt tablebase file");
+        }
+        for child in [left, right] {
+            if !visited[child] {
+                self.symlen[child] = self.expand(bytes, child, visited, depth + 1)?;
+            }
+        }
+        Ok(self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1))
+    }
+
+    /// A symbol's first child, or for a leaf its value.
+    fn left(&self, bytes: &[u8], symbol: usize) -> usize {
+        let at = self.btree + 3 * symbol;
+        (usize::from(byte(bytes, at + 1) & 0xf) << 8) | usize::from(byte(bytes, at))
+    }
+
+    /// A symbol's second child, 0xfff for a leaf.
+    fn right(&self, bytes: &[u8], symbol: usize) -> usize {
+        let at = self.btree + 3 * symbol;
+        (usize::from(byte(bytes, at + 2)) << 4) | usize::from(byte(bytes, at + 1) >> 4)
+    }
+
+    /// Decodes the value at `idx`.
+    fn decompress(&self, bytes: &[u8], idx: u64) -> usize {
+        if self.flags & SINGLE_VALUE != 0 {
+            return self.min_sym_len as usize;
+        }
+
+        // The sparse index knows which block holds the value in the middle of each span, and
+        // where in that block it is. Walk from there to the block that holds `idx`.
+        let entry = self.sparse_index + 6 * (idx / self.span) as usize;
+        let mut block = read_u32_le(bytes, entry) as usize;
+        let mut offset = i64::from(read_u16_le(bytes, entry + 4));
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        let block_length =
+            |block: usize| i64::from(read_u16_le(bytes, self.block_length + 2 * block));
+        while offset < 0 && block > 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) && block < self.num_blocks {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        // Read symbols from the start of the block until the one that covers the offset.
+        let mut at = self.data + block * self.block_size;
+        let mut buffer = read_u64_be(bytes, at);
+        at += 8;
+        let mut buffered = 64;
+        let min_sym_len = self.min_sym_len as usize;
+        let mut symbol = loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buffer < self.base64[len] {
+                len += 1;
+            }
+            let code = buffer.wrapping_sub(self.base64[len]) >> (64 - len - min_sym_len);
+            let symbol = (code as usize
+                + usize::from(read_u16_le(bytes, self.lowest_sym + 2 * len)))
+            .min(self.symlen.len().saturating_sub(1));
+            let count = i64::from(self.symlen[symbol]) + 1;
+            if offset < count {
+                break symbol;
+            }
+            offset -= count;
+            len += min_sym_len;
+            buffer <<= len;
+            buffered -= len;
+            if buffered <= 32 {
+                buffered += 32;
+                buffer |= u64::from(read_u32_be(bytes, at)) << (64 - buffered);
+                at += 4;
+            }
+        };
+
+        // Descend the pair tree to the value at the offset.
+        while self.symlen[symbol] != 0 {
+            let left = self.left(bytes, symbol);
+            let count = i64::from(self.symlen[left]) + 1;
+            if offset < count {
+                symbol = left;
+            } else {
+                offset -= count;
+                symbol = self.right(bytes, symbol);
+            }
+        }
+        self.left(bytes, symbol)
+    }
+}
+
+/// A table file, read into memory the first time it is probed.
+#[derive(Debug)]
+pub(super) struct Table {
+    kind: TableKind,
+    path: PathBuf,
+    /// As in the file name, the stronger side first, playing white.
+    material: Material,
+    /// Both sides have the same pieces, so only white to move is stored.
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// Pawns of the leading colour, the one with fewer, and then of the other.
+    pawn_counts: [u8; 2],
+    piece_count: usize,
+    data: OnceLock<Result<TableData, &'static str>>,
+}
+
+#[derive(Debug)]
+struct TableData {
+    bytes: Vec<u8>,
+    /// By side to move, then by file for tables with pawns.
+    pairs: Vec<PairsData>,
+    sides: usize,
+    files: usize,
+    /// Where the DTZ value maps start.
+    map: usize,
+}
+
+impl TableData {
+    fn pairs(&self, stm: usize, file: usize) -> &PairsData {
+        &self.pairs[(stm % self.sides) * self.files + file % self.files]
+    }
+}
+
+impl Table {
+    pub(super) fn new(kind: TableKind, path: PathBuf, material: Material) -> Self {
+        let counts = material.counts;
+        let has_unique_pieces = counts.iter().any(|side| side[1..].contains(&1));
+        let (white_pawns, black_pawns) = (material.pawns(0), material.pawns(1));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Self {
+            kind,
+            path,
+            material,
+            symmetric: material == material.flipped(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_counts: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            piece_count: material.piece_count(),
+            data: OnceLock::new(),
+        }
+    }
+
+    fn data(&self) -> Result<&TableData, &'static str> {
+        self.data
+            .get_or_init(|| self.load())
+            .as_ref()
+            .map_err(|&err| err)
+    }
+
+    fn load(&self) -> Result<TableData, &'static str> {
+        let bytes = fs::read(&self.path).map_err(|_| "Cannot read tablebase file")?;
+        if bytes.len() < 5 || bytes[..4] != self.kind.magic() {
+            return Err("Not a Syzygy tablebase file");
+        }
+        // Tables end in a 16 byte checksum after data padded to 64 bytes.
+        if bytes.len() % 64 != 16 {
+            return Err("Corrupt tablebase file");
+        }
+        let header = bytes[4];
+        if (header & HAS_PAWNS != 0) != self.has_pawns
+            || (self.kind == TableKind::Wdl && (header & SPLIT != 0) == self.symmetric)
+        {
+            return Err("Tablebase file does not match its name");
+        }
+
+        let sides = if self.kind == TableKind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+        let mut pairs = vec![PairsData::default(); sides * files];
+        let mut pos = 5;
+        for file in 0..files {
+            // A nibble per side, the second byte only when both sides have pawns.
+            let first = byte(&bytes, pos);
+            let second = if both_pawns {
+                byte(&bytes, pos + 1)
+            } else {
+                0xff
+            };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            pos += 1 + usize::from(both_pawns);
+            for k in 0..self.piece_count {
+                let code = byte(&bytes, pos + k);
+                pairs[file].pieces[k] = code & 0xf;
+                if sides == 2 {
+                    pairs[files + file].pieces[k] = code >> 4;
+                }
+            }
+            pos += self.piece_count;
+            for side in 0..sides {
+                self.set_groups(&mut pairs[side * files + file], order[side], file);
+            }
+        }
+        pos += pos & 1;
+
+        // The records of each section are stored file by file, then side by side.
+        let order: Vec<_> = (0..files)
+            .flat_map(|file| (0..sides).map(move |side| side * files + file))
+            .collect();
+        for &i in &order {
+            pos = pairs[i].read_sizes(&bytes, pos)?;
+        }
+
+        let map = pos;
+        if self.kind == TableKind::Dtz {
+            for d in &mut pairs {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for idx in &mut d.map_idx {
+                        *idx = (pos - map) / 2 + 1;
+                        pos += 2 * read_u16_le(&bytes, pos) as usize + 2;
+                    }
+                } else {
+                    for idx in &mut d.map_idx {
+                        *idx = pos - map + 1;
+                        pos += byte(&bytes, pos) as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for &i in &order {
+            pairs[i].sparse_index = pos;
+            pos += 6 * pairs[i].sparse_index_size;
+        }
+        for &i in &order {
+            pairs[i].block_length = pos;
+            pos += 2 * pairs[i].block_length_size;
+        }
+        for &i in &order {
+            pos = pos.next_multiple_of(64);
+            pairs[i].data = pos;
+            pos += pairs[i].num_blocks * pairs[i].block_size;
+        }
+        if pos > bytes.len() {
+            return Err("Corrupt tablebase file");
+        }
+
+        Ok(TableData {
+            bytes,
+            pairs,
+            sides,
+            files,
+            map,
+        })
+    }
+
+    /// Splits the pieces into groups and works out what each group's index is multiplied by.
+    /// The leading group is the leading pawns, or without pawns the kings and, if there is one,
+    /// a piece with no twin. Every other group is pieces of one kind. `order` says where the
+    /// leading group and the other side's pawns come in the encoding.
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], file: usize) {
+        let indexing = Indexing::get();
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    indexing.lead_pawns_size[d.group_len[0]][file]
+                } else if self.has_unique_pieces {
+                    31_332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= indexing.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= indexing.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    /// Looks `board` up, returning the raw WDL value or, for a DTZ table, the distance to
+    /// zeroing in plies given the position's `wdl`. `None` when a DTZ table only has the other
+    /// side to move.
+    pub(super) fn probe(&self, board: &Board, wdl: Wdl) -> Result<Option<i32>, &'static str> {
+        let data = self.data()?;
+        let Some((d, file, idx)) = self.index(data, board)? else {
+            return Ok(None);
+        };
+        let value = d.decompress(&data.bytes, idx);
+        Ok(Some(match self.kind {
+            TableKind::Wdl => value as i32 - 2,
+            TableKind::Dtz => self.map_dtz(data, file, value, wdl),
+        }))
+    }
+
+    /// The index of `board` in the table, with the pairs data and leading file it is in.
+    fn index<'a>(
+        &self,
+        data: &'a TableData,
+        board: &Board,
+    ) -> Result<Option<(&'a PairsData, usize, u64)>, &'static str> {
+        let indexing = Indexing::get();
+
+        // Tables have the stronger side as white. A weaker white, or black to move in a
+        // symmetric table, is looked up with the colours swapped and the board flipped.
+        let black_to_move = board.turn() == Colour::Black;
+        let flip =
+            (self.symmetric && black_to_move) || Material::from_board(board) != self.material;
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = usize::from(flip ^ black_to_move);
+
+        let mut on_board: Vec<_> = board
+            .into_iter()
+            .flatten()
+            .map(|(coord, piece)| (coord.index(), piece_code(piece)))
+            .collect();
+        on_board.sort_unstable();
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        // Tables with pawns are split by the file of the leading pawn, nearest the edge.
+        let lead_piece = self
+            .has_pawns
+            .then(|| data.pairs(0, 0).pieces[0] ^ flip_colour);
+        if let Some(lead_piece) = lead_piece {
+            for &(square, code) in &on_board {
+                if code == lead_piece {
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_pawns = size;
+            let lead = (0..lead_pawns)
+                .max_by_key(|&i| indexing.map_pawns[squares[i]])
+                .ok_or("Tablebase file does not match its name")?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+
+        if self.kind == TableKind::Dtz {
+            let flags = data.pairs(stm, file).flags;
+            // Symmetric tables without pawns look the same from either side.
+            let either_side = self.symmetric && !self.has_pawns;
+            if usize::from(flags & STM) != stm && !either_side {
+                return Ok(None);
+            }
+        }
+
+        for &(square, code) in &on_board {
+            if Some(code) != lead_piece {
+                squares[size] = square ^ flip_squares;
+                pieces[size] = code ^ flip_colour;
+                size += 1;
+            }
+        }
+        if size != self.piece_count {
+            return Err("Tablebase file does not match its name");
+        }
+
+        // Put the pieces in the table's order.
+        let d = data.pairs(stm, file);
+        for i in lead_pawns..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // Mirror the leading piece onto files a to d.
+        if squares[0] % 8 > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx = if self.has_pawns {
+            let mut idx = indexing.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| indexing.map_pawns[square]);
+            for (i, &square) in squares[..lead_pawns].iter().enumerate().skip(1) {
+                idx += indexing.binomial[i][indexing.map_pawns[square]];
+            }
+            idx
+        } else {
+            // Without pawns the leading piece also goes to ranks 1 to 4, and then below the
+            // diagonal, or failing that the first of its group that is off the diagonal.
+            if squares[0] / 8 > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = flip_diagonal(*square);
+                    }
+                }
+                break;
+            }
+            if self.has_unique_pieces {
+                lead_group_index(indexing, &squares)
+            } else {
+                indexing.map_kk[indexing.map_a1d1d4[squares[0]]][squares[1]]
+            }
+        };
+        idx *= d.group_idx[0];
+
+        // The other groups choose squares among those left, in ascending order.
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let taken = squares[..start].iter().filter(|&&s| square > s).count();
+                let pawn_ranks = if remaining_pawns { 8 } else { 0 };
+                n += indexing.binomial[i + 1][square - taken - pawn_ranks];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Ok(Some((d, file, idx)))
+    }
+
+    /// Turns a stored DTZ value into plies. Values are stored by frequency, mapped back through
+    /// a per-result table, and in full moves unless the table says otherwise.
+    fn map_dtz(&self, data: &TableData, file: usize, value: usize, wdl: Wdl) -> i32 {
+        let d = data.pairs(0, file);
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let at = d.map_idx[map] + value;
+            value = if d.flags & WIDE != 0 {
+                read_u16_le(&data.bytes, data.map + 2 * at) as usize
+            } else {
+                byte(&data.bytes, data.map + at) as usize
+            };
+        }
+        let plies = match wdl {
+            Wdl::Win => d.flags & WIN_PLIES != 0,
+            Wdl::Loss => d.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        let value = value as i32;
+        (if plies { value } else { 2 * value }) + 1
+    }
+}
+
+/// Index of three unique leading pieces, the first below the diagonal or on it, in 31,332
+/// ways.
+fn lead_group_index(indexing: &Indexing, squares: &[usize]) -> u64 {
+    let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+    let adjust1 = usize::from(s1 > s0);
+    let adjust2 = usize::from(s2 > s0) + usize::from(s2 > s1);
+    let rank = |square: usize| square / 8;
+    let idx = if off_diagonal(s0) != 0 {
+        (indexing.map_a1d1d4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+    } else if off_diagonal(s1) != 0 {
+        (6 * 63 + rank(s0) * 28 + indexing.map_b1h1h7[s1]) * 62 + s2 - adjust2
+    } else if off_diagonal(s2) != 0 {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(s0) * 7 * 28
+            + (rank(s1) - adjust1) * 28
+            + indexing.map_b1h1h7[s2]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(s0) * 7 * 6
+            + (rank(s1) - adjust1) * 6
+            + (rank(s2) - adjust2)
+    };
+    idx as u64
+}
+
+/// Reads past the end of the file as zeros, so a truncated file gives wrong values rather than
+/// a panic.
+fn byte(bytes: &[u8], at: usize) -> u8 {
+    bytes.get(at).copied().unwrap_or(0)
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], at: usize) -> [u8; N] {
+    std::array::from_fn(|i| byte(bytes, at + i))
+}
+
+fn read_u16_le(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(read_bytes(bytes, at))
+}
+
+fn read_u32_le(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(read_bytes(bytes, at))
+}
+
+fn read_u32_be(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(read_bytes(bytes, at))
+}
+
+fn read_u64_be(bytes: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(read_bytes(bytes, at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Coordinate;
+    use rstest::rstest;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn lookup_tables() {
+        let indexing = Indexing::get();
+        let kings: HashSet<_> = indexing
+            .map_kk
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, row)| {
+                let first =
+                    (0..28).find(|&s| indexing.map_a1d1d4[s] == idx && off_diagonal(s) <= 0);
+                row.iter().enumerate().filter(move |&(second, &code)| {
+                    let first = first.unwrap();
+                    code > 0 || (first, second) == (1, 0)
+                })
+            })
+            .map(|(_, &code)| code)
+            .collect();
+        assert_eq!(kings.len(), 462);
+        assert_eq!(kings.iter().max(), Some(&461));
+
+        let pawns: HashSet<_> = (8..56).map(|square| indexing.map_pawns[square]).collect();
+        assert_eq!(pawns, (0..48).collect());
+        // a2 leads over everything, then h2
+        assert_eq!(indexing.map_pawns[8], 47);
+        assert_eq!(indexing.map_pawns[15], 46);
+        assert_eq!(indexing.lead_pawns_size[1], [6; 4]);
+        assert_eq!(indexing.binomial[2][5], 10);
+        assert_eq!(indexing.binomial[3][2], 0);
+    }
+
+    #[rstest]
+    #[case("KRPvKR", true)]
+    #[case("KQvK", true)]
+    #[case("KBNNvKQ", true)]
+    #[case("KQRBNPvKQ", false)]
+    #[case("KQ", false)]
+    #[case("KQvQ", false)]
+    #[case("KXvK", false)]
+    fn parses_table_names(#[case] name: &str, #[case] valid: bool) {
+        assert_eq!(Material::parse(name).is_some(), valid);
+    }
+
+    #[test]
+    fn material_from_board() {
+        let board = Board::from_fen("8/8/3k4/3r4/8/8/1PR5/1K6 w - - 0 1").unwrap();
+        let material = Material::from_board(&board);
+        assert_eq!(Some(material), Material::parse("KRPvKR"));
+        assert_eq!(Some(material.flipped()), Material::parse("KRvKRP"));
+        assert_eq!(material.piece_count(), 5);
+    }
+
+    /// A table with the given piece order and no file, only white to move.
+    fn table(name: &str, pieces: &[u8], order: [u8; 2]) -> (Table, TableData) {
+        let table = Table::new(
+            TableKind::Wdl,
+            PathBuf::new(),
+            Material::parse(name).unwrap(),
+        );
+        let files = if table.has_pawns { 4 } else { 1 };
+        let pairs = (0..files)
+            .map(|file| {
+                let mut d = PairsData::default();
+                d.pieces[..pieces.len()].copy_from_slice(pieces);
+                table.set_groups(&mut d, order, file);
+                d
+            })
+            .collect();
+        let data = TableData {
+            bytes: Vec::new(),
+            pairs,
+            sides: 1,
+            files,
+            map: 0,
+        };
+        (table, data)
+    }
+
+    /// Mirrors a square by one of the board's symmetries: bit 0 the files, bit 1 the ranks and
+    /// bit 2 the diagonal.
+    fn transform(square: usize, symmetry: usize) -> usize {
+        let mut square = square;
+        if symmetry & 1 != 0 {
+            square ^= 7;
+        }
+        if symmetry & 2 != 0 {
+            square ^= 56;
+        }
+        if symmetry & 4 != 0 {
+            square = flip_diagonal(square);
+        }
+        square
+    }
+
+    /// Checks that positions only share an index when a symmetry maps one to the other, and that
+    /// indices fit in the table. The converse does not hold: when the leading piece is ambiguous,
+    /// as with pawns on a7 and h7, mirror images can have different indices.
+    fn assert_indexed_up_to_symmetry(
+        name: &str,
+        pieces: &[u8],
+        order: [u8; 2],
+        placements: impl Iterator<Item = Vec<(usize, Piece)>>,
+    ) {
+        let (table, data) = table(name, pieces, order);
+        let symmetries: &[usize] = if table.has_pawns {
+            &[0, 1]
+        } else {
+            &[0, 1, 2, 3, 4, 5, 6, 7]
+        };
+        let index = |placement: &[(usize, Piece)]| {
+            let mut board = Board::empty();
+            for &(square, piece) in placement {
+                board.set_square(Coordinate::from_index(square), Some(piece));
+            }
+            let (d, file, idx) = table.index(&data, &board).unwrap().unwrap();
+            let groups = d.group_len.iter().position(|&len| len == 0).unwrap();
+            assert!(idx < d.group_idx[groups], "{idx} out of range");
+            (file, idx)
+        };
+
+        let mut classes = HashMap::new();
+        let mut checked = 0;
+        for placement in placements {
+            let images: Vec<Vec<_>> = symmetries
+                .iter()
+                .map(|&symmetry| {
+                    let mut image: Vec<_> = placement
+                        .iter()
+                        .map(|&(square, piece)| (piece_code(piece), transform(square, symmetry)))
+                        .collect();
+                    image.sort_unstable();
+                    image
+                })
+                .collect();
+            let canonical = images.iter().min().unwrap().clone();
+            let idx = index(&placement);
+            let class = classes.entry(idx).or_insert_with(|| canonical.clone());
+            assert_eq!(*class, canonical, "different positions at index {idx:?}");
+            checked += 1;
+        }
+        assert!(checked > 1000);
+    }
+
+    /// Pseudo-random placements of the given pieces on distinct squares, with the kings apart
+    /// and pawns on ranks 2 to 7. One square in four is drawn from the long diagonals, where
+    /// the index has special cases.
+    fn placements(pieces: &[Piece], count: usize) -> impl Iterator<Item = Vec<(usize, Piece)>> {
+        let pieces = pieces.to_vec();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        std::iter::repeat_with(move || {
+            let mut taken: Vec<(usize, Piece)> = Vec::new();
+            for &piece in &pieces {
+                loop {
+                    let roll = next();
+                    let square = if roll % 4 == 0 {
+                        let i = (roll >> 8) as usize % 8;
+                        if roll & 0x10 != 0 { i * 9 } else { i * 7 + 7 }
+                    } else {
+                        (roll >> 8) as usize % 64
+                    };
+                    let pawn_ok = piece.piece_type != PieceType::Pawn || (8..56).contains(&square);
+                    let kings_apart = piece.piece_type != PieceType::King
+                        || taken.iter().all(|&(other, other_piece)| {
+                            other_piece.piece_type != PieceType::King
+                                || (other % 8).abs_diff(square % 8) > 1
+                                || (other / 8).abs_diff(square / 8) > 1
+                        });
+                    if pawn_ok && kings_apart && taken.iter().all(|&(other, _)| other != square) {
+                        taken.push((square, piece));
+                        break;
+                    }
+                }
+            }
+            taken
+        })
+        .take(count)
+    }
+
+    const WK: Piece = Piece::king(Colour::White);
+    const BK: Piece = Piece::king(Colour::Black);
+
+    #[test]
+    fn indexes_three_unique_pieces() {
+        let rook = Piece::rook(Colour::White);
+        assert_indexed_up_to_symmetry(
+            "KRvK",
+            &[6, 4, 14],
+            [0, 0xf],
+            placements(&[WK, rook, BK], 20_000),
+        );
+    }
+
+    #[test]
+    fn indexes_king_pair_and_twins() {
+        let rook = Piece::rook(Colour::White);
+        assert_indexed_up_to_symmetry(
+            "KRRvK",
+            &[6, 14, 4, 4],
+            [0, 0xf],
+            placements(&[WK, BK, rook, rook], 20_000),
+        );
+    }
+
+    #[test]
+    fn indexes_pawns() {
+        let pawn = Piece::pawn(Colour::White);
+        assert_indexed_up_to_symmetry(
+            "KPPvK",
+            &[1, 1, 6, 14],
+            [0, 0xf],
+            placements(&[WK, BK, pawn, pawn], 20_000),
+        );
+        assert_indexed_up_to_symmetry(
+            "KPvKP",
+            &[1, 9, 6, 14],
+            [0, 1],
+            placements(&[WK, BK, pawn, Piece::pawn(Colour::Black)], 20_000),
+        );
+    }
+
+    /// Pairs data with a hand-made Huffman code: "1" for a pair of values 0 and 2, "00" for 0
+    /// and "01" for 2, values being 2 above a WDL score.
+    fn huffman_table() -> (PairsData, Vec<u8>) {
+        let mut d = PairsData::default();
+        d.group_len[0] = 1;
+        d.group_idx[1] = 6;
+        let mut bytes = vec![
+            0, // flags
+            5, // 32 byte blocks
+            3, // a sparse index entry every 8 values
+            0, // padding
+            1, 0, 0, 0, // blocks
+            2, 1, // longest and shortest code
+            2, 0, 0, 0, // lowest symbol of each length
+            3, 0, // symbols
+            2, 0xf0, 0xff, // 0: leaf, value 2
+            4, 0xf0, 0xff, // 1: leaf, value 4
+            0, 0x10, 0x00, // 2: symbols 0 then 1
+            0,    // padding
+        ];
+        let end = d.read_sizes(&bytes, 0).unwrap();
+        assert_eq!(end, bytes.len());
+        d.sparse_index = bytes.len();
+        // The value at index 4 is at offset 4 in block 0.
+        bytes.extend([0, 0, 0, 0, 4, 0]);
+        d.block_length = bytes.len();
+        bytes.extend([5, 0]);
+        bytes.resize(64, 0);
+        d.data = 64;
+        // 1 01 00 1: 0 2, 2, 0, 0 2
+        bytes.push(0b1010_0100);
+        bytes.resize(96, 0);
+        (d, bytes)
+    }
+
+    #[test]
+    fn decodes_huffman_pairs() {
+        let (d, bytes) = huffman_table();
+        assert_eq!(d.symlen, [0, 0, 1]);
+        let values: Vec<_> = (0..6).map(|idx| d.decompress(&bytes, idx)).collect();
+        assert_eq!(values, [2, 4, 4, 2, 2, 4]);
+    }
+
+    #[test]
+    fn magic_numbers() {
+        // The first bytes of every published table, e.g. KQvK.rtbw and KQvK.rtbz
+        assert_eq!(TableKind::Wdl.magic(), [0x71, 0xe8, 0x23, 0x5d]);
+        assert_eq!(TableKind::Dtz.magic(), [0xd7, 0x66, 0x0c, 0xa5]);
+    }
+
+    #[test]
+    fn single_value_tables() {
+        let mut d = PairsData::default();
+        assert_eq!(d.read_sizes(&[SINGLE_VALUE, 3], 0), Ok(2));
+        assert_eq!(d.decompress(&[], 12_345), 3);
+    }
+}
@@ -0,0 +1,193 @@
+//! Deciding how long to think about a move when playing on a clock.
+
+use crate::board::Move;
+use std::time::Duration;
+
+/// The engine's clock when asked to move, as reported by the GUI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    /// Added to the clock after each move.
+    pub increment: Duration,
+    /// Moves until the next time control, `None` if the remaining time has to last the game.
+    pub moves_to_go: Option<u32>,
+    /// Time lost per move to communication with the GUI, kept in reserve.
+    pub overhead: Duration,
+}
+
+impl Clock {
+    pub const DEFAULT_OVERHEAD: Duration = Duration::from_millis(50);
+
+    pub fn new(remaining: Duration, increment: Duration) -> Self {
+        Self {
+            remaining,
+            increment,
+            moves_to_go: None,
+            overhead: Self::DEFAULT_OVERHEAD,
+        }
+    }
+}
+
+/// How many more moves the remaining time is assumed to be needed for when the time control
+/// does not say.
+const SUDDEN_DEATH_MOVES: u32 = 30;
+/// The hard limit is at most this many times the soft limit.
+const MAX_OVERRUN: u32 = 4;
+
+/// Splits a clock into a soft limit, after which the search does not start another iteration,
+/// and a hard limit at which it stops mid-iteration.
+///
+/// Between iterations the soft limit is stretched when the best move keeps changing or the
+/// score drops, and shrunk when the best move has been the same for several iterations.
+#[derive(Clone, Debug)]
+pub(crate) struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+    best_move: Option<Move>,
+    score: Option<i32>,
+    /// Iterations in a row that have kept the same best move.
+    stable_iterations: u32,
+    /// How much to scale the soft limit by, given the last iteration.
+    scale: f64,
+}
+
+impl TimeManager {
+    pub(crate) fn new(clock: &Clock) -> Self {
+        let available = clock.remaining.saturating_sub(clock.overhead);
+        let moves = clock.moves_to_go.unwrap_or(SUDDEN_DEATH_MOVES).max(1);
+        let base = available / moves + clock.increment * 3 / 4;
+
+        // Never risk more than part of the clock on one move unless it is the last before the
+        // time control, so a single long search cannot leave too little for the rest.
+        let cap = if moves == 1 {
+            available * 9 / 10
+        } else {
+            available / 2
+        };
+        let hard = (base * MAX_OVERRUN).min(cap).max(Duration::from_millis(1));
+        let soft = base.min(hard);
+        Self {
+            soft,
+            hard,
+            best_move: None,
+            score: None,
+            stable_iterations: 0,
+            scale: 1.0,
+        }
+    }
+
+    pub(crate) fn hard_limit(&self) -> Duration {
+        self.hard
+    }
+
+    /// The soft limit as adjusted after the last iteration.
+    pub(crate) fn soft_limit(&self) -> Duration {
+        self.soft.mul_f64(self.scale).min(self.hard)
+    }
+
+    /// Takes the best move and score of a completed iteration into account.
+    pub(crate) fn update(&mut self, best_move: Option<Move>, score: i32) {
+        if best_move == self.best_move {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+        }
+        let stability = match self.stable_iterations {
+            0 => 1.4,
+            1 => 1.2,
+            2 => 1.0,
+            3 => 0.85,
+            _ => 0.7,
+        };
+        // A falling score means trouble the search may still find a way out of.
+        let drop = self
+            .score
+            .map_or(0, |previous| previous - score)
+            .clamp(0, 200);
+        let swing = 1.0 + drop as f64 / 200.0;
+
+        self.best_move = best_move;
+        self.score = Some(score);
+        self.scale = stability * swing;
+    }
+
+    /// Whether there is time to start another iteration.
+    pub(crate) fn should_continue(&self, elapsed: Duration) -> bool {
+        elapsed < self.soft_limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use rstest::rstest;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[rstest]
+    #[case::sudden_death(Clock::new(60_000 * MS, Duration::ZERO))]
+    #[case::fischer(Clock::new(10_000 * MS, 1_000 * MS))]
+    #[case::fischer_low_on_time(Clock::new(300 * MS, 2_000 * MS))]
+    #[case::moves_to_go(Clock { moves_to_go: Some(10), ..Clock::new(60_000 * MS, Duration::ZERO) })]
+    #[case::last_move_before_control(Clock { moves_to_go: Some(1), ..Clock::new(5_000 * MS, Duration::ZERO) })]
+    #[case::almost_flagged(Clock::new(20 * MS, Duration::ZERO))]
+    fn limits_stay_within_the_clock(#[case] clock: Clock) {
+        let time_manager = TimeManager::new(&clock);
+        assert!(time_manager.soft_limit() <= time_manager.hard_limit());
+        let available = clock.remaining.saturating_sub(clock.overhead);
+        assert!(time_manager.hard_limit() <= available.max(MS));
+        assert!(time_manager.hard_limit() >= MS);
+    }
+
+    #[test]
+    fn splits_time_between_moves() {
+        let time_manager = TimeManager::new(&Clock::new(30_050 * MS, Duration::ZERO));
+        assert_eq!(time_manager.soft_limit(), 1_000 * MS);
+        assert_eq!(time_manager.hard_limit(), 4_000 * MS);
+
+        let with_increment = TimeManager::new(&Clock::new(30_050 * MS, 2_000 * MS));
+        assert_eq!(with_increment.soft_limit(), 2_500 * MS);
+
+        let moves_to_go = TimeManager::new(&Clock {
+            moves_to_go: Some(5),
+            ..Clock::new(10_050 * MS, Duration::ZERO)
+        });
+        assert_eq!(moves_to_go.soft_limit(), 2_000 * MS);
+    }
+
+    #[test]
+    fn stable_best_move_saves_time() {
+        let board = Board::new();
+        let moves = board.get_legal_moves();
+        let mut time_manager = TimeManager::new(&Clock::new(30_050 * MS, Duration::ZERO));
+        let base = time_manager.soft_limit();
+
+        time_manager.update(Some(moves[0]), 20);
+        assert!(time_manager.soft_limit() > base);
+        for _ in 0..4 {
+            time_manager.update(Some(moves[0]), 20);
+        }
+        assert!(time_manager.soft_limit() < base);
+
+        // Changing its mind makes the search think longer again
+        time_manager.update(Some(moves[1]), 20);
+        assert!(time_manager.soft_limit() > base);
+    }
+
+    #[test]
+    fn falling_score_extends_the_search() {
+        let board = Board::new();
+        let best = board.get_legal_moves().first().copied();
+        let mut steady = TimeManager::new(&Clock::new(30_050 * MS, Duration::ZERO));
+        let mut falling = steady.clone();
+        for score in [50, 50, 50] {
+            steady.update(best, score);
+        }
+        for score in [50, 50, -100] {
+            falling.update(best, score);
+        }
+        assert!(falling.soft_limit() > steady.soft_limit());
+        assert!(falling.soft_limit() <= falling.hard_limit());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::board::Move;
+use crate::search::MATE_BOUND;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How a stored score relates to the position's true score.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact, it fell inside the search window.
+    Exact,
+    /// The search failed high, the true score is at least this.
+    Lower,
+    /// The search failed low, the true score is at most this.
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtEntry {
+    key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+    generation: u8,
+}
+
+// An entry is packed into a single word: bits 0-25 hold the move, bit 26 whether there is one,
+// 27-42 the score, 43-50 the depth, 51-52 the bound and 53-60 the generation. Bit 63 marks the
+// slot as used.
+const MOVE_BITS: u64 = (1 << 26) - 1;
+const HAS_MOVE: u64 = 1 << 26;
+const SCORE_SHIFT: u32 = 27;
+const DEPTH_SHIFT: u32 = 43;
+const BOUND_SHIFT: u32 = 51;
+const GENERATION_SHIFT: u32 = 53;
+const USED: u64 = 1 << 63;
+
+impl TtEntry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        self.best_move
+            .map_or(0, |mv| mv.to_bits() as u64 | HAS_MOVE)
+            | (self.score as i16 as u16 as u64) << SCORE_SHIFT
+            | (self.depth.min(u8::MAX as u32) as u64) << DEPTH_SHIFT
+            | bound << BOUND_SHIFT
+            | (self.generation as u64) << GENERATION_SHIFT
+            | USED
+    }
+
+    fn unpack(key: u64, data: u64) -> Option<Self> {
+        if data & USED == 0 {
+            return None;
+        }
+        let best_move = if data & HAS_MOVE != 0 {
+            Move::from_bits((data & MOVE_BITS) as u32)
+        } else {
+            None
+        };
+        let bound = match (data >> BOUND_SHIFT) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        Some(Self {
+            key,
+            best_move,
+            score: (data >> SCORE_SHIFT) as u16 as i16 as i32,
+            depth: ((data >> DEPTH_SHIFT) & 0xFF) as u32,
+            bound,
+            generation: (data >> GENERATION_SHIFT) as u8,
+        })
+    }
+}
+
+/// One slot of the table. The key is stored xored with the data, so an entry torn by two threads
+/// writing at once fails to match its key rather than returning another position's data.
+#[derive(Debug, Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> Option<TtEntry> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        TtEntry::unpack(key, data)
+    }
+
+    fn save(&self, entry: &TtEntry) {
+        let data = entry.pack();
+        self.key.store(entry.key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// A fixed-size hash table of previously searched positions, indexed by Zobrist hash.
+///
+/// Entries are tagged with the search generation that wrote them, so a new search prefers to
+/// overwrite stale entries over deeper ones from the current search.
+///
+/// The table is lock free and can be shared by several search threads, which store and probe
+/// through a shared reference.
+#[derive(Debug)]
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    generation: u8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SIZE_MB)
+    }
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_SIZE_MB: usize = 16;
+
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / mem::size_of::<Slot>()).max(1);
+        Self {
+            slots: (0..len).map(|_| Slot::default()).collect(),
+            generation: 0,
+        }
+    }
+
+    /// Reallocates the table to `size_mb` megabytes, discarding its contents.
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = Self::new(size_mb);
+    }
+
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            *slot = Slot::default();
+        }
+        self.generation = 0;
+    }
+
+    /// Marks the start of a new search, ageing everything already stored.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        // Maps the key onto the table without the bias or cost of a modulo.
+        ((key as u128 * self.slots.len() as u128) >> 64) as usize
+    }
+
+    /// Looks up `key`, adjusting a stored mate score to be relative to `ply`.
+    pub fn probe(&self, key: u64, ply: u32) -> Option<TtEntry> {
+        self.slots[self.index(key)]
+            .load()
+            .filter(|entry| entry.key == key)
+            .map(|entry| TtEntry {
+                score: score_from_tt(entry.score, ply),
+                ..entry
+            })
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        depth: u32,
+        bound: Bound,
+        score: i32,
+        best_move: Option<Move>,
+        ply: u32,
+    ) {
+        let generation = self.generation;
+        let slot = &self.slots[self.index(key)];
+        let existing = slot.load();
+        if let Some(existing) = existing {
+            let replace = existing.key == key
+                || existing.generation != generation
+                || depth >= existing.depth
+                || bound == Bound::Exact;
+            if !replace {
+                return;
+            }
+        }
+        // Keep the previous best move when re-storing a position without one, e.g. after
+        // failing low.
+        let best_move = best_move.or_else(|| {
+            existing
+                .filter(|existing| existing.key == key)
+                .and_then(|existing| existing.best_move)
+        });
+        slot.save(&TtEntry {
+            key,
+            best_move,
+            score: score_to_tt(score, ply),
+            depth,
+            bound,
+            generation,
+        });
+    }
+
+    /// Permille of the table filled by the current search, as reported to UCI GUIs.
+    pub fn hashfull(&self) -> usize {
+        let sample = self.slots.len().min(1000);
+        self.slots[..sample]
+            .iter()
+            .filter_map(Slot::load)
+            .filter(|entry| entry.generation == self.generation)
+            .count()
+            * 1000
+            / sample
+    }
+}
+
+// Mate scores are relative to the root, but the same position can be reached at different plies.
+// They are stored relative to the position itself and converted back when probed.
+
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::search::MATE;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        tt.store(42, 3, Bound::Exact, 17, None, 0);
+        let entry = tt.probe(42, 0).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score),
+            (3, Bound::Exact, 17)
+        );
+        assert_eq!(tt.probe(43, 0), None);
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_position() {
+        let tt = TranspositionTable::new(1);
+        // Mate in 3 plies from a node 5 plies below the root
+        tt.store(7, 4, Bound::Exact, MATE - 8, None, 5);
+        // Reached again 2 plies below the root, the mate is now 5 plies away
+        assert_eq!(tt.probe(7, 2).unwrap().score, MATE - 5);
+
+        tt.store(9, 4, Bound::Exact, -MATE + 6, None, 4);
+        assert_eq!(tt.probe(9, 0).unwrap().score, -MATE + 2);
+    }
+
+    #[test]
+    fn deeper_entries_survive_within_a_search() {
+        let mut tt = TranspositionTable::new(1);
+        let len = tt.slots.len() as u128;
+        // Two keys landing in the same slot
+        let key = 1u64 << 63;
+        let other = key + (u64::MAX as u128 / len) as u64 / 4;
+        assert_eq!(tt.index(key), tt.index(other));
+
+        tt.store(key, 8, Bound::Lower, 10, None, 0);
+        tt.store(other, 2, Bound::Upper, 20, None, 0);
+        assert!(tt.probe(key, 0).is_some());
+
+        tt.new_search();
+        tt.store(other, 2, Bound::Upper, 20, None, 0);
+        assert!(tt.probe(key, 0).is_none());
+        assert!(tt.probe(other, 0).is_some());
+    }
+
+    #[test]
+    fn moves_and_negative_scores_survive_packing() {
+        let tt = TranspositionTable::new(1);
+        // Castling, en passant, promotions and captures
+        let board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        for (key, mv) in board.get_legal_moves().into_iter().enumerate() {
+            let key = key as u64 + 1;
+            tt.store(key, 5, Bound::Upper, -1234, Some(mv), 0);
+            let entry = tt.probe(key, 0).unwrap();
+            assert_eq!((entry.best_move, entry.score), (Some(mv), -1234));
+        }
+    }
+
+    #[test]
+    fn resize_changes_capacity() {
+        let mut tt = TranspositionTable::new(1);
+        let small = tt.slots.len();
+        tt.resize(2);
+        assert_eq!(tt.slots.len(), small * 2);
+    }
+}
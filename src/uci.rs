@@ -0,0 +1,640 @@
+//! The Universal Chess Interface, the text protocol most chess GUIs and tournament managers use
+//! to drive an engine over stdin and stdout.
+
+use crate::board::{Board, Colour};
+use crate::book::{Book, Selection};
+use crate::engine::Engine;
+use crate::search::{PvLine, SearchLimits, SearchOptions, SearchResult, Searcher};
+use crate::syzygy::Tablebase;
+use crate::timeman::Clock;
+use crate::tt::TranspositionTable;
+use crate::variant::Variant;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const MAX_HASH_MB: usize = 65_536;
+const MAX_OVERHEAD_MS: u64 = 5_000;
+const MAX_MULTI_PV: usize = 500;
+/// Switches for the search's selective techniques, for measuring them in self-play.
+const SEARCH_SWITCHES: [&str; 6] = [
+    "NullMove",
+    "LateMoveReductions",
+    "Futility",
+    "ReverseFutility",
+    "CheckExtensions",
+    "AspirationWindows",
+];
+
+/// The field of `options` behind one of `SEARCH_SWITCHES`, given its lowercased name.
+fn search_switch<'a>(options: &'a mut SearchOptions, name: &str) -> Option<&'a mut bool> {
+    Some(match name {
+        "nullmove" => &mut options.null_move,
+        "latemovereductions" => &mut options.late_move_reductions,
+        "futility" => &mut options.futility,
+        "reversefutility" => &mut options.reverse_futility,
+        "checkextensions" => &mut options.check_extensions,
+        "aspirationwindows" => &mut options.aspiration_windows,
+        _ => return None,
+    })
+}
+
+/// Reads UCI commands from `input` line by line and answers on `output` until `quit` or the end
+/// of the input.
+pub fn run(input: impl BufRead, output: impl Write + Send + 'static) {
+    let mut uci = Uci::new(output);
+    for line in input.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if uci.handle(&line).is_break() {
+            break;
+        }
+    }
+}
+
+struct Uci<W> {
+    board: Board,
+    engine: Engine,
+    /// Set by the `Move Overhead` option.
+    overhead: Duration,
+    /// Set by the `Book File` option, consulted before searching.
+    book: Option<Book<BufReader<File>>>,
+    /// Set by the `UCI_Chess960` option, which writes castling as the king taking its rook.
+    chess960: bool,
+    /// Set by the `UCI_Variant` option, the rules `position` sets up.
+    variant: Variant,
+    // Shared with the search thread, which reports its progress and best move.
+    output: Arc<Mutex<W>>,
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    fn new(output: W) -> Self {
+        Self {
+            board: Board::new(),
+            engine: Engine::default(),
+            overhead: Clock::DEFAULT_OVERHEAD,
+            book: None,
+            chess960: false,
+            variant: Variant::Standard,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> ControlFlow<()> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send(format_args!(
+                    "id name ferrous-chess {}",
+                    env!("CARGO_PKG_VERSION")
+                ));
+                self.send("id author the ferrous-chess developers");
+                self.send(format_args!(
+                    "option name Hash type spin default {} min 1 max {MAX_HASH_MB}",
+                    TranspositionTable::DEFAULT_SIZE_MB
+                ));
+                self.send(format_args!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    Searcher::MAX_THREADS
+                ));
+                self.send(format_args!(
+                    "option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}"
+                ));
+                self.send(format_args!(
+                    "option name Move Overhead type spin default {} min 0 max {MAX_OVERHEAD_MS}",
+                    Clock::DEFAULT_OVERHEAD.as_millis()
+                ));
+                self.send("option name Book File type string default <empty>");
+                self.send("option name SyzygyPath type string default <empty>");
+                self.send("option name UCI_Chess960 type check default false");
+                let variants = Variant::ALL
+                    .map(|variant| format!(" var {variant}"))
+                    .concat();
+                self.send(format_args!(
+                    "option name UCI_Variant type combo default {}{variants}",
+                    Variant::Standard
+                ));
+                for name in SEARCH_SWITCHES {
+                    self.send(format_args!("option name {name} type check default true"));
+                }
+                self.send("uciok");
+            }
+            Some("isready") => self.send("readyok"),
+            Some("ucinewgame") => {
+                self.engine.searcher().clear();
+                self.board = Board::start(self.variant);
+            }
+            Some("setoption") => self.set_option(&tokens.collect::<Vec<_>>()),
+            Some("position") => match parse_position(self.variant, &tokens.collect::<Vec<_>>()) {
+                Ok(board) => self.board = board,
+                Err(err) => self.send(format_args!("info string {err}")),
+            },
+            Some("go") => self.go(&tokens.collect::<Vec<_>>()),
+            Some("stop") => self.engine.stop(),
+            Some("quit") => return ControlFlow::Break(()),
+            // Unknown commands are ignored, as the protocol asks.
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn send(&self, line: impl Display) {
+        send(&self.output, line);
+    }
+
+    /// Handles `setoption name <name> value <value>`.
+    fn set_option(&mut self, tokens: &[&str]) {
+        let value_at = tokens.iter().position(|&token| token == "value");
+        let name = tokens[..value_at.unwrap_or(tokens.len())]
+            .iter()
+            .skip_while(|&&token| token == "name")
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Only file names may contain spaces, but every value is taken to the end of the line.
+        let value = value_at
+            .map(|at| tokens[at + 1..].join(" "))
+            .filter(|value| !value.is_empty());
+        let value = value.as_deref();
+
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("hash", Some(value)) => match value.parse::<usize>() {
+                Ok(size_mb) if (1..=MAX_HASH_MB).contains(&size_mb) => {
+                    self.engine.searcher().set_hash_size(size_mb)
+                }
+                _ => self.send(format_args!("info string Invalid Hash value {value}")),
+            },
+            ("book file", None | Some("<empty>")) => self.book = None,
+            ("book file", Some(path)) => match Book::open(path) {
+                Ok(book) => self.book = Some(book),
+                Err(err) => {
+                    self.book = None;
+                    self.send(format_args!("info string Cannot open book {path}: {err}"));
+                }
+            },
+            ("syzygypath", None | Some("<empty>")) => self.engine.searcher().set_tablebase(None),
+            ("syzygypath", Some(paths)) => {
+                // Several directories are separated as in the PATH variable.
+                let mut tablebase = Tablebase::new();
+                for dir in env::split_paths(paths) {
+                    if let Err(err) = tablebase.add_directory(&dir) {
+                        self.send(format_args!(
+                            "info string Cannot open tablebase directory {}: {err}",
+                            dir.display()
+                        ));
+                    }
+                }
+                self.send(format_args!(
+                    "info string Found tablebases for up to {} pieces",
+                    tablebase.max_pieces()
+                ));
+                self.engine
+                    .searcher()
+                    .set_tablebase(Some(Arc::new(tablebase)));
+            }
+            ("uci_chess960", Some(value)) => match value.parse() {
+                Ok(on) => self.chess960 = on,
+                _ => self.send(format_args!(
+                    "info string Invalid UCI_Chess960 value {value}"
+                )),
+            },
+            ("uci_variant", Some(value)) => match value.parse() {
+                Ok(variant) => {
+                    self.variant = variant;
+                    self.board = Board::start(variant);
+                }
+                _ => self.send(format_args!("info string Unknown variant {value}")),
+            },
+            ("move overhead", Some(value)) => match value.parse::<u64>() {
+                Ok(ms) if ms <= MAX_OVERHEAD_MS => self.overhead = Duration::from_millis(ms),
+                _ => self.send(format_args!(
+                    "info string Invalid Move Overhead value {value}"
+                )),
+            },
+            ("threads", Some(value)) => match value.parse::<usize>() {
+                Ok(threads) if (1..=Searcher::MAX_THREADS).contains(&threads) => {
+                    self.engine.searcher().set_threads(threads)
+                }
+                _ => self.send(format_args!("info string Invalid Threads value {value}")),
+            },
+            ("multipv", Some(value)) => match value.parse::<usize>() {
+                Ok(lines) if (1..=MAX_MULTI_PV).contains(&lines) => {
+                    self.engine.searcher().set_multi_pv(lines)
+                }
+                _ => self.send(format_args!("info string Invalid MultiPV value {value}")),
+            },
+            (switch_name, value) => {
+                let mut options = self.engine.searcher().options();
+                match (search_switch(&mut options, switch_name), value) {
+                    (Some(switch), Some(value)) => match value.parse() {
+                        Ok(on) => {
+                            *switch = on;
+                            self.engine.searcher().set_options(options);
+                        }
+                        _ => self.send(format_args!("info string Invalid {name} value {value}")),
+                    },
+                    _ => self.send(format_args!("info string Unknown option {name}")),
+                }
+            }
+        }
+    }
+
+    /// Handles `go`, starting a search in the background.
+    fn go(&mut self, tokens: &[&str]) {
+        let (limits, infinite) = self.parse_go(tokens);
+        // An infinite search must not answer before `stop`, so it is never cut short by the book.
+        if let Some(book) = &mut self.book
+            && !infinite
+        {
+            match book.choose_move(&self.board, Selection::WeightedRandom) {
+                Ok(Some(mv)) => {
+                    return self.send(format_args!("bestmove {}", mv.to_uci(self.chess960)));
+                }
+                Ok(None) => {}
+                Err(err) => self.send(format_args!("info string Cannot read book: {err}")),
+            }
+        }
+        let info_output = Arc::clone(&self.output);
+        let bestmove_output = Arc::clone(&self.output);
+        let chess960 = self.chess960;
+        self.engine.start(
+            self.board.clone(),
+            limits,
+            infinite,
+            move |result| {
+                for (index, line) in result.lines.iter().enumerate() {
+                    send(&info_output, info_line(result, index + 1, line, chess960));
+                }
+            },
+            move |result| {
+                let best_move = result
+                    .best_move
+                    .map_or_else(|| "0000".to_string(), |mv| mv.to_uci(chess960));
+                send(&bestmove_output, format_args!("bestmove {best_move}"));
+            },
+        );
+    }
+
+    /// Parses the search parameters of `go`, and whether it was `go infinite`.
+    fn parse_go(&self, tokens: &[&str]) -> (SearchLimits, bool) {
+        let mut limits = SearchLimits::default();
+        let mut infinite = false;
+        let (mut time, mut increment) = (None, Duration::ZERO);
+        let mut moves_to_go = None;
+        let (time_key, increment_key) = match self.board.turn() {
+            Colour::White => ("wtime", "winc"),
+            Colour::Black => ("btime", "binc"),
+        };
+
+        let mut tokens = tokens.iter();
+        while let Some(&token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+            match token {
+                "depth" => limits.depth = value().map(|depth| depth as u32),
+                "nodes" => limits.nodes = value(),
+                "movetime" => limits.time = value().map(Duration::from_millis),
+                "movestogo" => moves_to_go = value().map(|moves| moves as u32),
+                "infinite" => infinite = true,
+                key if key == time_key => time = value().map(Duration::from_millis),
+                key if key == increment_key => {
+                    increment = value().map_or(Duration::ZERO, Duration::from_millis)
+                }
+                _ => {}
+            }
+        }
+        limits.clock = time.map(|remaining| Clock {
+            moves_to_go,
+            overhead: self.overhead,
+            ..Clock::new(remaining, increment)
+        });
+        (limits, infinite)
+    }
+}
+
+fn send(output: &Mutex<impl Write>, line: impl Display) {
+    let mut output = output
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // There is nobody left to tell if the GUI has gone away.
+    let _ = writeln!(output, "{line}").and_then(|()| output.flush());
+}
+
+/// Parses the arguments of `position` for a game of `variant`: `startpos` or `fen <fen>`,
+/// optionally followed by `moves` and the moves played since, in long algebraic notation.
+fn parse_position(variant: Variant, tokens: &[&str]) -> Result<Board, &'static str> {
+    let moves_at = tokens
+        .iter()
+        .position(|&token| token == "moves")
+        .unwrap_or(tokens.len());
+    let mut board = match tokens[..moves_at] {
+        ["startpos"] => Board::start(variant),
+        ["fen", ref fen @ ..] => Board::from_variant_fen(variant, &fen.join(" "))?,
+        _ => return Err("Expected startpos or fen"),
+    };
+    for notation in tokens.iter().skip(moves_at + 1) {
+        let mv = board.parse_move(notation)?;
+        board.make_move(mv)?;
+    }
+    Ok(board)
+}
+
+/// Reports one line of a completed iteration, `multipv` counting from 1 for the best.
+fn info_line(result: &SearchResult, multipv: usize, line: &PvLine, chess960: bool) -> String {
+    let score = match line.mate_in() {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {}", line.score),
+    };
+    let pv = line
+        .pv
+        .iter()
+        .map(|mv| mv.to_uci(chess960))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "info depth {} multipv {multipv} score {score} nodes {} nps {} time {} pv {pv}",
+        result.depth,
+        result.nodes,
+        result.nps(),
+        result.elapsed.as_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_commands(commands: &str) -> Vec<String> {
+        let output = Output::default();
+        run(commands.as_bytes(), output.clone());
+        let bytes = output.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn handshake() {
+        let lines = run_commands("uci\nisready\n");
+        assert!(lines[0].starts_with("id name ferrous-chess"));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("option name Hash type spin"))
+        );
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    #[test]
+    fn searches_to_depth_and_reports() {
+        let lines = run_commands("position startpos moves e2e4 e7e5\ngo depth 3\n");
+        let infos: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with("info"))
+            .collect();
+        assert!(infos.len() <= 3);
+        for field in ["depth", "score cp", "nodes", "nps", "time", "pv"] {
+            assert!(infos[0].contains(field), "{} lacks {field}", infos[0]);
+        }
+
+        let best = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        let mut board = Board::new();
+        for notation in ["e2e4", "e7e5"] {
+            board
+                .make_move(board.parse_move(notation).unwrap())
+                .unwrap();
+        }
+        assert!(board.parse_move(best).is_ok());
+    }
+
+    #[test]
+    fn reports_mate_scores() {
+        let lines = run_commands("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 2\n");
+        assert!(lines.iter().any(|line| line.contains("score mate 1 ")));
+        assert_eq!(lines.last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn reports_each_multi_pv_line() {
+        let lines = run_commands("setoption name MultiPV value 3\nposition startpos\ngo depth 2\n");
+        for multipv in 1..=3 {
+            let prefix = format!("info depth 2 multipv {multipv} ");
+            assert!(lines.iter().any(|line| line.starts_with(&prefix)));
+        }
+        assert!(!lines.iter().any(|line| line.contains("multipv 4")));
+    }
+
+    #[test]
+    fn infinite_search_waits_for_stop() {
+        let lines = run_commands("position startpos\ngo infinite\nisready\nstop\n");
+        let ready = lines.iter().position(|line| line == "readyok").unwrap();
+        let best = lines
+            .iter()
+            .position(|line| line.starts_with("bestmove"))
+            .unwrap();
+        assert!(ready < best);
+        assert_eq!(best, lines.len() - 1);
+    }
+
+    #[test]
+    fn checkmated_side_has_no_move() {
+        let lines = run_commands("position fen R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1\ngo depth 1\n");
+        assert_eq!(lines.last().unwrap(), "bestmove 0000");
+    }
+
+    #[rstest]
+    #[case::start_position(&["startpos"], "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")]
+    #[case::with_moves(
+        &["startpos", "moves", "e2e4", "c7c5", "g1f3"],
+        "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    )]
+    #[case::fen_with_moves(
+        &["fen", "8/1P2k3/8/8/8/8/8/4K3", "w", "-", "-", "0", "1", "moves", "b7b8q"],
+        "1Q6/4k3/8/8/8/8/8/4K3 b - - 0 1"
+    )]
+    fn parses_positions(#[case] tokens: &[&str], #[case] fen: &str) {
+        assert_eq!(
+            parse_position(Variant::Standard, tokens).unwrap().to_fen(),
+            fen
+        );
+    }
+
+    #[rstest]
+    #[case::missing(&[], "Expected startpos or fen")]
+    #[case::bad_fen(&["fen", "8/8/8", "w", "-", "-", "0", "1"], "FEN piece placement must have 8 ranks")]
+    #[case::illegal_move(&["startpos", "moves", "e2e5"], "Illegal move")]
+    fn rejects_bad_positions(#[case] tokens: &[&str], #[case] err: &str) {
+        assert_eq!(parse_position(Variant::Standard, tokens), Err(err));
+    }
+
+    #[test]
+    fn clock_follows_the_side_to_move() {
+        let mut uci = Uci::new(Output::default());
+        let _ = uci.handle("setoption name Move Overhead value 100");
+        let _ = uci.handle("position startpos moves e2e4");
+        let (limits, infinite) = uci.parse_go(&[
+            "wtime",
+            "1000",
+            "btime",
+            "2000",
+            "binc",
+            "30",
+            "movestogo",
+            "12",
+        ]);
+        assert!(!infinite);
+        assert_eq!(
+            limits.clock,
+            Some(Clock {
+                remaining: Duration::from_millis(2000),
+                increment: Duration::from_millis(30),
+                moves_to_go: Some(12),
+                overhead: Duration::from_millis(100),
+            })
+        );
+    }
+
+    #[test]
+    fn toggles_search_techniques() {
+        let lines = run_commands("uci\n");
+        assert!(lines.contains(&"option name NullMove type check default true".to_string()));
+
+        let mut uci = Uci::new(Output::default());
+        let _ = uci.handle("setoption name NullMove value false");
+        let _ = uci.handle("setoption name aspirationwindows value false");
+        let _ = uci.handle("setoption name Futility value maybe");
+        let options = uci.engine.searcher().options();
+        assert!(!options.null_move && !options.aspiration_windows);
+        assert!(options.futility && options.late_move_reductions);
+    }
+
+    #[test]
+    fn plays_book_moves() {
+        use crate::book::{BookEntry, encode_move, polyglot_key};
+
+        let board = Board::new();
+        let entry = BookEntry {
+            key: polyglot_key(&board),
+            mv: encode_move(&board.parse_move("b1c3").unwrap()),
+            weight: 1,
+            learn: 0,
+        };
+        let path =
+            std::env::temp_dir().join(format!("ferrous-uci-book-{}.bin", std::process::id()));
+        std::fs::write(&path, entry.to_bytes()).unwrap();
+
+        let lines = run_commands(&format!(
+            "setoption name Book File value {}\nposition startpos\ngo depth 1\n\
+             position startpos moves e2e4\ngo depth 1\nsetoption name Book File value\n\
+             position startpos\ngo depth 1\n",
+            path.display()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        let best_moves: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with("bestmove"))
+            .collect();
+        assert_eq!(best_moves.len(), 3);
+        assert_eq!(best_moves[0], "bestmove b1c3");
+        // Out of book, the search decides
+        assert!(lines.iter().any(|line| line.starts_with("info depth 1")));
+
+        let lines = run_commands("setoption name Book File value /nonexistent/book.bin\n");
+        assert!(lines[0].starts_with("info string Cannot open book"));
+    }
+
+    #[test]
+    fn sets_syzygy_path() {
+        let mut uci = Uci::new(Output::default());
+        let dir = env::temp_dir();
+        let _ = uci.handle(&format!(
+            "setoption name SyzygyPath value {}",
+            dir.display()
+        ));
+        assert!(uci.engine.searcher().tablebase().is_some());
+        let _ = uci.handle("setoption name SyzygyPath value <empty>");
+        assert!(uci.engine.searcher().tablebase().is_none());
+
+        let lines = run_commands("setoption name SyzygyPath value /nonexistent/syzygy\n");
+        assert!(lines[0].starts_with("info string Cannot open tablebase directory"));
+        assert_eq!(lines[1], "info string Found tablebases for up to 0 pieces");
+    }
+
+    #[test]
+    fn chess960_castling_notation() {
+        let lines = run_commands("uci\n");
+        assert!(lines.contains(&"option name UCI_Chess960 type check default false".to_string()));
+
+        let mut uci = Uci::new(Output::default());
+        let _ = uci.handle("setoption name UCI_Chess960 value true");
+        assert!(uci.chess960);
+        // Both notations are understood whatever the option says
+        for castling in ["e1c1", "e1b1"] {
+            let _ = uci.handle(&format!(
+                "position fen 1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1 moves {castling}"
+            ));
+            assert_eq!(uci.board.to_fen(), "1r2k1r1/8/8/8/8/8/8/2KR2R1 b kq - 1 1");
+        }
+    }
+
+    #[test]
+    fn selects_variant() {
+        let lines = run_commands("uci\nsetoption name UCI_Variant value shogi\n");
+        assert!(
+            lines.contains(
+                &"option name UCI_Variant type combo default chess var chess var crazyhouse var 3check var kingofthehill var atomic var antichess var horde var racingkings"
+                    .to_string()
+            )
+        );
+        assert_eq!(lines.last().unwrap(), "info string Unknown variant shogi");
+
+        let mut uci = Uci::new(Output::default());
+        let _ = uci.handle("setoption name UCI_Variant value chess");
+        assert_eq!(uci.variant, Variant::Standard);
+    }
+
+    #[test]
+    fn sets_options() {
+        let mut uci = Uci::new(Output::default());
+        let _ = uci.handle("setoption name Threads value 3");
+        let _ = uci.handle("setoption name Hash value 1");
+        assert_eq!(uci.engine.searcher().threads(), 3);
+        assert_eq!(uci.engine.searcher().tt().hashfull(), 0);
+    }
+
+    #[test]
+    fn fixed_limits() {
+        let uci = Uci::new(Output::default());
+        let (limits, infinite) = uci.parse_go(&["depth", "7", "nodes", "5000", "movetime", "250"]);
+        assert_eq!(
+            limits,
+            SearchLimits {
+                depth: Some(7),
+                nodes: Some(5000),
+                time: Some(Duration::from_millis(250)),
+                clock: None,
+            }
+        );
+        assert!(!infinite);
+        assert!(uci.parse_go(&["infinite"]).1);
+    }
+}
@@ -0,0 +1,366 @@
+//! Chess variants. A `Board` plays by the rules of one `Variant`, which decides the start
+//! position, generates the legal moves, adds its own consequences to the moves `Board` plays by
+//! the classical rules, and decides any ways for the game to end besides running out of moves.
+
+mod antichess;
+mod atomic;
+mod crazyhouse;
+mod horde;
+
+use crate::board::{Board, Colour, Coordinate, Move, MoveKind, PROMOTION_TYPES, UndoState};
+use crate::game::{Outcome, Termination};
+use crate::piece::PieceType;
+use std::fmt;
+use std::str::FromStr;
+
+/// The rules a game is played by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// Classical chess. Chess960 positions are classical positions with unusual castling rights.
+    #[default]
+    Standard,
+    /// Captured pieces change sides and may be dropped back onto the board instead of moving.
+    Crazyhouse,
+    /// Giving a third check wins.
+    ThreeCheck,
+    /// Bringing the king to one of the four centre squares wins.
+    KingOfTheHill,
+    /// Captures blow up every piece but pawns around the destination square, the capturing
+    /// piece included, and blowing up the enemy king wins.
+    Atomic,
+    /// Captures are compulsory and the king is an ordinary piece. Losing every piece, or having
+    /// no move, wins.
+    Antichess,
+    /// White has 36 pawns and no king, and loses once Black has captured them all.
+    Horde,
+    /// Checks are forbidden and the first king to reach the eighth rank wins, although Black
+    /// draws by getting there on the very next move.
+    RacingKings,
+}
+
+/// The squares a king wins on in King of the Hill.
+const HILL: [Coordinate; 4] = [
+    Coordinate::new_unchecked(3, 3),
+    Coordinate::new_unchecked(4, 3),
+    Coordinate::new_unchecked(3, 4),
+    Coordinate::new_unchecked(4, 4),
+];
+
+impl Variant {
+    pub const ALL: [Self; 8] = [
+        Self::Standard,
+        Self::Crazyhouse,
+        Self::ThreeCheck,
+        Self::KingOfTheHill,
+        Self::Atomic,
+        Self::Antichess,
+        Self::Horde,
+        Self::RacingKings,
+    ];
+
+    /// The name used by UCI's `UCI_Variant` option.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Standard => "chess",
+            Self::Crazyhouse => "crazyhouse",
+            Self::ThreeCheck => "3check",
+            Self::KingOfTheHill => "kingofthehill",
+            Self::Atomic => "atomic",
+            Self::Antichess => "antichess",
+            Self::Horde => "horde",
+            Self::RacingKings => "racingkings",
+        }
+    }
+
+    /// The name used by XBoard's `variant` command.
+    pub const fn xboard_name(self) -> &'static str {
+        match self {
+            Self::Standard => "normal",
+            Self::Crazyhouse => "crazyhouse",
+            Self::ThreeCheck => "3check",
+            Self::KingOfTheHill => "kingofthehill",
+            Self::Atomic => "atomic",
+            Self::Antichess => "antichess",
+            Self::Horde => "horde",
+            Self::RacingKings => "racingkings",
+        }
+    }
+
+    /// The name used by PGN's `Variant` tag.
+    pub const fn pgn_name(self) -> &'static str {
+        match self {
+            Self::Standard => "Standard",
+            Self::Crazyhouse => "Crazyhouse",
+            Self::ThreeCheck => "Three-check",
+            Self::KingOfTheHill => "King of the Hill",
+            Self::Atomic => "Atomic",
+            Self::Antichess => "Antichess",
+            Self::Horde => "Horde",
+            Self::RacingKings => "Racing Kings",
+        }
+    }
+
+    /// The variant's start position, in the FEN dialect `Board::from_variant_fen` reads.
+    pub const fn start_fen(self) -> &'static str {
+        match self {
+            Self::Standard | Self::KingOfTheHill | Self::Atomic => {
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+            }
+            Self::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            Self::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0",
+            Self::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Self::Horde => {
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+            }
+            Self::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+        }
+    }
+
+    /// Whether captured pieces go to a pocket to be dropped later, as in crazyhouse.
+    pub const fn has_pockets(self) -> bool {
+        matches!(self, Self::Crazyhouse)
+    }
+
+    /// Whether the checks each side gives are counted, as in three-check.
+    pub const fn counts_checks(self) -> bool {
+        matches!(self, Self::ThreeCheck)
+    }
+
+    /// Whether the king is royal: it can be checked, cannot be captured and may castle. Not so in
+    /// antichess, where it is an ordinary piece that pawns may also promote to.
+    pub const fn has_royal_king(self) -> bool {
+        !matches!(self, Self::Antichess)
+    }
+
+    /// Generates the legal moves of `kind` in `board`, only for the piece on `only_from` if
+    /// given.
+    pub(crate) fn generate(
+        self,
+        board: &Board,
+        kind: MoveKind,
+        only_from: Option<Coordinate>,
+    ) -> Vec<Move> {
+        match self {
+            Self::Crazyhouse => {
+                let mut moves = board.classical_moves(kind, only_from);
+                // Drops never capture or promote.
+                if only_from.is_none() && kind != MoveKind::Tactical {
+                    moves.extend(crazyhouse::drops(board));
+                }
+                moves
+            }
+            Self::Atomic => atomic::moves(board, kind, only_from),
+            Self::Antichess => antichess::moves(board, kind, only_from),
+            Self::Horde => horde::moves(board, kind, only_from),
+            // Checks are forbidden.
+            Self::RacingKings => {
+                let mut moves = board.classical_moves(kind, only_from);
+                moves.retain(|mv| !board.gives_check(mv));
+                moves
+            }
+            Self::Standard | Self::ThreeCheck | Self::KingOfTheHill => {
+                board.classical_moves(kind, only_from)
+            }
+        }
+    }
+
+    /// Applies the variant's own consequences of `mv`, which `board` has just played by the
+    /// classical rules, saving in `undo` what `Variant::undo` needs to take them back.
+    pub(crate) fn after_move(self, board: &mut Board, mv: &Move, undo: &mut UndoState) {
+        match self {
+            Self::Crazyhouse => crazyhouse::update_pockets(board, mv),
+            Self::ThreeCheck if board.in_check() => board.count_check(mv.piece().colour),
+            // A capture blows up the capturing piece along with everything around it.
+            Self::Atomic if mv.captured().is_some() => undo.exploded = atomic::explode(board, mv),
+            Self::Horde => horde::after_move(board, mv),
+            _ => {}
+        }
+    }
+
+    /// Takes back what `Variant::after_move` did for `mv`, once `board` has taken back the move
+    /// itself. Pockets and check counters are restored with the rest of `undo`.
+    pub(crate) fn undo(self, board: &mut Board, mv: &Move, undo: &UndoState) {
+        if self == Self::Atomic {
+            atomic::restore(board, mv, &undo.exploded);
+        }
+    }
+
+    /// The pieces a pawn may promote to.
+    pub(crate) fn promotion_types(self) -> &'static [PieceType] {
+        match self {
+            Self::Antichess => &antichess::PROMOTION_TYPES,
+            _ => &PROMOTION_TYPES,
+        }
+    }
+
+    /// Whether `board` could come up in a game of the variant.
+    pub(crate) fn is_legal_position(self, board: &Board) -> bool {
+        match self {
+            Self::Antichess => antichess::is_legal_position(board),
+            Self::Horde => horde::is_legal_position(board),
+            Self::RacingKings => board.is_board_legal() && !board.in_check(),
+            _ => board.is_board_legal(),
+        }
+    }
+
+    /// Whether a king under attack in `board` is in check. Never in antichess, where the king is
+    /// an ordinary piece, nor in atomic chess while the kings touch, since capturing one would
+    /// blow up the other.
+    pub(crate) fn can_be_in_check(self, board: &Board) -> bool {
+        match self {
+            Self::Antichess => false,
+            Self::Atomic => !atomic::kings_touch(board),
+            _ => true,
+        }
+    }
+
+    /// Whether a castling king may pass through or land on `square` of `board`, which `by`'s
+    /// pieces may attack.
+    pub(crate) fn is_safe_for_castling_king(
+        self,
+        board: &Board,
+        square: &Coordinate,
+        by: Colour,
+    ) -> bool {
+        match self {
+            Self::Atomic => atomic::is_safe_for_castling_king(board, square, by),
+            _ => !board.is_square_attacked(square, by),
+        }
+    }
+
+    /// Static exchange evaluation of `mv` in `board`, in centipawns.
+    pub(crate) fn see(self, board: &Board, mv: &Move) -> i32 {
+        match self {
+            Self::Atomic if mv.captured().is_some() => atomic::see(board, mv),
+            _ => board.classical_see(mv),
+        }
+    }
+
+    /// The outcome if one of the variant's own rules has already ended the game, whatever moves
+    /// the side to move has left. In classical chess, that neither side has the material left
+    /// to mate; every other variant keeps some way to win, or drops to bring material back.
+    pub(crate) fn outcome(self, board: &Board) -> Option<Outcome> {
+        match self {
+            Self::Standard => board
+                .has_insufficient_material()
+                .then(|| Outcome::draw(Termination::InsufficientMaterial)),
+            Self::Crazyhouse => None,
+            Self::ThreeCheck => Colour::ALL
+                .into_iter()
+                .find(|&colour| board.checks_given(colour) >= 3)
+                .map(|colour| Outcome::win(colour, Termination::ThreeChecks)),
+            Self::KingOfTheHill => Colour::ALL
+                .into_iter()
+                .find(|&colour| {
+                    board
+                        .find_king(colour)
+                        .is_some_and(|king| HILL.contains(&king))
+                })
+                .map(|colour| Outcome::win(colour, Termination::KingOfTheHill)),
+            Self::Atomic => Colour::ALL
+                .into_iter()
+                .find(|&colour| board.find_king(colour).is_none())
+                .map(|colour| Outcome::win(colour.opposite(), Termination::Explosion)),
+            Self::Antichess => board
+                .into_iter()
+                .flatten()
+                .all(|(_, piece)| piece.colour != board.turn())
+                .then(|| Outcome::win(board.turn(), Termination::NoPiecesLeft)),
+            Self::Horde => board
+                .into_iter()
+                .flatten()
+                .all(|(_, piece)| piece.colour != Colour::White)
+                .then(|| Outcome::win(Colour::Black, Termination::NoPiecesLeft)),
+            Self::RacingKings => {
+                let on_goal = |colour| board.find_king(colour).is_some_and(|king| king.y() == 7);
+                let winner = match (on_goal(Colour::White), on_goal(Colour::Black)) {
+                    (false, false) => return None,
+                    (true, true) => None,
+                    (false, true) => Some(Colour::Black),
+                    // Black moves second, so gets one more move to draw level.
+                    (true, false) if board.turn() == Colour::Black => {
+                        let black_can_finish = board
+                            .get_legal_moves()
+                            .iter()
+                            .any(|mv| mv.piece().piece_type == PieceType::King && mv.to().y() == 7);
+                        if black_can_finish {
+                            return None;
+                        }
+                        Some(Colour::White)
+                    }
+                    (true, false) => Some(Colour::White),
+                };
+                Some(match winner {
+                    Some(winner) => Outcome::win(winner, Termination::EighthRank),
+                    None => Outcome::draw(Termination::EighthRank),
+                })
+            }
+        }
+    }
+
+    /// The outcome when the side to move has no legal move.
+    pub(crate) fn no_moves_outcome(self, board: &Board) -> Outcome {
+        match self {
+            Self::Antichess => Outcome::win(board.turn(), Termination::Stalemate),
+            _ if board.in_check() => Outcome::win(board.turn().opposite(), Termination::Checkmate),
+            _ => Outcome::draw(Termination::Stalemate),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses any of the variant's names, case-insensitively.
+impl FromStr for Variant {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|variant| {
+                [variant.name(), variant.xboard_name(), variant.pgn_name()]
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(s))
+            })
+            .ok_or("Unknown variant")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("chess", Variant::Standard)]
+    #[case("Standard", Variant::Standard)]
+    #[case("normal", Variant::Standard)]
+    #[case("Crazyhouse", Variant::Crazyhouse)]
+    #[case("3check", Variant::ThreeCheck)]
+    #[case("KingOfTheHill", Variant::KingOfTheHill)]
+    #[case("atomic", Variant::Atomic)]
+    #[case("antichess", Variant::Antichess)]
+    #[case("RacingKings", Variant::RacingKings)]
+    #[case("Three-check", Variant::ThreeCheck)]
+    #[case("King of the Hill", Variant::KingOfTheHill)]
+    fn parses_names(#[case] name: &str, #[case] variant: Variant) {
+        assert_eq!(name.parse(), Ok(variant));
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for variant in Variant::ALL {
+            assert_eq!(variant.to_string().parse(), Ok(variant));
+            assert_eq!(variant.xboard_name().parse(), Ok(variant));
+            assert_eq!(variant.pgn_name().parse(), Ok(variant));
+            let board = Board::from_variant_fen(variant, variant.start_fen()).unwrap();
+            assert_eq!(board.to_fen(), variant.start_fen());
+            assert_eq!(board, Board::start(variant));
+        }
+        assert!("shogi".parse::<Variant>().is_err());
+    }
+}
@@ -0,0 +1,34 @@
+//! Antichess's rules: captures are compulsory and the king is an ordinary piece, which cannot
+//! be checked or castle and which pawns may promote to.
+
+use crate::board::{Board, Colour, Coordinate, Move, MoveKind};
+use crate::piece::PieceType;
+
+/// The pieces a pawn may promote to, the king among them.
+pub(super) const PROMOTION_TYPES: [PieceType; 5] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::King,
+];
+
+/// Generates the moves of `kind` that are legal in antichess: every pseudo-legal move, but only
+/// captures if there is one to make. Whether there is depends on every piece, so moves are
+/// always generated for all of them.
+pub(super) fn moves(board: &Board, kind: MoveKind, only_from: Option<Coordinate>) -> Vec<Move> {
+    let mut moves = board.pseudo_legal_moves(None);
+    if moves.iter().any(|mv| mv.captured().is_some()) {
+        moves.retain(|mv| mv.captured().is_some());
+    }
+    moves.retain(|mv| kind.includes(mv) && only_from.is_none_or(|only| mv.from() == Some(only)));
+    moves
+}
+
+/// Whether `board` could come up in a game of antichess: with any number of kings, which are
+/// never in check, so only pawns on the first or last rank rule a position out.
+pub(super) fn is_legal_position(board: &Board) -> bool {
+    Colour::ALL
+        .into_iter()
+        .all(|colour| !board.has_pawn_on_rank(colour, 0) && !board.has_pawn_on_rank(colour, 7))
+}
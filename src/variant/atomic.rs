@@ -0,0 +1,121 @@
+//! Atomic chess's rules: a capture blows up the capturing piece along with every piece but a
+//! pawn around the destination square, and blowing up the enemy king wins.
+
+use crate::board::{Board, Colour, Coordinate, KING_DIRS, Move, MoveKind, exchange_value};
+use crate::piece::{Piece, PieceType};
+
+/// Generates the moves of `kind` that are legal in atomic chess. An explosion can remove a
+/// checking piece or the enemy king as well as the pieces shielding the own king, so the check
+/// and pin masks do not apply: each pseudo-legal move is played on a scratch board instead, and
+/// kept if the own king survives and is not left in check.
+pub(super) fn moves(board: &Board, kind: MoveKind, only_from: Option<Coordinate>) -> Vec<Move> {
+    let Some(king_pos) = board.find_king(board.turn()) else {
+        return Vec::new();
+    };
+    let mut moves = board.pseudo_legal_moves(only_from);
+    moves.retain(|mv| {
+        // A king would blow itself up by capturing.
+        let king_capture = mv.piece().piece_type == PieceType::King && mv.captured().is_some();
+        kind.includes(mv) && !king_capture && is_legal(board, mv)
+    });
+    if kind != MoveKind::Tactical
+        && only_from.is_none_or(|only| only == king_pos)
+        && !board.in_check()
+    {
+        board.push_castling_moves(&king_pos, &mut moves);
+    }
+    moves
+}
+
+fn is_legal(board: &Board, mv: &Move) -> bool {
+    let mut scratch = board.scratch();
+    scratch.make_move_unchecked(*mv);
+    let turn = board.turn();
+    scratch.find_king(turn).is_some()
+        && (scratch.find_king(turn.opposite()).is_none() || !scratch.king_in_check(turn))
+}
+
+/// Whether both kings stand next to each other, which means neither can be in check: capturing
+/// one king would blow up the other.
+pub(super) fn kings_touch(board: &Board) -> bool {
+    match (
+        board.find_king(Colour::White),
+        board.find_king(Colour::Black),
+    ) {
+        (Some(white), Some(black)) => white.distance(&black) == 1,
+        _ => false,
+    }
+}
+
+/// Whether a castling king may cross `square`: if it is not attacked by `by`, or if it is next to
+/// `by`'s king, where the king cannot be captured.
+pub(super) fn is_safe_for_castling_king(board: &Board, square: &Coordinate, by: Colour) -> bool {
+    !board.is_square_attacked(square, by)
+        || board
+            .find_king(by)
+            .is_some_and(|king| king.distance(square) <= 1)
+}
+
+/// Blows up the capturing piece of `mv` along with everything around it, returning the pieces
+/// lost besides the capturing and captured ones. Called once `mv` has been played.
+pub(super) fn explode(board: &mut Board, mv: &Move) -> [Option<Piece>; 8] {
+    let exploded = explosion(board, mv);
+    for (coord, piece) in exploded_squares(&mv.to(), &exploded) {
+        board.set_square(coord, None);
+        if piece.piece_type == PieceType::King {
+            board.remove_castling_rights(piece.colour);
+        }
+        board.remove_castling_right_on(&coord);
+    }
+    board.set_square(mv.to(), None);
+    exploded
+}
+
+/// Puts back the pieces `explode` blew up around the destination of `mv`.
+pub(super) fn restore(board: &mut Board, mv: &Move, exploded: &[Option<Piece>; 8]) {
+    for (coord, piece) in exploded_squares(&mv.to(), exploded) {
+        board.set_square(coord, Some(piece));
+    }
+}
+
+/// The material `mv`'s side wins by the capture `mv`, in centipawns. The exchange is settled at
+/// once, there is nothing left to recapture.
+pub(super) fn see(board: &Board, mv: &Move) -> i32 {
+    let colour = mv.piece().colour;
+    [mv.captured(), Some(mv.piece())]
+        .into_iter()
+        .chain(explosion(board, mv))
+        .flatten()
+        .map(|piece| {
+            let sign = if piece.colour == colour { -1 } else { 1 };
+            sign * exchange_value(piece.piece_type)
+        })
+        .sum()
+}
+
+/// The pieces a capture with `mv` blows up besides the capturing and captured pieces: everything
+/// but pawns on the squares around its destination, by `KING_DIRS`.
+fn explosion(board: &Board, mv: &Move) -> [Option<Piece>; 8] {
+    KING_DIRS.map(|direction| {
+        mv.to()
+            .try_apply_delta(direction)
+            .ok()
+            .filter(|coord| Some(*coord) != mv.from())
+            .and_then(|coord| board.get_square(&coord))
+            .filter(|piece| piece.piece_type != PieceType::Pawn)
+    })
+}
+
+/// Pairs the pieces recorded by `explosion` with the squares around `center` they stood on.
+fn exploded_squares<'a>(
+    center: &Coordinate,
+    exploded: &'a [Option<Piece>; 8],
+) -> impl Iterator<Item = (Coordinate, Piece)> + use<'a> {
+    let center = *center;
+    KING_DIRS
+        .iter()
+        .zip(exploded)
+        .filter_map(move |(&direction, piece)| {
+            Some((center.try_apply_delta(direction).ok()?, (*piece)?))
+        })
+}
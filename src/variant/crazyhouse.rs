@@ -0,0 +1,74 @@
+//! Crazyhouse's rules: captured pieces go to the capturer's pocket, from which they may be
+//! dropped back onto the board instead of moving.
+
+use crate::board::{Board, Coordinate, DROP_TYPES, Move, SquareMask};
+use crate::piece::{Piece, PieceType};
+
+/// Generates the legal drops from the side to move's pocket. A drop cannot uncover a check, so
+/// it only has to block the check the king may already be in.
+pub(super) fn drops(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    let turn = board.turn();
+    let pocket = board.pocket(turn);
+    let Some(king_pos) = board.find_king(turn) else {
+        return moves;
+    };
+    if pocket.is_empty() {
+        return moves;
+    }
+    let targets = match board.attackers_of(&king_pos, turn.opposite()).as_slice() {
+        [] => SquareMask::FULL,
+        [checker] => king_pos.squares_between(checker),
+        _ => return moves,
+    };
+    for piece_type in DROP_TYPES {
+        if pocket.count(piece_type) == 0 {
+            continue;
+        }
+        let piece = Piece {
+            piece_type,
+            colour: turn,
+        };
+        for (to, square) in board.into_iter().enumerate() {
+            let to = Coordinate::from_index(to);
+            let back_rank = to.y() == 0 || to.y() == 7;
+            if square.is_none()
+                && targets.contains(&to)
+                && !(piece_type == PieceType::Pawn && back_rank)
+            {
+                moves.push(Move::drop(piece, to));
+            }
+        }
+    }
+    moves
+}
+
+/// Moves the piece `mv` captured into the capturer's pocket, or the dropped piece out of it,
+/// and keeps track of which pieces were promoted.
+pub(super) fn update_pockets(board: &mut Board, mv: &Move) {
+    let colour = mv.piece().colour;
+    if let Some(captured) = mv.captured() {
+        let captured_on = if mv.is_en_passant() {
+            mv.en_passant_capture_square()
+        } else {
+            mv.to()
+        };
+        let piece_type = if board.is_promoted(&captured_on) {
+            PieceType::Pawn
+        } else {
+            captured.piece_type
+        };
+        board.pocket_mut(colour).add(piece_type);
+    }
+    let promoted = board.promoted_mut();
+    promoted.remove(&mv.to());
+    match mv.from() {
+        Some(from) => {
+            if promoted.contains(&from) || mv.promotion().is_some() {
+                promoted.insert(mv.to());
+            }
+            promoted.remove(&from);
+        }
+        None => board.pocket_mut(colour).remove(mv.piece().piece_type),
+    }
+}
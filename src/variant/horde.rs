@@ -0,0 +1,68 @@
+//! Horde's rules: White has pawns instead of a king, which may stand on the first rank and
+//! double-push from it, and loses once Black has captured them all.
+
+use crate::board::{Board, Colour, Coordinate, Move, MoveKind};
+use crate::piece::{Piece, PieceType};
+
+/// Generates the moves of `kind` that are legal in horde. With no king to protect, any of
+/// White's moves goes.
+pub(super) fn moves(board: &Board, kind: MoveKind, only_from: Option<Coordinate>) -> Vec<Move> {
+    let turn = board.turn();
+    let mut moves = if board.find_king(turn).is_some() {
+        board.classical_moves(kind, only_from)
+    } else {
+        let mut moves = board.pseudo_legal_moves(only_from);
+        moves.retain(|mv| kind.includes(mv));
+        moves
+    };
+    if turn == Colour::White && kind != MoveKind::Tactical {
+        moves.extend(first_rank_double_pushes(board, only_from).filter(|mv| {
+            board.find_king(turn).is_none() || {
+                let mut scratch = board.scratch();
+                scratch.make_move_unchecked(*mv);
+                !scratch.king_in_check(turn)
+            }
+        }));
+    }
+    moves
+}
+
+/// The double pushes of White's pawns on the first rank, only for the pawn on `only_from` if
+/// given.
+fn first_rank_double_pushes(
+    board: &Board,
+    only_from: Option<Coordinate>,
+) -> impl Iterator<Item = Move> + use<'_> {
+    let pawn = Piece::pawn(Colour::White);
+    (0..8)
+        .map(|x| Coordinate::new_unchecked(x, 0))
+        .filter(move |from| {
+            only_from.is_none_or(|only| only == *from)
+                && board.get_square(from) == Some(pawn)
+                && (1..=2).all(|y| {
+                    board
+                        .get_square(&Coordinate::new_unchecked(from.x(), y))
+                        .is_none()
+                })
+        })
+        .map(move |from| Move::new(pawn, from, Coordinate::new_unchecked(from.x(), 2), None))
+}
+
+/// A pawn double-pushing from the first rank cannot be taken en passant.
+pub(super) fn after_move(board: &mut Board, mv: &Move) {
+    if mv.piece().piece_type == PieceType::Pawn && mv.from().is_some_and(|from| from.y() == 0) {
+        board.clear_en_passant();
+    }
+}
+
+/// Whether `board` could come up in a game of horde: with White's pawns allowed on the first
+/// rank, and White's king, if any, in place of some of them.
+pub(super) fn is_legal_position(board: &Board) -> bool {
+    let pawns_placed = !board.has_pawn_on_rank(Colour::White, 7)
+        && !board.has_pawn_on_rank(Colour::Black, 0)
+        && !board.has_pawn_on_rank(Colour::Black, 7);
+    pawns_placed
+        && board.king_count(Colour::White) <= 1
+        && board.king_count(Colour::Black) == 1
+        && board.is_opponent_safe()
+}
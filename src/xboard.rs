@@ -0,0 +1,484 @@
+//! The Chess Engine Communication Protocol, spoken by XBoard, WinBoard and the testers built
+//! around them. Unlike UCI the engine keeps track of the game itself and decides when to move.
+
+use crate::board::{Board, Colour};
+use crate::engine::Engine;
+use crate::game::Termination;
+use crate::search::{self, SearchLimits, SearchResult};
+use crate::timeman::Clock;
+use crate::variant::Variant;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Reads CECP commands from `input` line by line and answers on `output` until `quit` or the
+/// end of the input.
+pub fn run(input: impl BufRead, output: impl Write + Send + 'static) {
+    let mut xboard = XBoard::new(output);
+    for line in input.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if xboard.handle(&line).is_break() {
+            break;
+        }
+    }
+}
+
+/// State shared with the search thread, which plays the engine's move when it finishes.
+struct Shared<W> {
+    board: Board,
+    output: W,
+    /// Whether to print thinking output, toggled by `post` and `nopost`.
+    post: bool,
+    /// Cleared when the search in progress is abandoned, so its move is not played.
+    play_result: bool,
+}
+
+impl<W: Write> Shared<W> {
+    fn send(&mut self, line: impl Display) {
+        // There is nobody left to tell if the GUI has gone away.
+        let _ = writeln!(self.output, "{line}").and_then(|()| self.output.flush());
+    }
+}
+
+/// A time control set by `level`: `moves` per session (0 for the whole game), `base` time for
+/// each session and `increment` per move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Level {
+    moves: u32,
+    base: Duration,
+    increment: Duration,
+}
+
+struct XBoard<W> {
+    engine: Engine,
+    shared: Arc<Mutex<Shared<W>>>,
+    /// The side the engine plays, `None` in force mode.
+    engine_colour: Option<Colour>,
+    level: Option<Level>,
+    /// Fixed time per move set by `st`.
+    move_time: Option<Duration>,
+    /// Depth limit set by `sd`.
+    depth: Option<u32>,
+    /// The time on the engine's clock, as last reported by `time`.
+    time_left: Option<Duration>,
+    /// Set by `variant`, back to standard chess on `new`.
+    variant: Variant,
+}
+
+impl<W: Write + Send + 'static> XBoard<W> {
+    fn new(output: W) -> Self {
+        Self {
+            engine: Engine::default(),
+            shared: Arc::new(Mutex::new(Shared {
+                board: Board::new(),
+                output,
+                post: false,
+                play_result: false,
+            })),
+            engine_colour: Some(Colour::Black),
+            level: None,
+            move_time: None,
+            depth: None,
+            time_left: None,
+            variant: Variant::Standard,
+        }
+    }
+
+    fn shared(&self) -> MutexGuard<'_, Shared<W>> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn send(&self, line: impl Display) {
+        self.shared().send(line);
+    }
+
+    fn turn(&self) -> Colour {
+        self.shared().board.turn()
+    }
+
+    fn handle(&mut self, line: &str) -> ControlFlow<()> {
+        let (command, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "otim" | "name" | "rating" | "" => {}
+            "protover" => {
+                let variants = Variant::ALL.map(Variant::xboard_name).join(",");
+                self.send(format_args!(
+                    "feature myname=\"ferrous-chess {}\" ping=1 setboard=1 usermove=1 \
+                     playother=1 sigint=0 sigterm=0 colors=0 reuse=1 analyze=0 \
+                     variants=\"{variants}\" done=1",
+                    env!("CARGO_PKG_VERSION")
+                ))
+            }
+            "new" => {
+                self.abandon_search();
+                self.engine.searcher().clear();
+                self.shared().board = Board::new();
+                self.engine_colour = Some(Colour::Black);
+                self.depth = None;
+                self.variant = Variant::Standard;
+            }
+            "variant" => match args.parse() {
+                Ok(variant) => {
+                    self.abandon_search();
+                    self.variant = variant;
+                    self.shared().board = Board::start(variant);
+                }
+                Err(_) => self.send(format_args!("Error (unsupported variant): {args}")),
+            },
+            "setboard" => {
+                self.abandon_search();
+                match Board::from_variant_fen(self.variant, args) {
+                    Ok(board) => self.shared().board = board,
+                    Err(err) => self.send(format_args!("tellusererror Illegal position: {err}")),
+                }
+            }
+            "usermove" => self.user_move(args),
+            "go" => {
+                self.abandon_search();
+                self.engine_colour = Some(self.turn());
+                self.think();
+            }
+            "playother" => {
+                self.abandon_search();
+                self.engine_colour = Some(self.turn().opposite());
+            }
+            "force" | "result" => {
+                self.abandon_search();
+                self.engine_colour = None;
+            }
+            "?" => self.engine.stop(),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "level" => match parse_level(args) {
+                Some(level) => {
+                    self.level = Some(level);
+                    self.move_time = None;
+                }
+                None => self.send(format_args!("Error (bad time control): {args}")),
+            },
+            "st" => match args.parse::<u64>() {
+                Ok(seconds) => self.move_time = Some(Duration::from_secs(seconds)),
+                Err(_) => self.send(format_args!("Error (bad time): {args}")),
+            },
+            "sd" => match args.parse() {
+                Ok(depth) => self.depth = Some(depth),
+                Err(_) => self.send(format_args!("Error (bad depth): {args}")),
+            },
+            "time" => match args.parse::<u64>() {
+                Ok(centiseconds) => self.time_left = Some(Duration::from_millis(centiseconds * 10)),
+                Err(_) => self.send(format_args!("Error (bad time): {args}")),
+            },
+            "post" => self.shared().post = true,
+            "nopost" => self.shared().post = false,
+            "ping" => self.send(format_args!("pong {args}")),
+            "quit" => return ControlFlow::Break(()),
+            _ => self.send(format_args!("Error (unknown command): {command}")),
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Stops the search in progress without playing its move.
+    fn abandon_search(&mut self) {
+        // The lock must be released before stopping, the search thread takes it to finish.
+        self.shared().play_result = false;
+        self.engine.stop();
+    }
+
+    fn user_move(&mut self, notation: &str) {
+        self.abandon_search();
+        {
+            let mut shared = self.shared();
+            let Ok(mv) = shared.board.parse_move(notation) else {
+                shared.send(format_args!("Illegal move: {notation}"));
+                return;
+            };
+            shared.board.make_move_unchecked(mv);
+            if let Some(result) = game_result(&shared.board) {
+                shared.send(result);
+                return;
+            }
+        }
+        if self.engine_colour == Some(self.turn()) {
+            self.think();
+        }
+    }
+
+    fn take_back(&mut self, moves: usize) {
+        self.abandon_search();
+        let mut shared = self.shared();
+        for _ in 0..moves {
+            shared.board.unmake_move();
+        }
+    }
+
+    /// Starts searching for the engine's move, which is played when the search finishes.
+    fn think(&mut self) {
+        let limits = self.limits();
+        let board = {
+            let mut shared = self.shared();
+            if let Some(result) = game_result(&shared.board) {
+                shared.send(result);
+                return;
+            }
+            shared.play_result = true;
+            shared.board.clone()
+        };
+
+        let thinking = Arc::clone(&self.shared);
+        let playing = Arc::clone(&self.shared);
+        self.engine.start(
+            board,
+            limits,
+            false,
+            move |result| {
+                let mut shared = thinking.lock().unwrap_or_else(|p| p.into_inner());
+                if shared.post {
+                    shared.send(thinking_line(result));
+                }
+            },
+            move |result| {
+                let mut shared = playing.lock().unwrap_or_else(|p| p.into_inner());
+                if !shared.play_result {
+                    return;
+                }
+                shared.play_result = false;
+                let Some(mv) = result.best_move else {
+                    return;
+                };
+                shared.board.make_move_unchecked(mv);
+                shared.send(format_args!("move {mv}"));
+                if let Some(result) = game_result(&shared.board) {
+                    shared.send(result);
+                }
+            },
+        );
+    }
+
+    fn limits(&self) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.depth,
+            ..Default::default()
+        };
+        if let Some(move_time) = self.move_time {
+            limits.time = Some(move_time);
+        } else if let Some(level) = self.level {
+            let moves_to_go = (level.moves > 0).then(|| {
+                let played = self.shared().board.fullmove_number() - 1;
+                level.moves - played % level.moves
+            });
+            limits.clock = Some(Clock {
+                moves_to_go,
+                ..Clock::new(self.time_left.unwrap_or(level.base), level.increment)
+            });
+        }
+        limits
+    }
+}
+
+/// Parses the arguments of `level`: moves per session, base time in minutes or `minutes:seconds`
+/// and increment in seconds.
+fn parse_level(args: &str) -> Option<Level> {
+    let [moves, base, increment] = args.split_whitespace().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let base = match base.split_once(':') {
+        Some((minutes, seconds)) => {
+            Duration::from_secs(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?)
+        }
+        None => Duration::from_secs(base.parse::<u64>().ok()? * 60),
+    };
+    Some(Level {
+        moves: moves.parse().ok()?,
+        base,
+        increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+    })
+}
+
+/// The result to announce if the game is over, with the reason in braces.
+fn game_result(board: &Board) -> Option<String> {
+    let outcome = board.outcome()?;
+    Some(match (outcome.termination, outcome.result.winner()) {
+        (Termination::Checkmate, Some(Colour::White)) => {
+            format!("{} {{White mates}}", outcome.result)
+        }
+        (Termination::Checkmate, Some(Colour::Black)) => {
+            format!("{} {{Black mates}}", outcome.result)
+        }
+        (termination, _) => format!("{} {{{termination}}}", outcome.result),
+    })
+}
+
+/// Thinking output: depth, score, time in centiseconds, nodes and the principal variation.
+/// Mates are reported as 100000 plus the number of moves, as XBoard expects.
+fn thinking_line(result: &SearchResult) -> String {
+    let score = match search::mate_in(result.score) {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => result.score,
+    };
+    let pv = result
+        .pv
+        .iter()
+        .map(|mv| mv.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{} {score} {} {} {pv}",
+        result.depth,
+        result.elapsed.as_millis() / 10,
+        result.nodes
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_commands(commands: &str) -> Vec<String> {
+        let output = Output::default();
+        run(commands.as_bytes(), output.clone());
+        let bytes = output.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn feature_negotiation() {
+        let lines = run_commands("xboard\nprotover 2\nping 7\n");
+        assert!(lines[0].starts_with("feature "));
+        assert!(lines[0].contains("usermove=1") && lines[0].ends_with("done=1"));
+        assert!(lines[0].contains(
+            "variants=\"normal,crazyhouse,3check,kingofthehill,atomic,antichess,horde,racingkings\""
+        ));
+        assert_eq!(lines[1], "pong 7");
+    }
+
+    #[test]
+    fn selects_variants() {
+        let mut xboard = XBoard::new(Output::default());
+        let _ = xboard.handle("new");
+        let _ = xboard.handle("variant normal");
+        assert_eq!(xboard.variant, Variant::Standard);
+        let lines = run_commands("new\nvariant shogi\n");
+        assert_eq!(lines, ["Error (unsupported variant): shogi"]);
+    }
+
+    #[rstest]
+    #[case::stalemate("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some("1/2-1/2 {Stalemate}"))]
+    #[case::fifty_moves("7k/8/6K1/8/8/8/8/R7 b - - 100 80", Some("1/2-1/2 {Fifty move rule}"))]
+    #[case::black_mates("7k/8/8/8/8/8/5PPP/r5K1 w - - 0 1", Some("0-1 {Black mates}"))]
+    #[case::in_progress("7k/8/6K1/8/8/8/8/R7 b - - 0 1", None)]
+    fn announces_results(#[case] fen: &str, #[case] expected: Option<&str>) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(game_result(&board).as_deref(), expected);
+    }
+
+    #[test]
+    fn replies_to_user_moves() {
+        let lines = run_commands("new\nsd 2\nusermove e2e4\n");
+        let reply = lines.last().unwrap().strip_prefix("move ").unwrap();
+        let mut board = Board::new();
+        board.make_move(board.parse_move("e2e4").unwrap()).unwrap();
+        assert!(board.parse_move(reply).is_ok());
+    }
+
+    #[test]
+    fn force_mode_only_records_moves() {
+        let lines = run_commands("new\nforce\nusermove e2e4\nusermove e7e5\nping 1\n");
+        assert_eq!(lines, ["pong 1"]);
+    }
+
+    #[test]
+    fn go_plays_for_the_side_to_move_and_announces_mate() {
+        let lines = run_commands(
+            "new\nforce\nsetboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\nsd 2\npost\ngo\n",
+        );
+        assert!(lines[0].starts_with("1 100001 "));
+        assert_eq!(lines[lines.len() - 2..], ["move a1a8", "1-0 {White mates}"]);
+    }
+
+    #[test]
+    fn undo_and_remove_take_moves_back() {
+        let mut xboard = XBoard::new(Output::default());
+        for command in ["force", "usermove e2e4", "usermove e7e5", "usermove g1f3"] {
+            let _ = xboard.handle(command);
+        }
+        let _ = xboard.handle("undo");
+        assert_eq!(xboard.shared().board.move_list().len(), 2);
+        let _ = xboard.handle("remove");
+        assert_eq!(xboard.shared().board, Board::new());
+    }
+
+    #[test]
+    fn rejects_illegal_input() {
+        let lines = run_commands("new\nusermove e2e5\nsetboard 8/8/8 w - - 0 1\nfoo\n");
+        assert_eq!(
+            lines,
+            [
+                "Illegal move: e2e5",
+                "tellusererror Illegal position: FEN piece placement must have 8 ranks",
+                "Error (unknown command): foo",
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::minutes("40 5 0", 40, 300, 0.0)]
+    #[case::minutes_and_seconds("0 2:30 12", 0, 150, 12.0)]
+    #[case::fractional_increment("0 1 0.5", 0, 60, 0.5)]
+    fn parses_levels(
+        #[case] args: &str,
+        #[case] moves: u32,
+        #[case] base: u64,
+        #[case] increment: f64,
+    ) {
+        assert_eq!(
+            parse_level(args),
+            Some(Level {
+                moves,
+                base: Duration::from_secs(base),
+                increment: Duration::from_secs_f64(increment),
+            })
+        );
+    }
+
+    #[test]
+    fn level_and_time_set_the_clock() {
+        let mut xboard = XBoard::new(Output::default());
+        let _ = xboard.handle("level 40 5 2");
+        let _ = xboard.handle("time 12000");
+        assert_eq!(
+            xboard.limits().clock,
+            Some(Clock {
+                moves_to_go: Some(40),
+                ..Clock::new(Duration::from_secs(120), Duration::from_secs(2))
+            })
+        );
+
+        let _ = xboard.handle("st 10");
+        assert_eq!(xboard.limits(), SearchLimits::time(Duration::from_secs(10)));
+    }
+}
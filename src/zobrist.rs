@@ -0,0 +1,106 @@
+use crate::board::{Colour, Coordinate};
+use crate::piece::{Piece, PieceType};
+
+// Zobrist keys, generated at compile time from a fixed seed so hashes are stable between runs.
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const PIECE_KEY_COUNT: usize = 12 * 64;
+const CASTLING_KEY_COUNT: usize = 4;
+const EN_PASSANT_KEY_COUNT: usize = 8;
+/// The most pieces of one type a crazyhouse pocket can hold.
+pub(crate) const MAX_POCKET_COUNT: u8 = 16;
+// One key per colour, droppable piece type and count from 1 up.
+const POCKET_KEY_COUNT: usize = 2 * 5 * MAX_POCKET_COUNT as usize;
+/// The most checks one side can give in three-check before the game ends.
+pub(crate) const MAX_CHECKS: u8 = 3;
+// One key per colour and count of checks given from 1 up.
+const CHECK_KEY_COUNT: usize = 2 * MAX_CHECKS as usize;
+const KEY_COUNT: usize = PIECE_KEY_COUNT
+    + CASTLING_KEY_COUNT
+    + EN_PASSANT_KEY_COUNT
+    + POCKET_KEY_COUNT
+    + CHECK_KEY_COUNT
+    + 1;
+
+const KEYS: [u64; KEY_COUNT] = {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = 0x0F3E_2C0D_E5CA_1E5Au64;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        let (next, key) = splitmix64(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+};
+
+const fn piece_index(piece: Piece) -> usize {
+    let kind = match piece.piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    };
+    kind * 2 + piece.colour as usize
+}
+
+pub(crate) const fn piece(piece: Piece, coord: &Coordinate) -> u64 {
+    KEYS[piece_index(piece) * 64 + (coord.y() * 8 + coord.x()) as usize]
+}
+
+pub(crate) const fn castling(colour: Colour, kingside: bool) -> u64 {
+    KEYS[PIECE_KEY_COUNT + colour as usize * 2 + kingside as usize]
+}
+
+pub(crate) const fn en_passant(coord: &Coordinate) -> u64 {
+    KEYS[PIECE_KEY_COUNT + CASTLING_KEY_COUNT + coord.x() as usize]
+}
+
+/// Mixed in while `colour` has exactly `count` pieces of `piece_type` in its pocket, so nothing is
+/// mixed in for an empty pocket. `count` must be at most `MAX_POCKET_COUNT`.
+pub(crate) const fn pocket(colour: Colour, piece_type: PieceType, count: u8) -> u64 {
+    if count == 0 {
+        return 0;
+    }
+    let slot = (colour as usize * 5 + piece_type as usize) * MAX_POCKET_COUNT as usize;
+    KEYS[PIECE_KEY_COUNT + CASTLING_KEY_COUNT + EN_PASSANT_KEY_COUNT + slot + count as usize - 1]
+}
+
+/// Mixed in while `colour` has given exactly `count` checks in three-check. `count` must be at
+/// most `MAX_CHECKS`.
+pub(crate) const fn checks(colour: Colour, count: u8) -> u64 {
+    if count == 0 {
+        return 0;
+    }
+    let slot = colour as usize * MAX_CHECKS as usize + count as usize - 1;
+    KEYS[PIECE_KEY_COUNT + CASTLING_KEY_COUNT + EN_PASSANT_KEY_COUNT + POCKET_KEY_COUNT + slot]
+}
+
+/// Mixed in when Black is to move.
+pub(crate) const fn black_to_move() -> u64 {
+    KEYS[KEY_COUNT - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys = KEYS.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), KEY_COUNT);
+        assert!(!keys.contains(&0));
+    }
+}